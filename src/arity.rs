@@ -0,0 +1,248 @@
+use crate::error::{RxError, RxResult};
+use crate::grammar::{AST, Node, Param};
+use crate::linker::Program;
+use crate::resolve::{Bindings, bind_program};
+use std::collections::{HashMap, HashSet};
+
+/// Where a parameter gets its value from in one call.
+#[derive(Debug, Clone, Copy)]
+pub enum ArgSource {
+    Positional(usize),
+    Named(usize),
+    Default,
+}
+
+/// Matches the arguments of a call, `positional` values followed by the
+/// `named` ones, to the parameters of the function called `name`
+/// (anonymous functions have none). The error is the message to report.
+pub fn bind_args(
+    name: Option<&str>,
+    params: &[Param],
+    positional: usize,
+    named: &[String],
+) -> Result<Vec<ArgSource>, String> {
+    let required = params.iter().filter(|p| p.default.is_none()).count();
+    let given = positional + named.len();
+    if positional > params.len() {
+        return Err(arity_message(name, required, params.len(), given));
+    }
+
+    for (i, arg) in named.iter().enumerate() {
+        match params.iter().position(|p| &p.name == arg) {
+            None => {
+                return Err(format!(
+                    "{} has no parameter named `{arg}`",
+                    describe_callee(name)
+                ));
+            }
+            Some(at) if at < positional || named[..i].contains(arg) => {
+                return Err(format!(
+                    "{} was given two values for parameter `{arg}`",
+                    describe_callee(name)
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut sources = Vec::with_capacity(params.len());
+    for (i, param) in params.iter().enumerate() {
+        let source = if i < positional {
+            ArgSource::Positional(i)
+        } else if let Some(at) = named.iter().position(|n| n == &param.name) {
+            ArgSource::Named(at)
+        } else if param.default.is_some() {
+            ArgSource::Default
+        } else if named.is_empty() {
+            return Err(arity_message(name, required, params.len(), given));
+        } else {
+            return Err(format!(
+                "missing argument `{}` in call to {}",
+                param.name,
+                describe_callee(name)
+            ));
+        };
+        sources.push(source);
+    }
+    Ok(sources)
+}
+
+/// The message for a call given the wrong number of arguments, when the
+/// function takes between `min` and `max`.
+pub fn arity_message(name: Option<&str>, min: usize, max: usize, given: usize) -> String {
+    let expected = if min == max {
+        min.to_string()
+    } else {
+        format!("{min} to {max}")
+    };
+    format!(
+        "{} expects {expected} argument{} but was given {given}",
+        describe_callee(name),
+        if max == 1 { "" } else { "s" }
+    )
+}
+
+fn describe_callee(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("`{name}`"),
+        None => "anonymous function".to_string(),
+    }
+}
+
+/// Reports calls whose arguments don't match the parameters before anything
+/// runs, wherever the callee is known statically: a top-level function of
+/// the same module, one imported by name, or `ns.f(...)` on an imported
+/// namespace. Calls through variables are left to the runtime check.
+pub fn check_arity(program: &Program) -> RxResult<()> {
+    let scope = bind_program(program);
+    let signatures: HashMap<usize, Signatures> = program
+        .modules
+        .iter()
+        .map(|m| (m.id, Signatures::of(&m.ast)))
+        .collect();
+
+    let mut errors = Vec::new();
+    for module in &program.modules {
+        let mut calls = Calls {
+            here: module.id,
+            bindings: &scope.bindings[&module.id],
+            signatures: &signatures,
+            shadowed: HashSet::new(),
+        };
+
+        // a name also bound as a variable or parameter may hold anything
+        for stmt in top_level(&module.ast) {
+            collect_bindings(stmt, true, &mut calls.shadowed);
+        }
+
+        calls.check(&module.ast, &mut errors);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RxError::many(errors))
+    }
+}
+
+/// Parameters of the top-level functions of one module.
+struct Signatures {
+    all: HashMap<String, Vec<Param>>,
+    exported: HashMap<String, Vec<Param>>,
+}
+
+impl Signatures {
+    fn of(ast: &Node) -> Self {
+        let mut sigs = Signatures {
+            all: HashMap::new(),
+            exported: HashMap::new(),
+        };
+        for stmt in top_level(ast) {
+            let (item, public) = match &stmt.kind {
+                AST::Pub(item) => (&**item, true),
+                _ => (stmt, false),
+            };
+            if let AST::FuncDef { name, params, .. } = &item.kind {
+                sigs.all.insert(name.clone(), params.clone());
+                if public {
+                    sigs.exported.insert(name.clone(), params.clone());
+                }
+            }
+        }
+        sigs
+    }
+}
+
+/// The calls of one module, checked against the functions its top level binds.
+struct Calls<'a> {
+    here: usize,
+    bindings: &'a Bindings,
+    signatures: &'a HashMap<usize, Signatures>,
+    shadowed: HashSet<String>,
+}
+
+impl Calls<'_> {
+    /// The parameters of the function `name` refers to. A function the
+    /// module defines itself wins over an import of the same name; the
+    /// linker reports that collision, and local calls mean the local one.
+    fn function(&self, name: &str) -> Option<&[Param]> {
+        if let Some(params) = self.signatures[&self.here].all.get(name) {
+            return Some(params);
+        }
+        let origin = self.bindings.origin(name)?;
+        self.signatures[&origin]
+            .exported
+            .get(name)
+            .map(Vec::as_slice)
+    }
+
+    fn check(&self, node: &Node, errors: &mut Vec<RxError>) {
+        match &node.kind {
+            AST::Call { name, args } if !self.shadowed.contains(name) => {
+                if let Some(params) = self.function(name) {
+                    compare(node, name, params, args, errors);
+                }
+            }
+            AST::MemberCall { base, name, args } => {
+                if let AST::Var(ns) = &base.kind
+                    && !self.shadowed.contains(ns)
+                    && let Some(id) = self.bindings.namespace(ns)
+                    && let Some(params) = self.signatures[&id].exported.get(name)
+                {
+                    compare(node, name, params, args, errors);
+                }
+            }
+            _ => {}
+        }
+
+        for child in node.children() {
+            self.check(child, errors);
+        }
+    }
+}
+
+fn compare(node: &Node, name: &str, params: &[Param], args: &[Node], errors: &mut Vec<RxError>) {
+    let named: Vec<String> = args
+        .iter()
+        .filter_map(|a| match &a.kind {
+            AST::NamedArg(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let positional = args.len() - named.len();
+    if let Err(msg) = bind_args(Some(name), params, positional, &named) {
+        errors.push(RxError::compile(msg, node.span.clone()));
+    }
+}
+
+pub(crate) fn top_level(ast: &Node) -> &[Node] {
+    match &ast.kind {
+        AST::Program(stmts) => stmts,
+        _ => std::slice::from_ref(ast),
+    }
+}
+
+/// Names bound anywhere in `node` other than by a top-level `func`.
+pub(crate) fn collect_bindings(node: &Node, top: bool, out: &mut HashSet<String>) {
+    match &node.kind {
+        AST::Assign(name, _)
+        | AST::ImmutableAssign(name, _)
+        | AST::ReactiveAssign(name, _)
+        | AST::For { var: name, .. } => {
+            out.insert(name.clone());
+        }
+        AST::FuncDef { name, params, .. } => {
+            if !top {
+                out.insert(name.clone());
+            }
+            out.extend(params.iter().map(|p| p.name.clone()));
+        }
+        AST::Lambda { params, .. } => out.extend(params.iter().map(|p| p.name.clone())),
+        _ => {}
+    }
+
+    let top = top && matches!(node.kind, AST::Pub(_));
+    for child in node.children() {
+        collect_bindings(child, top, out);
+    }
+}
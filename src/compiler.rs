@@ -1,40 +1,122 @@
-use crate::grammar::{AST, FieldAssignKind, Instruction, Operator};
+use crate::arity::check_arity;
+use crate::error::{RxError, RxResult};
+use crate::grammar::{
+    AST, CallArgs, FieldAssignKind, Instruction, Node, Operator, TemplatePart, UnaryOperator,
+};
+use crate::linker::Program;
+use crate::span::{Span, Spanned};
+use std::collections::HashMap;
 
 pub fn compile(
-    ast: AST,
-    code: &mut Vec<Instruction>,
+    node: Node,
+    code: &mut Vec<Spanned<Instruction>>,
     labels: &mut LabelGenerator,
-    break_stack: &mut Vec<String>,
-) {
-    match ast {
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    let span = node.span;
+    match node.kind {
         // ---------- literals ----------
-        AST::Number(n) => code.push(Instruction::Push(n)),
-        AST::Char(c) => code.push(Instruction::PushChar(c)),
-        AST::Var(name) => code.push(Instruction::Load(name)),
-
-        AST::StringLiteral(s) => compile_string_literal(s, code, labels),
+        AST::Number(n) => emit(code, &span, Instruction::Push(n)),
+        AST::Float(f) => emit(code, &span, Instruction::PushFloat(f)),
+        AST::Char(c) => emit(code, &span, Instruction::PushChar(c)),
+        AST::Var(name) => emit(code, &span, Instruction::Load(name)),
+
+        AST::StringLiteral(s) => compile_string_literal(s, &span, code, labels),
+
+        AST::Template(parts) => {
+            let n = parts.len();
+            for part in parts {
+                match part {
+                    TemplatePart::Text(text) => compile_string_literal(text, &span, code, labels),
+                    TemplatePart::Value(value, spec) => {
+                        compile(value, code, labels, break_stack)?;
+                        emit(code, &span, Instruction::Format(spec));
+                    }
+                }
+            }
+            emit(code, &span, Instruction::Concat(n));
+        }
 
         // ---------- expressions ----------
-        AST::ArrayNew(size) => {
-            compile(*size, code, labels, break_stack);
-            code.push(Instruction::ArrayNew);
+        AST::ArrayNew(dims) => {
+            let n = dims.len();
+            for d in dims {
+                compile(d, code, labels, break_stack)?;
+            }
+            emit(code, &span, Instruction::ArrayNew(n));
+        }
+
+        AST::ArrayLiteral(elems) => {
+            let n = elems.len();
+            for e in elems {
+                compile(e, code, labels, break_stack)?;
+            }
+            emit(code, &span, Instruction::ArrayLiteral(n));
         }
 
         AST::Index(base, index) => {
-            compile(*base, code, labels, break_stack);
-            compile(*index, code, labels, break_stack);
-            code.push(Instruction::ArrayGet);
+            compile(*base, code, labels, break_stack)?;
+            compile(*index, code, labels, break_stack)?;
+            emit(code, &span, Instruction::ArrayGet);
+        }
+
+        AST::Slice { base, start, end } => {
+            compile(*base, code, labels, break_stack)?;
+            match start {
+                Some(start) => compile(*start, code, labels, break_stack)?,
+                None => emit(code, &span, Instruction::Push(0)),
+            }
+            let has_end = end.is_some();
+            if let Some(end) = end {
+                compile(*end, code, labels, break_stack)?;
+            }
+            emit(code, &span, Instruction::ArraySlice { has_end });
         }
 
         AST::FieldAccess(base, field) => {
-            compile(*base, code, labels, break_stack);
-            code.push(Instruction::FieldGet(field));
+            compile(*base, code, labels, break_stack)?;
+            emit(code, &span, Instruction::FieldGet(field));
+        }
+
+        // `&&` and `||` skip their right side once the left one decides
+        AST::Operation(l, op @ (Operator::And | Operator::Or), r) => {
+            let rhs_lbl = labels.fresh("logic_rhs");
+            let false_lbl = labels.fresh("logic_false");
+            let end_lbl = labels.fresh("logic_end");
+
+            compile(*l, code, labels, break_stack)?;
+            if let Operator::And = op {
+                emit(code, &span, Instruction::JumpIfZero(false_lbl.clone()));
+            } else {
+                emit(code, &span, Instruction::JumpIfZero(rhs_lbl.clone()));
+                emit(code, &span, Instruction::Push(1));
+                emit(code, &span, Instruction::Jump(end_lbl.clone()));
+            }
+
+            emit(code, &span, Instruction::Label(rhs_lbl));
+            compile(*r, code, labels, break_stack)?;
+            emit(code, &span, Instruction::JumpIfZero(false_lbl.clone()));
+            emit(code, &span, Instruction::Push(1));
+            emit(code, &span, Instruction::Jump(end_lbl.clone()));
+
+            emit(code, &span, Instruction::Label(false_lbl));
+            emit(code, &span, Instruction::Push(0));
+            emit(code, &span, Instruction::Label(end_lbl));
         }
 
         AST::Operation(l, op, r) => {
-            compile(*l, code, labels, break_stack);
-            compile(*r, code, labels, break_stack);
-            emit_operator(op, code);
+            compile(*l, code, labels, break_stack)?;
+            compile(*r, code, labels, break_stack)?;
+            emit_operator(op, &span, code);
+        }
+
+        AST::Unary(op, expr) => {
+            compile(*expr, code, labels, break_stack)?;
+            let instr = match op {
+                UnaryOperator::Negate => Instruction::Negate,
+                UnaryOperator::BitNot => Instruction::BitNot,
+            };
+            emit(code, &span, instr);
         }
 
         AST::Ternary {
@@ -42,55 +124,93 @@ pub fn compile(
             then_expr,
             else_expr,
         } => {
-            compile(*cond, code, labels, break_stack);
+            compile(*cond, code, labels, break_stack)?;
 
             let else_lbl = labels.fresh("ternary_else");
             let end_lbl = labels.fresh("ternary_end");
 
-            code.push(Instruction::JumpIfZero(else_lbl.clone()));
-            compile(*then_expr, code, labels, break_stack);
-            code.push(Instruction::Jump(end_lbl.clone()));
+            emit(code, &span, Instruction::JumpIfZero(else_lbl.clone()));
+            compile(*then_expr, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Jump(end_lbl.clone()));
 
-            code.push(Instruction::Label(else_lbl));
-            compile(*else_expr, code, labels, break_stack);
+            emit(code, &span, Instruction::Label(else_lbl));
+            compile(*else_expr, code, labels, break_stack)?;
 
-            code.push(Instruction::Label(end_lbl));
+            emit(code, &span, Instruction::Label(end_lbl));
         }
 
         AST::Call { name, args } => {
-            let argc = args.len();
-            for a in args {
-                compile(a, code, labels, break_stack);
-            }
-            code.push(Instruction::Call(name, argc));
+            let args = compile_args(args, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Call(name, args));
+        }
+
+        AST::MemberCall { base, name, args } => {
+            compile(*base, code, labels, break_stack)?;
+            let args = compile_args(args, code, labels, break_stack)?;
+            emit(code, &span, Instruction::CallMember(name, args));
+        }
+
+        AST::Invoke { callee, args } => {
+            compile(*callee, code, labels, break_stack)?;
+            let args = compile_args(args, code, labels, break_stack)?;
+            emit(code, &span, Instruction::CallValue(args));
+        }
+
+        AST::NamedArg(..) => {
+            return Err(compile_error(
+                &span,
+                "named arguments are only allowed in a call",
+            ));
+        }
+
+        AST::Lambda { params, body } => {
+            emit(code, &span, Instruction::MakeClosure(params, body));
         }
 
         // ---------- assignments ----------
         AST::Assign(name, expr) => {
-            compile(*expr, code, labels, break_stack);
-            code.push(Instruction::Store(name));
+            compile(*expr, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Store(name));
         }
 
         AST::ImmutableAssign(name, expr) => {
-            compile(*expr, code, labels, break_stack);
-            code.push(Instruction::StoreImmutable(name));
+            compile(*expr, code, labels, break_stack)?;
+            emit(code, &span, Instruction::StoreImmutable(name));
         }
 
         AST::ReactiveAssign(name, expr) => {
-            code.push(Instruction::StoreReactive(name, expr));
+            emit(code, &span, Instruction::StoreReactive(name, expr));
         }
 
         AST::AssignTarget(target, value) => {
-            compile_lvalue(*target, code, labels, break_stack);
-            compile(*value, code, labels, break_stack);
-            code.push(Instruction::StoreThrough);
+            compile_lvalue(*target, code, labels, break_stack)?;
+            compile(*value, code, labels, break_stack)?;
+            emit(code, &span, Instruction::StoreThrough);
         }
 
         AST::ReactiveAssignTarget(target, value) => {
-            compile_lvalue(*target, code, labels, break_stack);
-            code.push(Instruction::StoreThroughReactive(value));
+            compile_lvalue(*target, code, labels, break_stack)?;
+            emit(code, &span, Instruction::StoreThroughReactive(value));
         }
 
+        // `x op= v` is `x = x op v`; an element or field target is
+        // resolved once and read through its lvalue
+        AST::CompoundAssign(target, op, value) => match target.kind {
+            AST::Var(name) => {
+                emit(code, &span, Instruction::Load(name.clone()));
+                compile(*value, code, labels, break_stack)?;
+                emit_operator(op, &span, code);
+                emit(code, &span, Instruction::Store(name));
+            }
+            _ => {
+                compile_lvalue(*target, code, labels, break_stack)?;
+                emit(code, &span, Instruction::Dup);
+                compile(*value, code, labels, break_stack)?;
+                emit_operator(op, &span, code);
+                emit(code, &span, Instruction::StoreThrough);
+            }
+        },
+
         AST::FieldAssign {
             base,
             field,
@@ -98,229 +218,636 @@ pub fn compile(
             kind,
         } => match kind {
             FieldAssignKind::Normal => {
-                compile(*base, code, labels, break_stack);
-                compile(*value, code, labels, break_stack);
-                code.push(Instruction::FieldSet(field));
+                compile(*base, code, labels, break_stack)?;
+                compile(*value, code, labels, break_stack)?;
+                emit(code, &span, Instruction::FieldSet(field));
             }
             FieldAssignKind::Reactive => {
-                compile(*base, code, labels, break_stack);
-                code.push(Instruction::FieldSetReactive(field, value));
+                compile(*base, code, labels, break_stack)?;
+                emit(code, &span, Instruction::FieldSetReactive(field, value));
             }
             FieldAssignKind::Immutable => {
-                panic!("immutable field assignment not allowed");
+                return Err(compile_error(
+                    &span,
+                    "immutable field assignment not allowed",
+                ));
             }
         },
 
         // ---------- control ----------
         AST::IfElse(cond, then_block, else_block) => {
-            compile(*cond, code, labels, break_stack);
+            compile(*cond, code, labels, break_stack)?;
 
             let else_lbl = labels.fresh("else");
             let end_lbl = labels.fresh("ifend");
 
-            code.push(Instruction::JumpIfZero(else_lbl.clone()));
+            emit(code, &span, Instruction::JumpIfZero(else_lbl.clone()));
 
             // THEN block scope
-            code.push(Instruction::PushImmutableContext);
-            for s in then_block {
-                compile(s, code, labels, break_stack);
-            }
-            code.push(Instruction::PopImmutableContext);
+            compile_block(then_block, &span, code, labels, break_stack)?;
 
-            code.push(Instruction::Jump(end_lbl.clone()));
+            emit(code, &span, Instruction::Jump(end_lbl.clone()));
 
-            code.push(Instruction::Label(else_lbl));
+            emit(code, &span, Instruction::Label(else_lbl));
 
             // ELSE block scope
-            code.push(Instruction::PushImmutableContext);
-            for s in else_block {
-                compile(s, code, labels, break_stack);
-            }
-            code.push(Instruction::PopImmutableContext);
+            compile_block(else_block, &span, code, labels, break_stack)?;
 
-            code.push(Instruction::Label(end_lbl));
+            emit(code, &span, Instruction::Label(end_lbl));
         }
 
-        AST::Loop(body) => {
+        AST::Loop { label, body } => {
             let start = labels.fresh("loop_start");
             let end = labels.fresh("loop_end");
-            break_stack.push(end.clone());
 
-            code.push(Instruction::PushImmutableContext);
-            code.push(Instruction::Label(start.clone()));
-            code.push(Instruction::ClearImmutableContext);
+            emit(code, &span, Instruction::PushImmutableContext);
+            emit(code, &span, Instruction::Label(start.clone()));
+            emit(code, &span, Instruction::ClearImmutableContext);
 
-            for s in body {
-                compile(s, code, labels, break_stack);
-            }
+            let scope = LoopScope::new(label, end.clone(), start.clone());
+            compile_loop_body(body, scope, code, labels, break_stack)?;
+
+            emit(code, &span, Instruction::Jump(start));
+            emit(code, &span, Instruction::Label(end));
+            emit(code, &span, Instruction::PopImmutableContext);
+        }
+
+        AST::While { label, cond, body } => {
+            let start = labels.fresh("while_start");
+            let end = labels.fresh("while_end");
+
+            emit(code, &span, Instruction::PushImmutableContext);
+            emit(code, &span, Instruction::Label(start.clone()));
+            emit(code, &span, Instruction::ClearImmutableContext);
+
+            compile(*cond, code, labels, break_stack)?;
+            emit(code, &span, Instruction::JumpIfZero(end.clone()));
 
-            code.push(Instruction::Jump(start));
-            code.push(Instruction::Label(end));
-            code.push(Instruction::PopImmutableContext);
+            let scope = LoopScope::new(label, end.clone(), start.clone());
+            compile_loop_body(body, scope, code, labels, break_stack)?;
 
-            break_stack.pop();
+            emit(code, &span, Instruction::Jump(start));
+            emit(code, &span, Instruction::Label(end));
+            emit(code, &span, Instruction::PopImmutableContext);
         }
 
-        AST::Break => {
-            let target = break_stack
-                .last()
-                .expect("break used outside of loop")
-                .clone();
-            code.push(Instruction::Jump(target));
+        AST::For {
+            label,
+            var,
+            start,
+            end,
+            body,
+        } => {
+            // both bounds are evaluated once, before the first iteration
+            let counter = labels.fresh("__for_counter");
+            let limit = labels.fresh("__for_limit");
+            compile(*start, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Store(counter.clone()));
+            compile(*end, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Store(limit.clone()));
+
+            let start = labels.fresh("for_start");
+            let next = labels.fresh("for_next");
+            let end = labels.fresh("for_end");
+
+            emit(code, &span, Instruction::PushImmutableContext);
+            emit(code, &span, Instruction::Label(start.clone()));
+            emit(code, &span, Instruction::ClearImmutableContext);
+
+            emit(code, &span, Instruction::Load(counter.clone()));
+            emit(code, &span, Instruction::Load(limit));
+            emit(code, &span, Instruction::Less);
+            emit(code, &span, Instruction::JumpIfZero(end.clone()));
+
+            // the loop variable is a fresh immutable binding each iteration
+            emit(code, &span, Instruction::Load(counter.clone()));
+            emit(code, &span, Instruction::StoreImmutable(var));
+
+            let scope = LoopScope::new(label, end.clone(), next.clone());
+            compile_loop_body(body, scope, code, labels, break_stack)?;
+
+            emit(code, &span, Instruction::Label(next));
+            emit(code, &span, Instruction::Load(counter.clone()));
+            emit(code, &span, Instruction::Push(1));
+            emit(code, &span, Instruction::Add);
+            emit(code, &span, Instruction::Store(counter));
+            emit(code, &span, Instruction::Jump(start));
+            emit(code, &span, Instruction::Label(end));
+            emit(code, &span, Instruction::PopImmutableContext);
         }
 
+        AST::Break(label) => compile_loop_exit(label, false, &span, code, break_stack)?,
+        AST::Continue(label) => compile_loop_exit(label, true, &span, code, break_stack)?,
+
         AST::Return(expr) => {
             if let Some(e) = expr {
-                compile(*e, code, labels, break_stack);
+                compile(*e, code, labels, break_stack)?;
             } else {
-                code.push(Instruction::Push(0));
+                emit(code, &span, Instruction::Push(0));
             }
-            code.push(Instruction::Return);
+            emit(code, &span, Instruction::Return);
         }
 
         // ---------- definitions ----------
         AST::FuncDef { name, params, body } => {
-            code.push(Instruction::StoreFunction(name, params, body));
+            emit(code, &span, Instruction::StoreFunction(name, params, body));
         }
 
         AST::StructDef { name, fields } => {
-            code.push(Instruction::StoreStruct(name, fields));
+            emit(code, &span, Instruction::StoreStruct(name, fields));
+        }
+
+        AST::StructNew(name, fields) => {
+            let mut names = Vec::with_capacity(fields.len());
+            for (field, value) in fields {
+                compile(value, code, labels, break_stack)?;
+                names.push(field);
+            }
+            emit(code, &span, Instruction::NewStruct(name, names));
         }
 
-        AST::StructNew(name) => {
-            code.push(Instruction::NewStruct(name));
+        AST::Import { .. } => {
+            return Err(compile_error(
+                &span,
+                "`import` is only allowed at the top level of a module",
+            ));
         }
 
-        AST::Import(path) => {
-            code.push(Instruction::Import(path));
+        AST::Pub(_) => {
+            return Err(compile_error(
+                &span,
+                "`pub` is only allowed at the top level of a module",
+            ));
         }
 
         AST::Program(stmts) => {
-            let mut has_main = false;
+            let imports = HashMap::new();
+            compile_entry(
+                stmts,
+                &span,
+                EntryPoint::Auto,
+                &imports,
+                code,
+                labels,
+                break_stack,
+            )?;
+        }
+
+        AST::Print(e) => {
+            compile(*e, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Print);
+        }
 
+        AST::Println(e) => {
+            compile(*e, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Println);
+        }
+
+        AST::ImmutableAssignTarget(target, value) => {
+            compile_lvalue(*target, code, labels, break_stack)?;
+            compile(*value, code, labels, break_stack)?;
+            emit(code, &span, Instruction::StoreThroughImmutable);
+        }
+        AST::Cast { target, expr } => {
+            compile(*expr, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Cast(target));
+        }
+    }
+    Ok(())
+}
+
+/// How the top level of a program is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryPoint {
+    /// Call `main` if the program defines one, otherwise run it as a script.
+    #[default]
+    Auto,
+    /// Require a `main` function and call it after the top level has run.
+    Main,
+    /// Run the top-level statements in order; `main` gets no special treatment.
+    Script,
+}
+
+/// Compiles a linked program with an explicit entry point. Each module's
+/// top level runs once, after the modules it imports; the program's own
+/// top level runs last.
+pub fn compile_program(
+    program: Program,
+    entry: EntryPoint,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+) -> RxResult<()> {
+    check_arity(&program)?;
+
+    for module in program.modules {
+        let span = module.ast.span;
+        emit(
+            code,
+            &span,
+            Instruction::EnterModule(module.id, module.name),
+        );
+
+        let stmts = match module.ast.kind {
+            AST::Program(stmts) => stmts,
+            kind => vec![Node::new(kind, span.clone())],
+        };
+
+        let mut break_stack = Vec::new();
+        if module.id == 0 {
+            compile_entry(
+                stmts,
+                &span,
+                entry,
+                &module.imports,
+                code,
+                labels,
+                &mut break_stack,
+            )?;
+        } else {
+            let start = code.len();
             for s in stmts {
-                if let AST::FuncDef { name, .. } = &s {
-                    if name == "main" {
-                        has_main = true;
-                    }
-                }
-                compile(s, code, labels, break_stack);
+                compile_item(s, &module.imports, code, labels, &mut break_stack)?;
             }
+            top_level_returns(
+                &mut code[start..],
+                Some("`return` outside a function; an imported module's top level cannot return"),
+            )?;
+        }
+    }
+    Ok(())
+}
 
-            if !has_main {
-                panic!("no `main` function defined");
+/// Function bodies compile when they are called, so every `Return` in a
+/// module's top-level code comes from a `return` written at its top level.
+/// A script ends there, with the value as its exit status; anywhere else
+/// this is an error.
+fn top_level_returns(code: &mut [Spanned<Instruction>], error: Option<&str>) -> RxResult<()> {
+    for instr in code {
+        if let Instruction::Return = instr.node {
+            match error {
+                Some(msg) => return Err(compile_error(&instr.span, msg)),
+                None => instr.node = Instruction::Exit,
             }
+        }
+    }
+    Ok(())
+}
+
+/// Compiles a program and every function body in it without running anything.
+/// Function bodies are otherwise only compiled when they are first called, so
+/// this is what catches e.g. a stray `break` in a function that never runs.
+pub fn check_program(program: Program, entry: EntryPoint) -> RxResult<()> {
+    let mut bodies = Vec::new();
+    for module in &program.modules {
+        collect_function_bodies(&module.ast, &mut bodies);
+    }
+
+    let mut errors = Vec::new();
+    if let Err(e) = compile_program(program, entry, &mut Vec::new(), &mut LabelGenerator::new()) {
+        errors.push(e);
+    }
 
-            code.push(Instruction::Call("main".to_string(), 0));
-            code.push(Instruction::Return);
+    for body in bodies {
+        let mut code = Vec::new();
+        let mut labels = LabelGenerator::new();
+        let mut break_stack = Vec::new();
+        for stmt in body {
+            if let Err(e) = compile(stmt, &mut code, &mut labels, &mut break_stack) {
+                errors.push(e);
+                break;
+            }
         }
+    }
 
-        AST::Print(e) => {
-            compile(*e, code, labels, break_stack);
-            code.push(Instruction::Print);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RxError::many(errors))
+    }
+}
+
+fn collect_function_bodies(node: &Node, out: &mut Vec<Vec<Node>>) {
+    if let AST::FuncDef { body, .. } | AST::Lambda { body, .. } = &node.kind {
+        out.push(body.clone());
+    }
+    for child in node.children() {
+        collect_function_bodies(child, out);
+    }
+}
+
+fn compile_entry(
+    stmts: Vec<Node>,
+    span: &Span,
+    entry: EntryPoint,
+    imports: &HashMap<Vec<String>, usize>,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    let has_main = stmts
+        .iter()
+        .map(|s| match &s.kind {
+            AST::Pub(item) => item,
+            _ => s,
+        })
+        .any(|s| matches!(&s.kind, AST::FuncDef { name, .. } if name == "main"));
+
+    let call_main = match entry {
+        EntryPoint::Auto => has_main,
+        EntryPoint::Main if !has_main => {
+            return Err(compile_error(span, "no `main` function defined"));
         }
+        EntryPoint::Main => true,
+        EntryPoint::Script => false,
+    };
 
-        AST::Println(e) => {
-            compile(*e, code, labels, break_stack);
-            code.push(Instruction::Println);
+    let start = code.len();
+    for s in stmts {
+        compile_item(s, imports, code, labels, break_stack)?;
+    }
+    top_level_returns(
+        &mut code[start..],
+        call_main.then_some(
+            "`return` at the top level would skip `main`; return the exit status from `main` instead",
+        ),
+    )?;
+
+    if call_main {
+        emit(code, span, Instruction::CallMain);
+    }
+    emit(code, span, Instruction::Return);
+    Ok(())
+}
+
+/// Compiles a top-level statement. `pub` items are exported from the module,
+/// and imports bind the module the linker resolved them to.
+fn compile_item(
+    node: Node,
+    imports: &HashMap<Vec<String>, usize>,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    let span = node.span;
+    match node.kind {
+        AST::Import { path, alias, names } => {
+            let module = *imports.get(&path).ok_or_else(|| {
+                compile_error(&span, &format!("unresolved import `{}`", path.join(".")))
+            })?;
+            emit(
+                code,
+                &span,
+                Instruction::Import {
+                    module,
+                    alias,
+                    names,
+                },
+            );
         }
 
-        AST::ImmutableAssignTarget(target, value) => {
-            compile_lvalue(*target, code, labels, break_stack);
-            compile(*value, code, labels, break_stack);
-            code.push(Instruction::StoreThroughImmutable);
+        AST::Pub(item) => {
+            let name = match &item.kind {
+                AST::FuncDef { name, .. }
+                | AST::StructDef { name, .. }
+                | AST::ImmutableAssign(name, _) => name.clone(),
+                _ => return Err(compile_error(&span, "only items can be `pub`")),
+            };
+
+            compile(*item, code, labels, break_stack)?;
+            emit(code, &span, Instruction::Export(name));
         }
-        AST::Cast { target, expr } => {
-            compile(*expr, code, labels, break_stack);
-            code.push(Instruction::Cast(target));
+
+        kind => compile(Node::new(kind, span), code, labels, break_stack)?,
+    }
+    Ok(())
+}
+
+/// An enclosing loop, as seen by `break` and `continue`.
+pub struct LoopScope {
+    label: Option<String>,
+    break_to: String,
+    continue_to: String,
+    // `if` branches open inside this loop (and not inside a nested loop),
+    // each with its own immutable context
+    blocks: usize,
+}
+
+impl LoopScope {
+    fn new(label: Option<String>, break_to: String, continue_to: String) -> Self {
+        Self {
+            label,
+            break_to,
+            continue_to,
+            blocks: 0,
         }
     }
 }
-pub fn compile_module(
-    ast: AST,
-    code: &mut Vec<Instruction>,
+
+/// Compiles statements in their own immutable context.
+fn compile_block(
+    stmts: Vec<Node>,
+    span: &Span,
+    code: &mut Vec<Spanned<Instruction>>,
     labels: &mut LabelGenerator,
-    break_stack: &mut Vec<String>,
-) {
-    match ast {
-        AST::Program(stmts) => {
-            for s in stmts {
-                compile(s, code, labels, break_stack);
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    emit(code, span, Instruction::PushImmutableContext);
+    if let Some(scope) = break_stack.last_mut() {
+        scope.blocks += 1;
+    }
+
+    let mut result = Ok(());
+    for s in stmts {
+        result = compile(s, code, labels, break_stack);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if let Some(scope) = break_stack.last_mut() {
+        scope.blocks -= 1;
+    }
+    emit(code, span, Instruction::PopImmutableContext);
+    result
+}
+
+fn compile_loop_body(
+    body: Vec<Node>,
+    scope: LoopScope,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    break_stack.push(scope);
+    let mut result = Ok(());
+    for s in body {
+        result = compile(s, code, labels, break_stack);
+        if result.is_err() {
+            break;
+        }
+    }
+    break_stack.pop();
+    result
+}
+
+/// `break` or `continue`, optionally naming an outer loop. Every immutable
+/// context opened since the target loop's own is popped before jumping.
+fn compile_loop_exit(
+    label: Option<String>,
+    is_continue: bool,
+    span: &Span,
+    code: &mut Vec<Spanned<Instruction>>,
+    break_stack: &mut [LoopScope],
+) -> RxResult<()> {
+    let keyword = if is_continue { "continue" } else { "break" };
+    let index = match &label {
+        None => break_stack
+            .len()
+            .checked_sub(1)
+            .ok_or_else(|| compile_error(span, &format!("{keyword} used outside of loop")))?,
+        Some(name) => break_stack
+            .iter()
+            .rposition(|l| l.label.as_ref() == Some(name))
+            .ok_or_else(|| compile_error(span, &format!("unknown loop label `'{name}`")))?,
+    };
+
+    let inner: usize = break_stack[index + 1..].iter().map(|l| l.blocks + 1).sum();
+    for _ in 0..break_stack[index].blocks + inner {
+        emit(code, span, Instruction::PopImmutableContext);
+    }
+
+    let target = &break_stack[index];
+    let target = if is_continue {
+        target.continue_to.clone()
+    } else {
+        target.break_to.clone()
+    };
+    emit(code, span, Instruction::Jump(target));
+    Ok(())
+}
+
+/// Pushes the argument values of a call, named ones last.
+fn compile_args(
+    args: Vec<Node>,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<CallArgs> {
+    let count = args.len();
+    let mut names = Vec::new();
+    for a in args {
+        match a.kind {
+            AST::NamedArg(name, value) => {
+                names.push(name);
+                compile(*value, code, labels, break_stack)?;
             }
+            _ => compile(a, code, labels, break_stack)?,
         }
-        other => compile(other, code, labels, break_stack),
     }
+    Ok(CallArgs { count, names })
 }
+
 fn compile_lvalue(
-    ast: AST,
-    code: &mut Vec<Instruction>,
+    node: Node,
+    code: &mut Vec<Spanned<Instruction>>,
     labels: &mut LabelGenerator,
-    break_stack: &mut Vec<String>,
-) {
-    match ast {
+    break_stack: &mut Vec<LoopScope>,
+) -> RxResult<()> {
+    let span = node.span;
+    match node.kind {
         AST::Var(name) => {
-            code.push(Instruction::Load(name));
+            emit(code, &span, Instruction::Load(name));
         }
 
         AST::Index(base, index) => {
-            compile_lvalue(*base, code, labels, break_stack);
-            compile(*index, code, labels, break_stack);
-            code.push(Instruction::ArrayLValue);
+            compile_lvalue(*base, code, labels, break_stack)?;
+            compile(*index, code, labels, break_stack)?;
+            emit(code, &span, Instruction::ArrayLValue);
         }
 
         AST::FieldAccess(base, field) => {
-            compile_lvalue(*base, code, labels, break_stack);
-            code.push(Instruction::FieldLValue(field));
+            compile_lvalue(*base, code, labels, break_stack)?;
+            emit(code, &span, Instruction::FieldLValue(field));
         }
 
-        other => panic!("invalid assignment target: {:?}", other),
+        // a slice is a view, so `s[..][i] = v` writes through to the array
+        kind @ AST::Slice { .. } => {
+            compile(Node::new(kind, span), code, labels, break_stack)?;
+        }
+
+        other => {
+            let msg = format!("invalid assignment target: {:?}", other);
+            return Err(compile_error(&span, &msg));
+        }
     }
+    Ok(())
 }
 
-fn emit_operator(op: Operator, code: &mut Vec<Instruction>) {
+fn emit_operator(op: Operator, span: &Span, code: &mut Vec<Spanned<Instruction>>) {
     use Operator::*;
     match op {
-        Addition => code.push(Instruction::Add),
-        Subtraction => code.push(Instruction::Sub),
-        Multiplication => code.push(Instruction::Mul),
-        Division => code.push(Instruction::Div),
-        Modulo => code.push(Instruction::Modulo),
-        Greater => code.push(Instruction::Greater),
-        Less => code.push(Instruction::Less),
-        Equal => code.push(Instruction::Equal),
-        NotEqual => code.push(Instruction::NotEqual),
-        GreaterEqual => code.push(Instruction::GreaterEqual),
-        LessEqual => code.push(Instruction::LessEqual),
-        And => code.push(Instruction::And),
-        Or => code.push(Instruction::Or),
+        Addition => emit(code, span, Instruction::Add),
+        Subtraction => emit(code, span, Instruction::Sub),
+        Multiplication => emit(code, span, Instruction::Mul),
+        Division => emit(code, span, Instruction::Div),
+        Modulo => emit(code, span, Instruction::Modulo),
+        Power => emit(code, span, Instruction::Power),
+        BitAnd => emit(code, span, Instruction::BitAnd),
+        BitOr => emit(code, span, Instruction::BitOr),
+        BitXor => emit(code, span, Instruction::BitXor),
+        ShiftLeft => emit(code, span, Instruction::ShiftLeft),
+        ShiftRight => emit(code, span, Instruction::ShiftRight),
+        Greater => emit(code, span, Instruction::Greater),
+        Less => emit(code, span, Instruction::Less),
+        Equal => emit(code, span, Instruction::Equal),
+        NotEqual => emit(code, span, Instruction::NotEqual),
+        Identical => emit(code, span, Instruction::Identical),
+        NotIdentical => emit(code, span, Instruction::NotIdentical),
+        GreaterEqual => emit(code, span, Instruction::GreaterEqual),
+        LessEqual => emit(code, span, Instruction::LessEqual),
+        And | Or => unreachable!("`&&` and `||` compile to jumps"),
     }
 }
 
-fn compile_string_literal(s: String, code: &mut Vec<Instruction>, labels: &mut LabelGenerator) {
-    code.push(Instruction::Push(s.chars().count() as i32));
-    code.push(Instruction::ArrayNew);
+fn compile_string_literal(
+    s: String,
+    span: &Span,
+    code: &mut Vec<Spanned<Instruction>>,
+    labels: &mut LabelGenerator,
+) {
+    emit(code, span, Instruction::Push(s.chars().count() as i32));
+    emit(code, span, Instruction::ArrayNew(1));
 
     let tmp = labels.fresh("__strlit");
-    code.push(Instruction::Store(tmp.clone()));
+    emit(code, span, Instruction::Store(tmp.clone()));
 
     for (i, ch) in s.chars().enumerate() {
-        code.push(Instruction::Load(tmp.clone()));
-        code.push(Instruction::Push(i as i32));
-        code.push(Instruction::ArrayLValue);
-        code.push(Instruction::PushChar(ch as u32));
-        code.push(Instruction::StoreThrough);
+        emit(code, span, Instruction::Load(tmp.clone()));
+        emit(code, span, Instruction::Push(i as i32));
+        emit(code, span, Instruction::ArrayLValue);
+        emit(code, span, Instruction::PushChar(ch as u32));
+        emit(code, span, Instruction::StoreThrough);
     }
 
-    code.push(Instruction::Load(tmp));
+    emit(code, span, Instruction::Load(tmp));
+}
+
+fn emit(code: &mut Vec<Spanned<Instruction>>, span: &Span, instr: Instruction) {
+    code.push(Spanned::new(instr, span.clone()));
+}
+
+fn compile_error(span: &Span, msg: &str) -> RxError {
+    RxError::compile(msg, span.clone())
 }
 
+#[derive(Default)]
 pub struct LabelGenerator {
     counter: usize,
 }
 
 impl LabelGenerator {
     pub fn new() -> Self {
-        Self { counter: 0 }
+        Self::default()
     }
 
     pub fn fresh(&mut self, prefix: &str) -> String {
@@ -0,0 +1,85 @@
+use crate::span::Span;
+use std::fmt;
+
+/// Every failure the language can report. Compile errors come from the
+/// tokenizer, parser and compiler before any code runs; runtime errors come
+/// from the VM while the program executes. `Many` collects several compile
+/// errors found in one pass (the parser keeps going after a syntax error).
+#[derive(Debug, Clone)]
+pub enum RxError {
+    Compile { message: String, span: Option<Span> },
+    Runtime { message: String, span: Option<Span> },
+    Many(Vec<RxError>),
+}
+
+pub type RxResult<T> = Result<T, RxError>;
+
+impl RxError {
+    pub fn compile(message: impl Into<String>, span: Span) -> Self {
+        RxError::Compile {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    pub fn runtime(message: impl Into<String>, span: Option<Span>) -> Self {
+        RxError::Runtime {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Wraps a list of diagnostics, unwrapping it when there is only one.
+    pub fn many(mut errors: Vec<RxError>) -> Self {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            RxError::Many(errors)
+        }
+    }
+
+    /// The individual diagnostics, flattened.
+    pub fn errors(&self) -> Vec<&RxError> {
+        match self {
+            RxError::Many(errors) => errors.iter().flat_map(|e| e.errors()).collect(),
+            other => vec![other],
+        }
+    }
+
+    /// Message of the error, or of the first one for `Many`.
+    pub fn message(&self) -> &str {
+        match self {
+            RxError::Compile { message, .. } | RxError::Runtime { message, .. } => message,
+            RxError::Many(errors) => errors.first().map_or("", |e| e.message()),
+        }
+    }
+
+    /// Span of the error, or of the first one for `Many`.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            RxError::Compile { span, .. } | RxError::Runtime { span, .. } => span.as_ref(),
+            RxError::Many(errors) => errors.first().and_then(|e| e.span()),
+        }
+    }
+}
+
+impl fmt::Display for RxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            RxError::Compile { .. } => "error",
+            RxError::Runtime { .. } => "runtime error",
+            RxError::Many(errors) => {
+                for e in errors {
+                    writeln!(f, "{e}\n")?;
+                }
+                return write!(f, "error: aborting due to {} previous errors", errors.len());
+            }
+        };
+        match self.span() {
+            Some(span) => write!(f, "{}", span.render(label, self.message())),
+            None => write!(f, "{label}: {}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for RxError {}
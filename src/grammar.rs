@@ -1,3 +1,4 @@
+use crate::span::Span;
 use std::collections::{HashMap, HashSet};
 
 //
@@ -8,9 +9,19 @@ use std::collections::{HashMap, HashSet};
 pub enum Token {
     // literals / identifiers
     Number(i32),
+    Float(f64),
     Ident(String),
     Char(u32),
     StringLiteral(String),
+    LoopLabel(String),
+
+    // `"a {x} b"`: TemplateStart, text pieces and `{...}` holes, TemplateEnd
+    TemplateStart,
+    TemplateEnd,
+    InterpStart,
+    InterpEnd,
+    // the text after `:` in a `{x:spec}` hole
+    FormatSpec(String),
 
     // arithmetic
     Add,
@@ -18,6 +29,15 @@ pub enum Token {
     Mul,
     Div,
     Modulo,
+    Power,
+
+    // bitwise
+    BitAnd,
+    BitOr,
+    BitXor,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
 
     // comparison / logic
     Greater,
@@ -26,6 +46,8 @@ pub enum Token {
     LessEqual,
     Equal,
     NotEqual,
+    Identical,
+    NotIdentical,
     And,
     Or,
     Not,
@@ -33,6 +55,8 @@ pub enum Token {
     Assign,
     ImmutableAssign,
     ReactiveAssign,
+    // `+=`, `<<=`, ...
+    CompoundAssign(Operator),
 
     // punctuation
     LParen,
@@ -43,6 +67,7 @@ pub enum Token {
     RSquare,
     Semicolon,
     Dot,
+    DotDot,
     Comma,
     Colon,
     Question,
@@ -51,13 +76,20 @@ pub enum Token {
     If,
     Else,
     Loop,
+    While,
+    For,
     Break,
+    Continue,
     Func,
     Return,
     Struct,
     Import,
+    Pub,
     Print,
     Println,
+
+    // end of input
+    Eof,
 }
 
 //
@@ -67,15 +99,37 @@ pub enum Token {
 #[derive(Debug, Clone)]
 pub enum Type {
     Integer(i32),
+    Float(f64),
     Char(u32),
 
     ArrayRef(usize),
+    // `len` elements of array `id` from index `start` on, sharing its storage
+    ArraySlice {
+        id: usize,
+        start: usize,
+        len: usize,
+    },
     StructRef(usize),
 
-    Function { params: Vec<String>, body: Vec<AST> },
+    // `module` is the module the function was defined in; its body resolves
+    // names there no matter where it is called from. `env` holds the local
+    // and immutable bindings captured where a closure or nested function was
+    // created. `name` is unset for anonymous functions; a named function
+    // can always call itself by it.
+    Function {
+        params: Vec<Param>,
+        body: Vec<Node>,
+        module: usize,
+        env: HashMap<String, Type>,
+        name: Option<String>,
+    },
 
-    LazyValue(Box<AST>, HashMap<String, Type>),
+    // expression, captured immutables, defining module
+    LazyValue(Box<Node>, HashMap<String, Type>, usize),
     LValue(LValue),
+
+    // namespace bound by `import a.b` or `import a.b as x`
+    Module(usize),
     Uninitialized,
 }
 
@@ -87,6 +141,9 @@ pub enum LValue {
 
 #[derive(Debug, Clone)]
 pub struct StructInstance {
+    // the struct's name and its fields in declaration order, for printing
+    pub name: String,
+    pub field_order: Vec<String>,
     pub fields: HashMap<String, Type>,
     pub immutables: HashSet<String>,
 }
@@ -94,67 +151,220 @@ pub struct StructInstance {
 //
 // ----------------------------- AST -----------------------------
 //
+
+/// An AST node together with the source location it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: AST,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: AST, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The nodes directly inside this one, statements and expressions alike,
+    /// including function bodies and struct field initializers.
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.kind {
+            AST::Number(_)
+            | AST::Float(_)
+            | AST::Char(_)
+            | AST::StringLiteral(_)
+            | AST::Var(_)
+            | AST::Break(_)
+            | AST::Continue(_)
+            | AST::Import { .. } => Vec::new(),
+
+            AST::Operation(l, _, r)
+            | AST::Index(l, r)
+            | AST::ImmutableAssignTarget(l, r)
+            | AST::AssignTarget(l, r)
+            | AST::ReactiveAssignTarget(l, r)
+            | AST::CompoundAssign(l, _, r) => vec![l, r],
+            AST::Ternary {
+                cond,
+                then_expr,
+                else_expr,
+            } => vec![cond, then_expr, else_expr],
+
+            AST::Assign(_, e)
+            | AST::ImmutableAssign(_, e)
+            | AST::ReactiveAssign(_, e)
+            | AST::Print(e)
+            | AST::Println(e)
+            | AST::Cast { expr: e, .. }
+            | AST::Unary(_, e)
+            | AST::FieldAccess(e, _)
+            | AST::Pub(e)
+            | AST::NamedArg(_, e) => vec![e],
+            AST::Return(e) => e.iter().map(|e| &**e).collect(),
+
+            AST::Program(body)
+            | AST::Loop { body, .. }
+            | AST::Call { args: body, .. }
+            | AST::ArrayNew(body)
+            | AST::ArrayLiteral(body) => body.iter().collect(),
+            AST::StructNew(_, fields) => fields.iter().map(|(_, value)| value).collect(),
+            AST::Template(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    TemplatePart::Value(value, _) => Some(value),
+                    TemplatePart::Text(_) => None,
+                })
+                .collect(),
+            AST::FuncDef { params, body, .. } | AST::Lambda { params, body } => params
+                .iter()
+                .filter_map(|p| p.default.as_ref())
+                .chain(body)
+                .collect(),
+            AST::IfElse(cond, then_block, else_block) => std::iter::once(&**cond)
+                .chain(then_block)
+                .chain(else_block)
+                .collect(),
+            AST::While { cond, body, .. } => std::iter::once(&**cond).chain(body).collect(),
+            AST::For {
+                start, end, body, ..
+            } => [&**start, &**end].into_iter().chain(body).collect(),
+            AST::Slice { base, start, end } => std::iter::once(&**base)
+                .chain(start.as_deref())
+                .chain(end.as_deref())
+                .collect(),
+            AST::Invoke { callee: base, args } | AST::MemberCall { base, args, .. } => {
+                std::iter::once(&**base).chain(args).collect()
+            }
+
+            AST::StructDef { fields, .. } => fields
+                .iter()
+                .filter_map(|(_, init)| match init {
+                    Some(
+                        StructFieldInit::Mutable(e)
+                        | StructFieldInit::Immutable(e)
+                        | StructFieldInit::Reactive(e),
+                    ) => Some(e),
+                    None => None,
+                })
+                .collect(),
+            AST::FieldAssign { base, value, .. } => vec![base, value],
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum CastType {
     Int,
+    Float,
     Char,
+    Str,
 }
+
 #[derive(Debug, Clone)]
 pub enum AST {
     // literals
     Number(i32),
+    Float(f64),
     Char(u32),
     StringLiteral(String),
+    // `"x = {x}"`
+    Template(Vec<TemplatePart>),
 
     // variables
     Var(String),
 
     // expressions
-    Operation(Box<AST>, Operator, Box<AST>),
+    Operation(Box<Node>, Operator, Box<Node>),
+    // `-x` and `~x`
+    Unary(UnaryOperator, Box<Node>),
     Ternary {
-        cond: Box<AST>,
-        then_expr: Box<AST>,
-        else_expr: Box<AST>,
+        cond: Box<Node>,
+        then_expr: Box<Node>,
+        else_expr: Box<Node>,
     },
 
     // arrays
-    ArrayNew(Box<AST>),
-    Index(Box<AST>, Box<AST>),
+    // `[n]` allocates n zeros; `[n][m]` allocates n arrays of m zeros, etc.
+    ArrayNew(Vec<Node>),
+    // `[a, b, c]`
+    ArrayLiteral(Vec<Node>),
+    Index(Box<Node>, Box<Node>),
+    // `arr[a..b]`, `arr[a..]` or `arr[..b]`
+    Slice {
+        base: Box<Node>,
+        start: Option<Box<Node>>,
+        end: Option<Box<Node>>,
+    },
 
     // assignment (binding-level)
-    Assign(String, Box<AST>),
-    ImmutableAssign(String, Box<AST>),
-    ReactiveAssign(String, Box<AST>),
-    ImmutableAssignTarget(Box<AST>, Box<AST>),
+    Assign(String, Box<Node>),
+    ImmutableAssign(String, Box<Node>),
+    ReactiveAssign(String, Box<Node>),
+    ImmutableAssignTarget(Box<Node>, Box<Node>),
 
     // assignment (lvalue-level)
-    AssignTarget(Box<AST>, Box<AST>),
-    ReactiveAssignTarget(Box<AST>, Box<AST>),
+    AssignTarget(Box<Node>, Box<Node>),
+    ReactiveAssignTarget(Box<Node>, Box<Node>),
+    // `target op= value`
+    CompoundAssign(Box<Node>, Operator, Box<Node>),
 
     // control flow
-    Program(Vec<AST>),
-    IfElse(Box<AST>, Vec<AST>, Vec<AST>),
-    Loop(Vec<AST>),
-    Break,
-    Return(Option<Box<AST>>),
+    Program(Vec<Node>),
+    IfElse(Box<Node>, Vec<Node>, Vec<Node>),
+    Loop {
+        label: Option<String>,
+        body: Vec<Node>,
+    },
+    While {
+        label: Option<String>,
+        cond: Box<Node>,
+        body: Vec<Node>,
+    },
+    // `for var in start..end`; `end` is exclusive and evaluated once
+    For {
+        label: Option<String>,
+        var: String,
+        start: Box<Node>,
+        end: Box<Node>,
+        body: Vec<Node>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Option<Box<Node>>),
 
     // IO
-    Print(Box<AST>),
-    Println(Box<AST>),
+    Print(Box<Node>),
+    Println(Box<Node>),
 
     // functions
     FuncDef {
         name: String,
-        params: Vec<String>,
-        body: Vec<AST>,
+        params: Vec<Param>,
+        body: Vec<Node>,
     },
     Call {
         name: String,
-        args: Vec<AST>,
+        args: Vec<Node>,
+    },
+    // anonymous `func(params) { body }`
+    Lambda {
+        params: Vec<Param>,
+        body: Vec<Node>,
+    },
+    // call through any expression: `arr[i](x)`, `make()(x)`
+    Invoke {
+        callee: Box<Node>,
+        args: Vec<Node>,
+    },
+    MemberCall {
+        base: Box<Node>,
+        name: String,
+        args: Vec<Node>,
     },
+    // `name: value` in an argument list, after every positional argument
+    NamedArg(String, Box<Node>),
     Cast {
         target: CastType,
-        expr: Box<AST>,
+        expr: Box<Node>,
     },
 
     // structs
@@ -162,17 +372,86 @@ pub enum AST {
         name: String,
         fields: Vec<(String, Option<StructFieldInit>)>,
     },
-    StructNew(String),
-    FieldAccess(Box<AST>, String),
+    // `struct Name`, or `struct Name { field: value, ... }`
+    StructNew(String, Vec<(String, Node)>),
+    FieldAccess(Box<Node>, String),
     FieldAssign {
-        base: Box<AST>,
+        base: Box<Node>,
         field: String,
-        value: Box<AST>,
+        value: Box<Node>,
         kind: FieldAssignKind,
     },
 
     // modules
-    Import(Vec<String>),
+    Import {
+        path: Vec<String>,
+        alias: Option<String>,
+        names: Option<Vec<String>>,
+    },
+    // `pub` function, struct or top-level `:=`
+    Pub(Box<Node>),
+}
+
+//
+// ----------------------------- STRING TEMPLATES -----------------------------
+//
+
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    Text(String),
+    // `{expr}` or `{expr:spec}`
+    Value(Node, Option<FormatSpec>),
+}
+
+/// `[[fill]align][0][width][.precision][kind]` after the `:` of a
+/// `{x:spec}` hole.
+#[derive(Debug, Clone)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    // `0`: pad numbers with zeros after the sign
+    pub zero: bool,
+    pub width: usize,
+    // `.2`: a number with that many digits after the point
+    pub precision: Option<usize>,
+    pub kind: FormatKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatKind {
+    // as `print` shows it
+    Display,
+    // `d`, `x`, `X`, `o`, `b`: an integer in that radix
+    Int { radix: u32, upper: bool },
+    // `c`: an integer as the char with that code
+    Char,
+}
+
+//
+// ----------------------------- FUNCTIONS -----------------------------
+//
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    // evaluated at each call that leaves the parameter out, after the
+    // parameters before it are bound
+    pub default: Option<Node>,
+}
+
+/// The arguments of a call as they sit on the stack: `count` values, the
+/// last `names.len()` of which were passed by name.
+#[derive(Debug, Clone)]
+pub struct CallArgs {
+    pub count: usize,
+    pub names: Vec<String>,
 }
 
 //
@@ -188,22 +467,29 @@ pub enum FieldAssignKind {
 
 #[derive(Debug, Clone)]
 pub enum StructFieldInit {
-    Mutable(AST),
-    Immutable(AST),
-    Reactive(AST),
+    Mutable(Node),
+    Immutable(Node),
+    Reactive(Node),
 }
 
 //
 // ----------------------------- OPERATORS -----------------------------
 //
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Operator {
     Addition,
     Subtraction,
     Multiplication,
     Division,
     Modulo,
+    Power,
+
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
 
     Greater,
     Less,
@@ -211,11 +497,19 @@ pub enum Operator {
     LessEqual,
     Equal,
     NotEqual,
+    Identical,
+    NotIdentical,
 
     And,
     Or,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+    BitNot,
+}
+
 //
 // ----------------------------- BYTECODE -----------------------------
 //
@@ -224,13 +518,16 @@ pub enum Operator {
 pub enum Instruction {
     // stack ops
     Push(i32),
+    PushFloat(f64),
     PushChar(u32),
     Load(String),
+    // pushes a copy of the top value
+    Dup,
 
     // variable storage
     Store(String),
     StoreImmutable(String),
-    StoreReactive(String, Box<AST>),
+    StoreReactive(String, Box<Node>),
 
     // arithmetic
     Add,
@@ -238,6 +535,16 @@ pub enum Instruction {
     Mul,
     Div,
     Modulo,
+    Power,
+    Negate,
+
+    // bitwise
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
 
     // comparison / logic
     Greater,
@@ -246,8 +553,8 @@ pub enum Instruction {
     LessEqual,
     Equal,
     NotEqual,
-    And,
-    Or,
+    Identical,
+    NotIdentical,
 
     // control flow
     Label(String),
@@ -256,28 +563,39 @@ pub enum Instruction {
     Return,
 
     // arrays
-    ArrayNew,
+    ArrayNew(usize),
+    ArrayLiteral(usize),
     ArrayGet,
     ArrayLValue,
+    // base, start and, unless the slice is open-ended, end are on the stack
+    ArraySlice {
+        has_end: bool,
+    },
     StoreIndex(String),
-    StoreIndexReactive(String, Box<AST>),
+    StoreIndexReactive(String, Box<Node>),
 
     // structs
     StoreStruct(String, Vec<(String, Option<StructFieldInit>)>),
-    NewStruct(String),
+    NewStruct(String, Vec<String>),
     FieldGet(String),
     FieldSet(String),
-    FieldSetReactive(String, Box<AST>),
+    FieldSetReactive(String, Box<Node>),
     FieldLValue(String),
 
     // indirect stores
     StoreThrough,
-    StoreThroughReactive(Box<AST>),
+    StoreThroughReactive(Box<Node>),
     StoreThroughImmutable,
 
     // functions
-    StoreFunction(String, Vec<String>, Vec<AST>),
-    Call(String, usize),
+    StoreFunction(String, Vec<Param>, Vec<Node>),
+    Call(String, CallArgs),
+    CallValue(CallArgs),
+    CallMember(String, CallArgs),
+    MakeClosure(Vec<Param>, Vec<Node>),
+    CallMain,
+    // ends a script, with the popped value as the exit status
+    Exit,
 
     // immutable scopes
     PushImmutableContext,
@@ -289,8 +607,18 @@ pub enum Instruction {
     Println,
 
     // modules
-    Import(Vec<String>),
+    EnterModule(usize, String),
+    Import {
+        module: usize,
+        alias: Option<String>,
+        names: Option<Vec<String>>,
+    },
+    Export(String),
 
     //casts
     Cast(CastType),
+
+    // string templates: a hole's value as text, then the top `n` strings joined
+    Format(Option<FormatSpec>),
+    Concat(usize),
 }
@@ -1,5 +1,11 @@
+pub mod arity;
 pub mod compiler;
+pub mod error;
 pub mod grammar;
+pub mod linker;
+pub mod loader;
 pub mod parser;
+pub mod resolve;
+pub mod span;
 pub mod tokenizer;
 pub mod vm;
@@ -0,0 +1,182 @@
+use crate::error::{RxError, RxResult};
+use crate::grammar::{AST, Node};
+use crate::loader::ModuleLoader;
+use crate::parser::parse;
+use crate::resolve::check_names;
+use crate::span::Span;
+use crate::tokenizer::tokenize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// One source file of a linked program.
+#[derive(Debug, Clone)]
+pub struct LinkedModule {
+    /// Module id; 0 is the program itself.
+    pub id: usize,
+    /// Dotted import path (`std.maths`); empty for the program itself.
+    pub name: String,
+    pub ast: Node,
+    /// The module each `import` path in this file resolves to.
+    pub imports: HashMap<Vec<String>, usize>,
+}
+
+/// A program together with every module it imports, directly or not,
+/// ordered so that each module comes after everything it imports. The
+/// program itself is always last.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub modules: Vec<LinkedModule>,
+}
+
+/// Resolves, loads and parses the whole import graph of `root` before
+/// anything runs. Missing modules, syntax errors in imported files, import
+/// cycles and name collisions between modules are all reported here.
+pub fn link(root: Node, loader: &ModuleLoader) -> RxResult<Program> {
+    let root_key = source_key(&root.span.source.name);
+    let mut linker = Linker {
+        loader,
+        modules: Vec::new(),
+        ids: HashMap::from([(root_key, 0)]),
+        order: Vec::new(),
+        visiting: Vec::new(),
+        errors: Vec::new(),
+    };
+    linker.modules.push(LinkedModule {
+        id: 0,
+        name: String::new(),
+        ast: root,
+        imports: HashMap::new(),
+    });
+    linker.visit(0);
+
+    if !linker.errors.is_empty() {
+        return Err(RxError::many(linker.errors));
+    }
+
+    let mut modules: Vec<Option<LinkedModule>> = linker.modules.into_iter().map(Some).collect();
+    let modules = linker
+        .order
+        .into_iter()
+        .filter_map(|id| modules[id].take())
+        .collect();
+    let program = Program { modules };
+    check_names(&program)?;
+    Ok(program)
+}
+
+struct Linker<'a> {
+    loader: &'a ModuleLoader,
+    modules: Vec<LinkedModule>,
+
+    // Module id of every file loaded so far, by resolved path
+    ids: HashMap<PathBuf, usize>,
+
+    // Finished modules, dependencies first
+    order: Vec<usize>,
+
+    // Modules whose imports are being resolved, outermost first
+    visiting: Vec<usize>,
+
+    errors: Vec<RxError>,
+}
+
+impl Linker<'_> {
+    fn visit(&mut self, id: usize) {
+        self.visiting.push(id);
+
+        let importer = self.modules[id].ast.span.source.name.clone();
+        for (path, span) in self.imports_of(id) {
+            let source = match self.loader.load(&path, Some(Path::new(&importer))) {
+                Ok(source) => source,
+                Err(msg) => {
+                    self.errors.push(RxError::compile(msg, span));
+                    continue;
+                }
+            };
+
+            let key = source_key(&source.name);
+            let dep = match self.ids.get(&key) {
+                Some(&dep) if self.visiting.contains(&dep) => {
+                    let start = self.visiting.iter().position(|&m| m == dep).unwrap_or(0);
+                    let chain: Vec<String> = self.visiting[start..]
+                        .iter()
+                        .chain([&dep])
+                        .map(|&m| self.display_name(m))
+                        .collect();
+                    self.errors.push(RxError::compile(
+                        format!("import cycle: {}", chain.join(" -> ")),
+                        span,
+                    ));
+                    continue;
+                }
+                Some(&dep) => dep,
+                None => {
+                    let ast = tokenize(&source).and_then(parse).unwrap_or_else(|e| {
+                        self.errors.push(e);
+                        Node::new(AST::Program(Vec::new()), span.clone())
+                    });
+
+                    let dep = self.modules.len();
+                    self.ids.insert(key, dep);
+                    self.modules.push(LinkedModule {
+                        id: dep,
+                        name: path.join("."),
+                        ast,
+                        imports: HashMap::new(),
+                    });
+                    self.visit(dep);
+                    dep
+                }
+            };
+            self.modules[id].imports.insert(path, dep);
+        }
+
+        self.visiting.pop();
+        self.order.push(id);
+    }
+
+    /// The top-level imports of a module. Imports anywhere else are errors,
+    /// since every module is loaded before the program starts.
+    fn imports_of(&mut self, id: usize) -> Vec<(Vec<String>, Span)> {
+        let mut imports = Vec::new();
+        let AST::Program(stmts) = &self.modules[id].ast.kind else {
+            return imports;
+        };
+
+        for stmt in stmts {
+            match &stmt.kind {
+                AST::Import { path, .. } => imports.push((path.clone(), stmt.span.clone())),
+                _ => nested_imports(stmt, &mut self.errors),
+            }
+        }
+        imports
+    }
+
+    fn display_name(&self, id: usize) -> String {
+        let module = &self.modules[id];
+        if id != 0 {
+            return module.name.clone();
+        }
+        Path::new(&module.ast.span.source.name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+fn nested_imports(node: &Node, errors: &mut Vec<RxError>) {
+    if let AST::Import { .. } = node.kind {
+        errors.push(RxError::compile(
+            "`import` is only allowed at the top level of a module",
+            node.span.clone(),
+        ));
+    }
+    for child in node.children() {
+        nested_imports(child, errors);
+    }
+}
+
+/// Identifies a source file, so one reached through two paths loads once.
+fn source_key(name: &str) -> PathBuf {
+    std::fs::canonicalize(name).unwrap_or_else(|_| PathBuf::from(name))
+}
@@ -0,0 +1,110 @@
+use crate::span::Source;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The standard library, compiled into the binary so `import std.x` works
+/// from anywhere. A file at `std/x.rx` on the search path takes precedence.
+const EMBEDDED_STD: &[(&str, &str)] = &[
+    ("char", include_str!("../project/std/char.rx")),
+    ("hashmap", include_str!("../project/std/hashmap.rx")),
+    ("maths", include_str!("../project/std/maths.rx")),
+    ("matrix", include_str!("../project/std/matrix.rx")),
+    ("queue", include_str!("../project/std/queue.rx")),
+    ("stack", include_str!("../project/std/stack.rx")),
+    ("vector2", include_str!("../project/std/vector2.rx")),
+];
+
+/// Resolves `import a.b.c` to a source file. A module is looked up relative
+/// to the file that imports it first, then in each search root in order, and
+/// finally among the embedded `std` modules.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleLoader {
+    roots: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_root(&mut self, root: impl Into<PathBuf>) {
+        self.roots.push(root.into());
+    }
+
+    /// Adds every directory listed in `RX_PATH` (separated like `PATH`).
+    pub fn add_env_roots(&mut self) {
+        if let Some(paths) = std::env::var_os("RX_PATH") {
+            self.roots.extend(std::env::split_paths(&paths));
+        }
+    }
+
+    /// Directories searched for a module imported from `importer`, in order.
+    fn search_dirs(&self, importer: Option<&Path>) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        if let Some(dir) = importer.and_then(Path::parent) {
+            dirs.push(dir.to_path_buf());
+        }
+        for root in &self.roots {
+            if !dirs.contains(root) {
+                dirs.push(root.clone());
+            }
+        }
+        dirs
+    }
+
+    pub fn resolve(&self, module: &[String], importer: Option<&Path>) -> Option<PathBuf> {
+        let relative: PathBuf = module.iter().collect();
+        let relative = relative.with_extension("rx");
+
+        self.search_dirs(importer)
+            .into_iter()
+            .map(|dir| dir.join(&relative))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// Reads the module `module` imported from `importer`.
+    pub fn load(&self, module: &[String], importer: Option<&Path>) -> Result<Arc<Source>, String> {
+        let name = module.join(".");
+        let Some(path) = self.resolve(module, importer) else {
+            if let Some(text) = embedded_std(module) {
+                return Ok(Source::new(format!("<std>/{}.rx", module[1]), text));
+            }
+
+            let searched: Vec<String> = self
+                .search_dirs(importer)
+                .iter()
+                .map(|d| display_dir(d))
+                .collect();
+            return Err(format!(
+                "could not find module `{name}` (searched: {})",
+                searched.join(", ")
+            ));
+        };
+
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "could not read module `{name}` from `{}`: {e}",
+                path.display()
+            )
+        })?;
+        Ok(Source::new(path.display().to_string(), text))
+    }
+}
+
+fn embedded_std(module: &[String]) -> Option<&'static str> {
+    match module {
+        [std, name] if std == "std" => EMBEDDED_STD
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, text)| *text),
+        _ => None,
+    }
+}
+
+fn display_dir(dir: &Path) -> String {
+    if dir.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        dir.display().to_string()
+    }
+}
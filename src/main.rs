@@ -1,42 +1,190 @@
 use std::fs;
-use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::thread;
 
-use reactive_language::compiler::{LabelGenerator, compile};
+use reactive_language::compiler::{EntryPoint, LabelGenerator, check_program, compile_program};
+use reactive_language::error::RxResult;
 use reactive_language::grammar::Instruction;
+use reactive_language::linker::link;
+use reactive_language::loader::ModuleLoader;
 use reactive_language::parser::parse;
+use reactive_language::span::{Source, Spanned};
 use reactive_language::tokenizer::tokenize;
 use reactive_language::vm::VM;
 
+const USAGE: &str = "\
+usage: rx <command> [options] <path> [args...]
+
+commands:
+    run <path> [args...]    compile and run a program
+    check <path>            report errors without running anything
+    help                    show this message
+
+`rx <path> [args...]` is short for `rx run`, so scripts can start with
+`#!/usr/bin/env rx`.
+
+options:
+    --script                run the top level only, even if `main` is defined
+    -I, --path <dir>        add <dir> to the module search path
+    --debug                 dump the VM state when a reactive lookup fails
+
+Imports resolve relative to the importing file, then the program's own
+folder, then each -I directory, then each directory listed in RX_PATH.
+
+Arguments after <path> are passed to `main(args)` as an array of strings.
+The integer returned from `main` becomes the exit status.";
+
+/// Stack size of the thread that compiles and runs the program.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+enum Command {
+    Run,
+    Check,
+}
+
+struct Options {
+    command: Command,
+    entry: EntryPoint,
+    search_path: Vec<PathBuf>,
+    debug: bool,
+    path: String,
+    args: Vec<String>,
+}
+
 fn main() {
-    print!("Enter file name (relative to root/project/, .rx optional, nothing for main): ");
-    io::stdout().flush().unwrap();
+    let opts = parse_args(std::env::args().skip(1).collect());
 
-    let mut input_name = String::new();
-    io::stdin().read_line(&mut input_name).unwrap();
-    let mut name = input_name.trim().to_string();
+    let text = match fs::read_to_string(&opts.path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error: failed to read `{}`: {}", opts.path, e);
+            process::exit(1);
+        }
+    };
 
-    if name.is_empty() {
-        name = "main".to_string();
+    let mut loader = ModuleLoader::new();
+    if let Some(dir) = Path::new(&opts.path).parent() {
+        loader.add_root(dir);
     }
+    for dir in opts.search_path {
+        loader.add_root(dir);
+    }
+    loader.add_env_roots();
+
+    let source = Source::new(opts.path, text);
+
+    // The interpreter recurses for every call and reactive evaluation;
+    // the VM stops at its own depth limit, which this stack has room for
+    let worker = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match opts.command {
+            Command::Run => run(source, opts.entry, loader, opts.args, opts.debug),
+            Command::Check => check(source, opts.entry, loader).map(|()| 0),
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("error: failed to start the interpreter: {e}");
+            process::exit(1);
+        });
+    let result = worker
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
 
-    if !name.ends_with(".rx") {
-        name.push_str(".rx");
+    match result {
+        Ok(status) => process::exit(status),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
     }
+}
+
+fn parse_args(argv: Vec<String>) -> Options {
+    let mut argv = argv.into_iter().peekable();
 
-    let file_path = format!("project/{}", name);
+    let command = match argv.peek().map(String::as_str) {
+        Some("run") => {
+            argv.next();
+            Command::Run
+        }
+        Some("check") => {
+            argv.next();
+            Command::Check
+        }
+        Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            process::exit(0);
+        }
+        Some(_) => Command::Run,
+        None => usage_error("missing command"),
+    };
 
-    let input = fs::read_to_string(&file_path)
-        .unwrap_or_else(|e| panic!("failed to read `{}`: {}", file_path, e));
+    let mut entry = EntryPoint::Auto;
+    let mut search_path = Vec::new();
+    let mut debug = false;
+    while let Some(flag) = argv.next_if(|a| a.starts_with('-')) {
+        match flag.as_str() {
+            "--script" => entry = EntryPoint::Script,
+            "--debug" => debug = true,
+            "-I" | "--path" => match argv.next() {
+                Some(dir) => search_path.push(PathBuf::from(dir)),
+                None => usage_error(&format!("`{flag}` expects a directory")),
+            },
+            other => usage_error(&format!("unknown option `{other}`")),
+        }
+    }
 
-    let tokens = tokenize(&input);
-    let ast = parse(tokens);
+    let Some(path) = argv.next() else {
+        usage_error("missing <path>");
+    };
+    let args: Vec<String> = argv.collect();
 
-    let mut bytecode: Vec<Instruction> = Vec::new();
+    if matches!(command, Command::Check) && !args.is_empty() {
+        usage_error("`check` takes a single <path>");
+    }
+
+    Options {
+        command,
+        entry,
+        search_path,
+        debug,
+        path,
+        args,
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("error: {msg}\n\n{USAGE}");
+    process::exit(2);
+}
+
+fn run(
+    source: Arc<Source>,
+    entry: EntryPoint,
+    loader: ModuleLoader,
+    args: Vec<String>,
+    debug: bool,
+) -> RxResult<i32> {
+    let tokens = tokenize(&source)?;
+    let ast = parse(tokens)?;
+    let program = link(ast, &loader)?;
+
+    let mut bytecode: Vec<Spanned<Instruction>> = Vec::new();
     let mut label_gen = LabelGenerator::new();
-    let mut break_stack = Vec::new();
 
-    compile(ast, &mut bytecode, &mut label_gen, &mut break_stack);
+    compile_program(program, entry, &mut bytecode, &mut label_gen)?;
 
     let mut vm = VM::new(bytecode);
-    vm.run();
+    vm.set_args(args);
+    vm.set_debug(debug);
+    vm.run()?;
+    Ok(vm.exit_status())
+}
+
+fn check(source: Arc<Source>, entry: EntryPoint, loader: ModuleLoader) -> RxResult<()> {
+    let tokens = tokenize(&source)?;
+    let ast = parse(tokens)?;
+    let program = link(ast, &loader)?;
+    check_program(program, entry)
 }
@@ -1,63 +1,150 @@
-use crate::grammar::{AST, Operator, StructFieldInit, Token};
+use crate::error::{RxError, RxResult};
+use crate::grammar::{
+    AST, Align, FormatKind, FormatSpec, Node, Operator, Param, StructFieldInit, TemplatePart,
+    Token, UnaryOperator,
+};
+use crate::span::{Span, Spanned};
 
 struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<Spanned<Token>>,
     index: usize,
+    // Syntax errors already reported; parsing resumes after each one
+    errors: Vec<RxError>,
 }
 
 impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, index: 0 }
+    fn new(tokens: Vec<Spanned<Token>>) -> Self {
+        Self {
+            tokens,
+            index: 0,
+            errors: Vec::new(),
+        }
     }
 
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.index)
+        self.peek_n(0)
     }
 
     fn peek_n(&self, n: usize) -> Option<&Token> {
-        self.tokens.get(self.index + n)
+        match self.tokens.get(self.index + n) {
+            Some(Spanned {
+                node: Token::Eof, ..
+            })
+            | None => None,
+            Some(tok) => Some(&tok.node),
+        }
     }
 
     fn next(&mut self) -> Option<&Token> {
-        let tok = self.tokens.get(self.index);
+        self.peek()?;
         self.index += 1;
-        tok
+        Some(&self.tokens[self.index - 1].node)
+    }
+
+    /// Span of the next token, or of the end of input.
+    fn span(&self) -> Span {
+        let i = self.index.min(self.tokens.len() - 1);
+        self.tokens[i].span.clone()
     }
 
-    fn expect(&mut self, expected: Token) {
-        let got = self.next().cloned();
+    fn error(&self, msg: &str) -> RxError {
+        RxError::compile(msg, self.span())
+    }
+
+    fn expect(&mut self, expected: Token) -> RxResult<()> {
+        let got = self.peek().cloned();
         if got.as_ref() != Some(&expected) {
-            panic!("Expected {:?}, got {:?}", expected, got);
+            return Err(self.error(&format!(
+                "expected {:?}, got {}",
+                expected,
+                describe(got.as_ref())
+            )));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn expect_ident(&mut self) -> RxResult<String> {
+        match self.peek() {
+            Some(Token::Ident(s)) => {
+                let s = s.clone();
+                self.next();
+                Ok(s)
+            }
+            other => Err(self.error(&format!("expected identifier, got {}", describe(other)))),
         }
     }
 
-    fn expect_ident(&mut self) -> String {
-        match self.next() {
-            Some(Token::Ident(s)) => s.clone(),
-            other => panic!("Expected identifier, got {:?}", other),
+    // ---------------- error recovery ----------------
+
+    /// Records a syntax error. An unclosed block is reported once, not once
+    /// per enclosing block.
+    fn report(&mut self, err: RxError) {
+        let duplicate = self.errors.iter().any(|e| {
+            e.message() == err.message()
+                && e.span().map(|s| (s.line, s.col)) == err.span().map(|s| (s.line, s.col))
+        });
+        if !duplicate {
+            self.errors.push(err);
+        }
+    }
+
+    /// Skips the rest of a broken statement: up to and including the next `;`,
+    /// or up to (not including) the `}` closing the enclosing block.
+    fn recover_statement(&mut self) {
+        let mut depth = 0usize;
+        while let Some(tok) = self.peek() {
+            match tok {
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => return,
+                Token::RBrace => depth -= 1,
+                Token::Semicolon if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Skips a broken top-level item: up to the next `;` or stray `}`, or the
+    /// next `import`, `pub`, `func` or `struct` definition outside any braces.
+    fn recover_toplevel(&mut self) {
+        let start = self.index;
+        let mut depth = 0usize;
+        while let Some(tok) = self.peek() {
+            let at_item = depth == 0 && self.index > start;
+            match tok {
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                Token::RBrace => depth -= 1,
+                Token::Semicolon if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                Token::Import | Token::Pub | Token::Func if at_item => return,
+                Token::Struct if at_item && matches!(self.peek_n(2), Some(Token::LBrace)) => {
+                    return;
+                }
+                _ => {}
+            }
+            self.next();
         }
     }
 
     // ---------------- expressions ----------------
 
-    fn parse_factor(&mut self) -> AST {
-        match self.next() {
+    fn parse_factor(&mut self) -> RxResult<Node> {
+        let span = self.span();
+        let kind = match self.next() {
             Some(Token::Ident(name)) => {
                 let name = name.clone();
                 if matches!(self.peek(), Some(Token::LParen)) {
-                    self.next();
-                    let mut args = Vec::new();
-                    if !matches!(self.peek(), Some(Token::RParen)) {
-                        loop {
-                            args.push(self.parse_ternary());
-                            if matches!(self.peek(), Some(Token::Comma)) {
-                                self.next();
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                    self.expect(Token::RParen);
+                    let args = self.parse_args()?;
                     AST::Call { name, args }
                 } else {
                     AST::Var(name)
@@ -65,52 +152,252 @@ impl Parser {
             }
 
             Some(Token::Number(n)) => AST::Number(*n),
+            Some(Token::Float(f)) => AST::Float(*f),
             Some(Token::Char(c)) => AST::Char(*c),
             Some(Token::StringLiteral(s)) => AST::StringLiteral(s.clone()),
+            Some(Token::TemplateStart) => AST::Template(self.parse_template()?),
 
             Some(Token::LParen) => {
-                let expr = self.parse_ternary();
-                self.expect(Token::RParen);
-                expr
+                let expr = self.parse_ternary()?;
+                self.expect(Token::RParen)?;
+                return Ok(expr);
             }
 
+            // `[a, b]` and `[a,]` list elements; `[n]` and `[n][m]` allocate
             Some(Token::LSquare) => {
-                let size = self.parse_ternary();
-                self.expect(Token::RSquare);
-                AST::ArrayNew(Box::new(size))
+                if matches!(self.peek(), Some(Token::RSquare)) {
+                    self.next();
+                    return Ok(Node::new(AST::ArrayLiteral(Vec::new()), span));
+                }
+                let first = self.parse_ternary()?;
+                if matches!(self.peek(), Some(Token::Comma)) {
+                    let mut elems = vec![first];
+                    while matches!(self.peek(), Some(Token::Comma)) {
+                        self.next();
+                        if matches!(self.peek(), Some(Token::RSquare)) {
+                            break;
+                        }
+                        elems.push(self.parse_ternary()?);
+                    }
+                    self.expect(Token::RSquare)?;
+                    AST::ArrayLiteral(elems)
+                } else {
+                    self.expect(Token::RSquare)?;
+                    let mut dims = vec![first];
+                    while matches!(self.peek(), Some(Token::LSquare)) {
+                        self.next();
+                        dims.push(self.parse_ternary()?);
+                        self.expect(Token::RSquare)?;
+                    }
+                    AST::ArrayNew(dims)
+                }
+            }
+
+            // `func(params) { body }` is an anonymous function
+            Some(Token::Func) => {
+                let (params, body) = self.parse_func_rest()?;
+                AST::Lambda { params, body }
             }
 
             Some(Token::Struct) => {
-                let name = self.expect_ident();
-                AST::StructNew(name)
+                let mut name = self.expect_ident()?;
+                // `struct ns.Name` creates a struct defined in an imported module
+                if matches!(self.peek(), Some(Token::Dot))
+                    && matches!(self.peek_n(1), Some(Token::Ident(_)))
+                {
+                    self.next();
+                    name = format!("{name}.{}", self.expect_ident()?);
+                }
+                if matches!(self.peek(), Some(Token::LParen)) {
+                    return Err(self.error(&format!(
+                        "struct fields are given by name: write `struct {name} {{ field: value }}`, not `struct {name}(...)`"
+                    )));
+                }
+                let fields = if matches!(self.peek(), Some(Token::LBrace)) {
+                    self.parse_struct_literal()?
+                } else {
+                    Vec::new()
+                };
+                AST::StructNew(name, fields)
             }
 
-            other => panic!("[parse_factor] invalid token {:?}", other),
+            other => {
+                let msg = format!("invalid token {}", describe(other));
+                // put the token back so error recovery can resync on it
+                if other.is_some() {
+                    self.index -= 1;
+                }
+                return Err(RxError::compile(msg, span));
+            }
+        };
+        Ok(Node::new(kind, span))
+    }
+
+    /// Parses the text pieces and `{expr:spec}` holes of an interpolated
+    /// string, after its `TemplateStart`.
+    fn parse_template(&mut self) -> RxResult<Vec<TemplatePart>> {
+        let mut parts = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::StringLiteral(text)) => {
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(text.clone()));
+                    }
+                }
+                Some(Token::InterpStart) => {
+                    if matches!(self.peek(), Some(Token::InterpEnd)) {
+                        return Err(self.error("empty `{}` in string; write `{{}}` for braces"));
+                    }
+                    let value = self.parse_ternary()?;
+                    let spec = match self.peek() {
+                        Some(Token::FormatSpec(spec)) => {
+                            let spec = parse_format_spec(spec).map_err(|msg| self.error(&msg))?;
+                            self.next();
+                            Some(spec)
+                        }
+                        _ => None,
+                    };
+                    if !matches!(self.peek(), Some(Token::InterpEnd)) {
+                        return Err(self.error(&format!(
+                            "expected `}}` to close `{{` in string, got {}",
+                            describe(self.peek())
+                        )));
+                    }
+                    self.next();
+                    parts.push(TemplatePart::Value(value, spec));
+                }
+                Some(Token::TemplateEnd) => return Ok(parts),
+                other => {
+                    let msg = format!("invalid token {} in string", describe(other));
+                    return Err(self.error(&msg));
+                }
+            }
         }
     }
 
-    fn parse_postfix(&mut self) -> AST {
-        let mut expr = self.parse_factor();
+    /// Parses the `{ field: value, ... }` of a struct literal.
+    fn parse_struct_literal(&mut self) -> RxResult<Vec<(String, Node)>> {
+        self.expect(Token::LBrace)?;
+        let mut fields: Vec<(String, Node)> = Vec::new();
+        while !matches!(self.peek(), Some(Token::RBrace)) {
+            let span = self.span();
+            let name = self.expect_ident()?;
+            self.expect(Token::Colon)?;
+            let value = self.parse_ternary()?;
+            // keep parsing the literal, so nothing after it is misreported
+            if fields.iter().any(|(f, _)| f == &name) {
+                self.report(RxError::compile(
+                    format!("field `{name}` is given more than once"),
+                    span,
+                ));
+            } else {
+                fields.push((name, value));
+            }
+            if matches!(self.peek(), Some(Token::Comma)) {
+                self.next();
+            } else {
+                break;
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(fields)
+    }
+
+    /// Parses a parenthesised, comma-separated argument list. Arguments
+    /// passed by name (`x: 1`) come after all positional ones.
+    fn parse_args(&mut self) -> RxResult<Vec<Node>> {
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        if !matches!(self.peek(), Some(Token::RParen)) {
+            loop {
+                let span = self.span();
+                if let (Some(Token::Ident(name)), Some(Token::Colon)) =
+                    (self.peek(), self.peek_n(1))
+                {
+                    let name = name.clone();
+                    self.next();
+                    self.next();
+                    let value = self.parse_ternary()?;
+                    args.push(Node::new(AST::NamedArg(name, Box::new(value)), span));
+                } else if args.iter().any(|a| matches!(a.kind, AST::NamedArg(..))) {
+                    return Err(self.error("positional argument after a named argument"));
+                } else {
+                    args.push(self.parse_ternary()?);
+                }
+                if matches!(self.peek(), Some(Token::Comma)) {
+                    self.next();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
+
+    fn parse_postfix(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_factor()?;
         loop {
+            let span = self.span();
             match self.peek() {
                 Some(Token::LSquare) => {
                     self.next();
-                    let idx = self.parse_ternary();
-                    self.expect(Token::RSquare);
-                    expr = AST::Index(Box::new(expr), Box::new(idx));
+                    let start = if matches!(self.peek(), Some(Token::DotDot)) {
+                        None
+                    } else {
+                        Some(Box::new(self.parse_ternary()?))
+                    };
+
+                    match start {
+                        Some(idx) if !matches!(self.peek(), Some(Token::DotDot)) => {
+                            self.expect(Token::RSquare)?;
+                            expr = Node::new(AST::Index(Box::new(expr), idx), span);
+                        }
+                        start => {
+                            self.expect(Token::DotDot)?;
+                            let end = if matches!(self.peek(), Some(Token::RSquare)) {
+                                None
+                            } else {
+                                Some(Box::new(self.parse_ternary()?))
+                            };
+                            self.expect(Token::RSquare)?;
+                            let base = Box::new(expr);
+                            expr = Node::new(AST::Slice { base, start, end }, span);
+                        }
+                    }
                 }
                 Some(Token::Dot) => {
                     self.next();
-                    let field = self.expect_ident();
-                    expr = AST::FieldAccess(Box::new(expr), field);
+                    let field = self.expect_ident()?;
+                    if matches!(self.peek(), Some(Token::LParen)) {
+                        let args = self.parse_args()?;
+                        let base = Box::new(expr);
+                        expr = Node::new(
+                            AST::MemberCall {
+                                base,
+                                name: field,
+                                args,
+                            },
+                            span,
+                        );
+                    } else {
+                        expr = Node::new(AST::FieldAccess(Box::new(expr), field), span);
+                    }
+                }
+                Some(Token::LParen) => {
+                    let args = self.parse_args()?;
+                    let callee = Box::new(expr);
+                    expr = Node::new(AST::Invoke { callee, args }, span);
                 }
                 _ => break,
             }
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_unary(&mut self) -> AST {
+    fn parse_unary(&mut self) -> RxResult<Node> {
+        let span = self.span();
+
         if matches!(self.peek(), Some(Token::LParen)) {
             let save = self.index;
             self.next();
@@ -118,23 +405,39 @@ impl Parser {
             if let Some(Token::Ident(name)) = self.peek() {
                 let target = match name.as_str() {
                     "int" => Some(crate::grammar::CastType::Int),
+                    "float" => Some(crate::grammar::CastType::Float),
                     "char" => Some(crate::grammar::CastType::Char),
+                    "str" => Some(crate::grammar::CastType::Str),
                     _ => None,
                 };
 
+                // `int` and `char` always start a cast. `float` and `str`
+                // came later, so `(str) + 1` and `(float)[0]` still use a
+                // variable of that name.
+                let target = target.filter(|target| {
+                    matches!(
+                        target,
+                        crate::grammar::CastType::Int | crate::grammar::CastType::Char
+                    ) || (matches!(self.peek_n(1), Some(Token::RParen))
+                        && starts_cast_operand(self.peek_n(2)))
+                });
+
                 if let Some(target) = target {
                     self.next();
 
                     if !matches!(self.peek(), Some(Token::RParen)) {
-                        panic!("expected ')' after cast");
+                        return Err(self.error("expected ')' after cast"));
                     }
                     self.next();
 
-                    let expr = self.parse_unary();
-                    return AST::Cast {
-                        target,
-                        expr: Box::new(expr),
-                    };
+                    let expr = self.parse_unary()?;
+                    return Ok(Node::new(
+                        AST::Cast {
+                            target,
+                            expr: Box::new(expr),
+                        },
+                        span,
+                    ));
                 }
             }
 
@@ -144,54 +447,130 @@ impl Parser {
         match self.peek() {
             Some(Token::Sub) => {
                 self.next();
-                AST::Operation(
-                    Box::new(AST::Number(0)),
-                    Operator::Subtraction,
-                    Box::new(self.parse_unary()),
-                )
+                let expr = self.parse_unary()?;
+                Ok(Node::new(
+                    AST::Unary(UnaryOperator::Negate, Box::new(expr)),
+                    span,
+                ))
             }
 
             Some(Token::Not) => {
                 self.next();
-                let expr = self.parse_unary();
-                AST::Operation(Box::new(expr), Operator::Equal, Box::new(AST::Number(0)))
+                let expr = self.parse_unary()?;
+                let zero = Node::new(AST::Number(0), span.clone());
+                Ok(Node::new(
+                    AST::Operation(Box::new(expr), Operator::Equal, Box::new(zero)),
+                    span,
+                ))
+            }
+
+            Some(Token::Tilde) => {
+                self.next();
+                let expr = self.parse_unary()?;
+                Ok(Node::new(
+                    AST::Unary(UnaryOperator::BitNot, Box::new(expr)),
+                    span,
+                ))
             }
 
-            _ => self.parse_postfix(),
+            _ => self.parse_power(),
         }
     }
 
-    fn parse_mul(&mut self) -> AST {
-        let mut expr = self.parse_unary();
+    /// `a ** b` binds tighter than a unary minus on its left and groups
+    /// to the right: `-2 ** 2` is `-4` and `2 ** 3 ** 2` is `2 ** 9`.
+    fn parse_power(&mut self) -> RxResult<Node> {
+        let expr = self.parse_postfix()?;
+        if !matches!(self.peek(), Some(Token::Power)) {
+            return Ok(expr);
+        }
+        let span = self.span();
+        self.next();
+        let rhs = self.parse_unary()?;
+        Ok(Node::new(
+            AST::Operation(Box::new(expr), Operator::Power, Box::new(rhs)),
+            span,
+        ))
+    }
+
+    fn parse_mul(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_unary()?;
         while let Some(Token::Mul | Token::Div | Token::Modulo) = self.peek() {
+            let span = self.span();
             let op = match self.next() {
                 Some(Token::Mul) => Operator::Multiplication,
                 Some(Token::Div) => Operator::Division,
                 Some(Token::Modulo) => Operator::Modulo,
                 _ => unreachable!(),
             };
-            let rhs = self.parse_unary();
-            expr = AST::Operation(Box::new(expr), op, Box::new(rhs));
+            let rhs = self.parse_unary()?;
+            expr = Node::new(AST::Operation(Box::new(expr), op, Box::new(rhs)), span);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_add(&mut self) -> AST {
-        let mut expr = self.parse_mul();
+    fn parse_add(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_mul()?;
         while let Some(Token::Add | Token::Sub) = self.peek() {
+            let span = self.span();
             let op = match self.next() {
                 Some(Token::Add) => Operator::Addition,
                 Some(Token::Sub) => Operator::Subtraction,
                 _ => unreachable!(),
             };
-            let rhs = self.parse_mul();
-            expr = AST::Operation(Box::new(expr), op, Box::new(rhs));
+            let rhs = self.parse_mul()?;
+            expr = Node::new(AST::Operation(Box::new(expr), op, Box::new(rhs)), span);
+        }
+        Ok(expr)
+    }
+
+    fn parse_shift(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_add()?;
+        while let Some(Token::ShiftLeft | Token::ShiftRight) = self.peek() {
+            let span = self.span();
+            let op = match self.next() {
+                Some(Token::ShiftLeft) => Operator::ShiftLeft,
+                Some(Token::ShiftRight) => Operator::ShiftRight,
+                _ => unreachable!(),
+            };
+            let rhs = self.parse_add()?;
+            expr = Node::new(AST::Operation(Box::new(expr), op, Box::new(rhs)), span);
+        }
+        Ok(expr)
+    }
+
+    /// `&`, then `^`, then `|`, each looser than the last and all tighter
+    /// than comparisons, so `flags & MASK == 0` tests the masked bits.
+    fn parse_bit_and(&mut self) -> RxResult<Node> {
+        self.parse_bitwise(Token::BitAnd, Operator::BitAnd, Self::parse_shift)
+    }
+
+    fn parse_bit_xor(&mut self) -> RxResult<Node> {
+        self.parse_bitwise(Token::BitXor, Operator::BitXor, Self::parse_bit_and)
+    }
+
+    fn parse_bit_or(&mut self) -> RxResult<Node> {
+        self.parse_bitwise(Token::BitOr, Operator::BitOr, Self::parse_bit_xor)
+    }
+
+    fn parse_bitwise(
+        &mut self,
+        token: Token,
+        op: Operator,
+        operand: fn(&mut Self) -> RxResult<Node>,
+    ) -> RxResult<Node> {
+        let mut expr = operand(self)?;
+        while self.peek() == Some(&token) {
+            let span = self.span();
+            self.next();
+            let rhs = operand(self)?;
+            expr = Node::new(AST::Operation(Box::new(expr), op, Box::new(rhs)), span);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_cmp(&mut self) -> AST {
-        let mut expr = self.parse_add();
+    fn parse_cmp(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_bit_or()?;
         while let Some(tok) = self.peek() {
             let op = match tok {
                 Token::Greater => Operator::Greater,
@@ -200,96 +579,208 @@ impl Parser {
                 Token::GreaterEqual => Operator::GreaterEqual,
                 Token::LessEqual => Operator::LessEqual,
                 Token::NotEqual => Operator::NotEqual,
+                Token::Identical => Operator::Identical,
+                Token::NotIdentical => Operator::NotIdentical,
                 _ => break,
             };
+            let span = self.span();
             self.next();
-            let rhs = self.parse_add();
-            expr = AST::Operation(Box::new(expr), op, Box::new(rhs));
+            let rhs = self.parse_bit_or()?;
+            expr = Node::new(AST::Operation(Box::new(expr), op, Box::new(rhs)), span);
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_and(&mut self) -> AST {
-        let mut expr = self.parse_cmp();
+    fn parse_and(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_cmp()?;
         while matches!(self.peek(), Some(Token::And)) {
+            let span = self.span();
             self.next();
-            let rhs = self.parse_cmp();
-            expr = AST::Operation(Box::new(expr), Operator::And, Box::new(rhs));
+            let rhs = self.parse_cmp()?;
+            expr = Node::new(
+                AST::Operation(Box::new(expr), Operator::And, Box::new(rhs)),
+                span,
+            );
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_or(&mut self) -> AST {
-        let mut expr = self.parse_and();
+    fn parse_or(&mut self) -> RxResult<Node> {
+        let mut expr = self.parse_and()?;
         while matches!(self.peek(), Some(Token::Or)) {
+            let span = self.span();
             self.next();
-            let rhs = self.parse_and();
-            expr = AST::Operation(Box::new(expr), Operator::Or, Box::new(rhs));
+            let rhs = self.parse_and()?;
+            expr = Node::new(
+                AST::Operation(Box::new(expr), Operator::Or, Box::new(rhs)),
+                span,
+            );
         }
-        expr
+        Ok(expr)
     }
 
-    fn parse_ternary(&mut self) -> AST {
-        let cond = self.parse_or();
+    fn parse_ternary(&mut self) -> RxResult<Node> {
+        let cond = self.parse_or()?;
         if matches!(self.peek(), Some(Token::Question)) {
+            let span = self.span();
             self.next();
-            let then_expr = self.parse_ternary();
-            self.expect(Token::Colon);
-            let else_expr = self.parse_ternary();
-            AST::Ternary {
-                cond: Box::new(cond),
-                then_expr: Box::new(then_expr),
-                else_expr: Box::new(else_expr),
-            }
+            let then_expr = self.parse_ternary()?;
+            self.expect(Token::Colon)?;
+            let else_expr = self.parse_ternary()?;
+            Ok(Node::new(
+                AST::Ternary {
+                    cond: Box::new(cond),
+                    then_expr: Box::new(then_expr),
+                    else_expr: Box::new(else_expr),
+                },
+                span,
+            ))
         } else {
-            cond
+            Ok(cond)
         }
     }
 
     // ---------------- statements ----------------
 
-    fn parse_block(&mut self) -> Vec<AST> {
-        self.expect(Token::LBrace);
+    fn parse_block(&mut self) -> RxResult<Vec<Node>> {
+        self.expect(Token::LBrace)?;
         let mut stmts = Vec::new();
         while !matches!(self.peek(), Some(Token::RBrace)) {
-            stmts.push(self.parse_statement());
+            if self.peek().is_none() {
+                return Err(self.error("unexpected end of input, expected RBrace"));
+            }
+            match self.parse_statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => {
+                    self.report(e);
+                    self.recover_statement();
+                    continue;
+                }
+            }
             if matches!(self.peek(), Some(Token::Semicolon)) {
                 self.next();
             }
         }
-        self.expect(Token::RBrace);
-        stmts
+        self.expect(Token::RBrace)?;
+        Ok(stmts)
     }
 
-    fn parse_if(&mut self) -> AST {
+    fn parse_if(&mut self) -> RxResult<Node> {
+        let span = self.span();
         self.next();
 
-        let cond = self.parse_ternary();
-        let then_block = self.parse_block();
+        let cond = self.parse_ternary()?;
+        let then_block = self.parse_block()?;
 
         let else_block = if matches!(self.peek(), Some(Token::Else)) {
             self.next();
 
             if matches!(self.peek(), Some(Token::If)) {
-                vec![self.parse_if()]
+                vec![self.parse_if()?]
             } else {
-                self.parse_block()
+                self.parse_block()?
             }
         } else {
             Vec::new()
         };
 
-        AST::IfElse(Box::new(cond), then_block, else_block)
+        Ok(Node::new(
+            AST::IfElse(Box::new(cond), then_block, else_block),
+            span,
+        ))
     }
 
-    fn parse_func_def(&mut self) -> AST {
+    /// Parses `loop`, `while cond` or `for var in start..end`, followed by a block.
+    fn parse_loop(&mut self, label: Option<String>) -> RxResult<Node> {
+        let span = self.span();
+        let kind = match self.next() {
+            Some(Token::While) => {
+                let cond = Box::new(self.parse_ternary()?);
+                let body = self.parse_block()?;
+                AST::While { label, cond, body }
+            }
+            Some(Token::For) => {
+                let var = self.expect_ident()?;
+                match self.peek() {
+                    Some(Token::Ident(kw)) if kw == "in" => {
+                        self.next();
+                    }
+                    other => {
+                        return Err(self.error(&format!(
+                            "expected `in` after `for {var}`, got {}",
+                            describe(other)
+                        )));
+                    }
+                }
+                let start = Box::new(self.parse_ternary()?);
+                self.expect(Token::DotDot)?;
+                let end = Box::new(self.parse_ternary()?);
+                let body = self.parse_block()?;
+                AST::For {
+                    label,
+                    var,
+                    start,
+                    end,
+                    body,
+                }
+            }
+            _ => AST::Loop {
+                label,
+                body: self.parse_block()?,
+            },
+        };
+        Ok(Node::new(kind, span))
+    }
+
+    /// The optional `'label` after `break` or `continue`.
+    fn parse_label_ref(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::LoopLabel(label)) => {
+                let label = label.clone();
+                self.next();
+                Some(label)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_func_def(&mut self) -> RxResult<Node> {
+        let span = self.span();
         self.next();
-        let name = self.expect_ident();
-        self.expect(Token::LParen);
-        let mut params = Vec::new();
+        let name = self.expect_ident()?;
+        let (params, body) = self.parse_func_rest()?;
+        Ok(Node::new(AST::FuncDef { name, params, body }, span))
+    }
+
+    /// Parses the parameter list and body following `func` or `func name`.
+    /// Parameters with a default (`capacity = 16`) come after all others.
+    fn parse_func_rest(&mut self) -> RxResult<(Vec<Param>, Vec<Node>)> {
+        self.expect(Token::LParen)?;
+        let mut params: Vec<Param> = Vec::new();
         if !matches!(self.peek(), Some(Token::RParen)) {
             loop {
-                params.push(self.expect_ident());
+                let span = self.span();
+                let name = self.expect_ident()?;
+                if params.iter().any(|p| p.name == name) {
+                    return Err(RxError::compile(
+                        format!("duplicate parameter `{name}`"),
+                        span,
+                    ));
+                }
+                let default = if matches!(self.peek(), Some(Token::Assign)) {
+                    self.next();
+                    Some(self.parse_ternary()?)
+                } else if params.iter().any(|p| p.default.is_some()) {
+                    return Err(RxError::compile(
+                        format!(
+                            "parameter `{name}` needs a default, like the parameters before it"
+                        ),
+                        span,
+                    ));
+                } else {
+                    None
+                };
+                params.push(Param { name, default });
                 if matches!(self.peek(), Some(Token::Comma)) {
                     self.next();
                 } else {
@@ -297,93 +788,176 @@ impl Parser {
                 }
             }
         }
-        self.expect(Token::RParen);
-        let body = self.parse_block();
-        AST::FuncDef { name, params, body }
+        self.expect(Token::RParen)?;
+        let body = self.parse_block()?;
+        Ok((params, body))
     }
 
-    fn parse_struct_def(&mut self) -> AST {
+    fn parse_struct_def(&mut self) -> RxResult<Node> {
+        let span = self.span();
         self.next();
-        let name = self.expect_ident();
-        self.expect(Token::LBrace);
+        let name = self.expect_ident()?;
+        self.expect(Token::LBrace)?;
 
         let mut fields = Vec::new();
         while !matches!(self.peek(), Some(Token::RBrace)) {
-            let fname = self.expect_ident();
-            let init = match self.peek() {
-                Some(Token::Assign) => {
-                    self.next();
-                    Some(StructFieldInit::Mutable(self.parse_ternary()))
-                }
-                Some(Token::ImmutableAssign) => {
-                    self.next();
-                    Some(StructFieldInit::Immutable(self.parse_ternary()))
-                }
-                Some(Token::ReactiveAssign) => {
-                    self.next();
-                    Some(StructFieldInit::Reactive(self.parse_ternary()))
+            if self.peek().is_none() {
+                return Err(self.error("unexpected end of input, expected RBrace"));
+            }
+            match self.parse_struct_field() {
+                Ok(field) => fields.push(field),
+                Err(e) => {
+                    self.report(e);
+                    self.recover_statement();
+                    continue;
                 }
-                _ => None,
-            };
-            fields.push((fname, init));
+            }
             if matches!(self.peek(), Some(Token::Semicolon)) {
                 self.next();
             }
         }
 
-        self.expect(Token::RBrace);
-        AST::StructDef { name, fields }
+        self.expect(Token::RBrace)?;
+        Ok(Node::new(AST::StructDef { name, fields }, span))
     }
 
-    fn parse_return(&mut self) -> AST {
+    fn parse_struct_field(&mut self) -> RxResult<(String, Option<StructFieldInit>)> {
+        let name = self.expect_ident()?;
+        let init = match self.peek() {
+            Some(Token::Assign) => {
+                self.next();
+                Some(StructFieldInit::Mutable(self.parse_ternary()?))
+            }
+            Some(Token::ImmutableAssign) => {
+                self.next();
+                Some(StructFieldInit::Immutable(self.parse_ternary()?))
+            }
+            Some(Token::ReactiveAssign) => {
+                self.next();
+                Some(StructFieldInit::Reactive(self.parse_ternary()?))
+            }
+            _ => None,
+        };
+        Ok((name, init))
+    }
+
+    fn parse_return(&mut self) -> RxResult<Node> {
+        let span = self.span();
         self.next();
         if matches!(self.peek(), Some(Token::Semicolon | Token::RBrace)) || self.peek().is_none() {
-            AST::Return(None)
+            Ok(Node::new(AST::Return(None), span))
         } else {
-            AST::Return(Some(Box::new(self.parse_ternary())))
+            let value = self.parse_ternary()?;
+            Ok(Node::new(AST::Return(Some(Box::new(value))), span))
         }
     }
 
-    fn parse_statement(&mut self) -> AST {
+    fn parse_import(&mut self) -> RxResult<Node> {
+        let span = self.span();
+        self.next();
+        let mut path = vec![self.expect_ident()?];
+        while matches!(self.peek(), Some(Token::Dot)) {
+            self.next();
+            path.push(self.expect_ident()?);
+        }
+
+        // `as` is only a keyword here, so it stays usable as a name elsewhere
+        let mut alias = None;
+        let mut names = None;
         match self.peek() {
-            Some(Token::Import) => {
+            Some(Token::Ident(kw)) if kw == "as" => {
                 self.next();
-                let mut path = vec![self.expect_ident()];
-                while matches!(self.peek(), Some(Token::Dot)) {
+                alias = Some(self.expect_ident()?);
+            }
+            Some(Token::LParen) => {
+                self.next();
+                let mut list = vec![self.expect_ident()?];
+                while matches!(self.peek(), Some(Token::Comma)) {
                     self.next();
-                    path.push(self.expect_ident());
+                    list.push(self.expect_ident()?);
                 }
-                AST::Import(path)
+                self.expect(Token::RParen)?;
+                names = Some(list);
             }
+            _ => {}
+        }
 
-            Some(Token::Func) => self.parse_func_def(),
+        Ok(Node::new(AST::Import { path, alias, names }, span))
+    }
+
+    fn parse_pub(&mut self) -> RxResult<Node> {
+        let span = self.span();
+        self.next();
+        let item = match self.peek() {
+            Some(Token::Func) => self.parse_func_def()?,
+            Some(Token::Struct) if matches!(self.peek_n(2), Some(Token::LBrace)) => {
+                self.parse_struct_def()?
+            }
+            Some(Token::Ident(_)) if matches!(self.peek_n(1), Some(Token::ImmutableAssign)) => {
+                self.parse_statement()?
+            }
+            other => {
+                return Err(self.error(&format!(
+                    "expected a function, struct or `:=` constant after `pub`, got {}",
+                    describe(other)
+                )));
+            }
+        };
+        Ok(Node::new(AST::Pub(Box::new(item)), span))
+    }
+
+    fn parse_statement(&mut self) -> RxResult<Node> {
+        let span = self.span();
+        let node = match self.peek() {
+            Some(Token::Import) => self.parse_import()?,
+
+            Some(Token::Pub) => self.parse_pub()?,
+
+            Some(Token::Func) if !matches!(self.peek_n(1), Some(Token::LParen)) => {
+                self.parse_func_def()?
+            }
 
             Some(Token::Struct) if matches!(self.peek_n(2), Some(Token::LBrace)) => {
-                self.parse_struct_def()
+                self.parse_struct_def()?
             }
 
-            Some(Token::Return) => self.parse_return(),
+            Some(Token::Return) => self.parse_return()?,
 
             Some(Token::Break) => {
                 self.next();
-                AST::Break
+                Node::new(AST::Break(self.parse_label_ref()), span)
+            }
+
+            Some(Token::Continue) => {
+                self.next();
+                Node::new(AST::Continue(self.parse_label_ref()), span)
             }
 
-            Some(Token::If) => self.parse_if(),
+            Some(Token::If) => self.parse_if()?,
 
             Some(Token::Print) => {
                 self.next();
-                AST::Print(Box::new(self.parse_ternary()))
+                Node::new(AST::Print(Box::new(self.parse_ternary()?)), span)
             }
 
             Some(Token::Println) => {
                 self.next();
-                AST::Println(Box::new(self.parse_ternary()))
+                Node::new(AST::Println(Box::new(self.parse_ternary()?)), span)
             }
 
-            Some(Token::Loop) => {
+            Some(Token::Loop | Token::While | Token::For) => self.parse_loop(None)?,
+
+            Some(Token::LoopLabel(label)) => {
+                let label = label.clone();
                 self.next();
-                AST::Loop(self.parse_block())
+                self.expect(Token::Colon)?;
+                if !matches!(self.peek(), Some(Token::Loop | Token::While | Token::For)) {
+                    return Err(self.error(&format!(
+                        "expected a loop after label `'{label}`, got {}",
+                        describe(self.peek())
+                    )));
+                }
+                self.parse_loop(Some(label))?
             }
 
             Some(Token::Ident(name))
@@ -395,82 +969,199 @@ impl Parser {
                 let name = name.clone();
                 self.next();
                 let op = self.next().cloned().unwrap();
-                let rhs = self.parse_ternary();
-                match op {
+                let rhs = self.parse_ternary()?;
+                let kind = match op {
                     Token::Assign => AST::Assign(name, Box::new(rhs)),
                     Token::ReactiveAssign => AST::ReactiveAssign(name, Box::new(rhs)),
                     Token::ImmutableAssign => AST::ImmutableAssign(name, Box::new(rhs)),
                     _ => unreachable!(),
-                }
+                };
+                Node::new(kind, span)
             }
 
             _ => {
-                let lhs = self.parse_ternary();
-                match self.peek() {
+                let lhs = self.parse_ternary()?;
+                let kind = match self.peek() {
                     Some(Token::Assign) => {
                         self.next();
-                        AST::AssignTarget(Box::new(lhs), Box::new(self.parse_ternary()))
+                        AST::AssignTarget(Box::new(lhs), Box::new(self.parse_ternary()?))
                     }
                     Some(Token::ReactiveAssign) => {
                         self.next();
-                        AST::ReactiveAssignTarget(Box::new(lhs), Box::new(self.parse_ternary()))
+                        AST::ReactiveAssignTarget(Box::new(lhs), Box::new(self.parse_ternary()?))
                     }
                     Some(Token::ImmutableAssign) => {
                         self.next();
-                        AST::ImmutableAssignTarget(Box::new(lhs), Box::new(self.parse_ternary()))
+                        AST::ImmutableAssignTarget(Box::new(lhs), Box::new(self.parse_ternary()?))
                     }
-                    _ => lhs,
-                }
+                    Some(&Token::CompoundAssign(op)) => {
+                        self.next();
+                        AST::CompoundAssign(Box::new(lhs), op, Box::new(self.parse_ternary()?))
+                    }
+                    _ => return Ok(lhs),
+                };
+                Node::new(kind, span)
             }
-        }
+        };
+        Ok(node)
     }
 
-    fn parse_toplevel(&mut self) -> AST {
-        match self.peek() {
-            Some(Token::Import) => {
-                self.next();
-                let mut path = vec![self.expect_ident()];
-                while matches!(self.peek(), Some(Token::Dot)) {
-                    self.next();
-                    path.push(self.expect_ident());
+    fn parse_program(&mut self) -> Node {
+        let span = self.span();
+        let mut items = Vec::new();
+        while self.peek().is_some() {
+            // top-level statements are allowed; scripts run them in order
+            match self.parse_statement() {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    self.report(e);
+                    self.recover_toplevel();
+                    continue;
                 }
-                AST::Import(path)
             }
+            if matches!(self.peek(), Some(Token::Semicolon)) {
+                self.next();
+            }
+        }
+        Node::new(AST::Program(items), span)
+    }
+}
 
-            Some(Token::Func) => self.parse_func_def(),
+/// `[[fill]align][0][width][.precision][kind]`, where align is `<`, `>` or
+/// `^`, precision is a number of digits after the point and kind is one of
+/// `s d x X o b c`.
+fn parse_format_spec(spec: &str) -> Result<FormatSpec, String> {
+    let align_of = |c: char| match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    };
 
-            Some(Token::Struct) if matches!(self.peek_n(2), Some(Token::LBrace)) => {
-                self.parse_struct_def()
-            }
+    let chars: Vec<char> = spec.chars().collect();
+    let (fill, align, mut i) = match chars.as_slice() {
+        [fill, a, ..] if align_of(*a).is_some() => (*fill, align_of(*a), 2),
+        [a, ..] if align_of(*a).is_some() => (' ', align_of(*a), 1),
+        _ => (' ', None, 0),
+    };
 
-            Some(Token::Ident(_)) if matches!(self.peek_n(1), Some(Token::ImmutableAssign)) => {
-                let name = self.expect_ident();
-                self.expect(Token::ImmutableAssign);
-                let rhs = self.parse_ternary();
-                AST::ImmutableAssign(name, Box::new(rhs))
-            }
+    let zero = chars.get(i) == Some(&'0');
+    if zero {
+        i += 1;
+    }
 
-            other => panic!("invalid top-level item: {:?}", other),
+    let number = |i: &mut usize, what: &str| {
+        let mut n = 0usize;
+        while let Some(d) = chars.get(*i).and_then(|c| c.to_digit(10)) {
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(d as usize))
+                .filter(|&n| n <= 1024)
+                .ok_or_else(|| format!("format {what} in `{spec}` is too large"))?;
+            *i += 1;
         }
-    }
+        Ok::<_, String>(n)
+    };
 
-    fn parse_program(&mut self) -> AST {
-        let mut items = Vec::new();
-        while self.peek().is_some() {
-            items.push(self.parse_toplevel());
-            if matches!(self.peek(), Some(Token::Semicolon)) {
-                self.next();
-            }
+    let width = number(&mut i, "width")?;
+    let precision = if chars.get(i) == Some(&'.') {
+        i += 1;
+        if !chars.get(i).is_some_and(char::is_ascii_digit) {
+            return Err(format!("expected digits after `.` in format spec `{spec}`"));
+        }
+        Some(number(&mut i, "precision")?)
+    } else {
+        None
+    };
+
+    let kind = match chars[i..].iter().collect::<String>().as_str() {
+        "" | "s" => FormatKind::Display,
+        "d" => FormatKind::Int {
+            radix: 10,
+            upper: false,
+        },
+        "x" => FormatKind::Int {
+            radix: 16,
+            upper: false,
+        },
+        "X" => FormatKind::Int {
+            radix: 16,
+            upper: true,
+        },
+        "o" => FormatKind::Int {
+            radix: 8,
+            upper: false,
+        },
+        "b" => FormatKind::Int {
+            radix: 2,
+            upper: false,
+        },
+        "c" => FormatKind::Char,
+        _ => {
+            return Err(format!(
+                "invalid format spec `{spec}`; expected [[fill]align][0][width][.precision][s|d|x|X|o|b|c]"
+            ));
         }
-        AST::Program(items)
+    };
+    if precision.is_some() && kind != FormatKind::Display {
+        return Err(format!(
+            "format spec `{spec}` has a precision, which only applies to numbers shown as they print"
+        ));
     }
+
+    Ok(FormatSpec {
+        fill,
+        align,
+        zero,
+        width,
+        precision,
+        kind,
+    })
 }
 
-pub fn parse(tokens: Vec<Token>) -> AST {
+fn describe(tok: Option<&Token>) -> String {
+    match tok {
+        Some(tok) => format!("{:?}", tok),
+        None => "end of input".to_string(),
+    }
+}
+
+/// Parses a whole program, reporting every syntax error found.
+pub fn parse(tokens: Vec<Spanned<Token>>) -> RxResult<Node> {
+    let (ast, errors) = parse_recovering(tokens);
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(RxError::many(errors))
+    }
+}
+
+/// Parses as much of a program as possible. Statements and top-level items
+/// that fail to parse are reported and left out of the returned AST.
+pub fn parse_recovering(tokens: Vec<Spanned<Token>>) -> (Node, Vec<RxError>) {
     let mut parser = Parser::new(tokens);
     let ast = parser.parse_program();
-    if parser.index != parser.tokens.len() {
-        panic!("parser did not consume all tokens");
-    }
-    ast
+    (ast, parser.errors)
+}
+
+/// Whether `token` can start the operand of a `(float)` or `(str)` cast. A
+/// `-` or `[` after `(str)` continues an expression instead, so `(str) -x`
+/// is written `(str)(-x)`.
+fn starts_cast_operand(token: Option<&Token>) -> bool {
+    matches!(
+        token,
+        Some(
+            Token::Number(_)
+                | Token::Float(_)
+                | Token::Ident(_)
+                | Token::Char(_)
+                | Token::StringLiteral(_)
+                | Token::TemplateStart
+                | Token::LParen
+                | Token::Not
+                | Token::Tilde
+                | Token::Func
+                | Token::Struct
+        )
+    )
 }
@@ -0,0 +1,472 @@
+use crate::arity::{collect_bindings, top_level};
+use crate::error::{RxError, RxResult};
+use crate::grammar::{AST, Node};
+use crate::linker::{LinkedModule, Program};
+use crate::span::Span;
+use crate::vm::arith::ARITH_BUILTINS;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// =========================================================
+// Top-level bindings
+// =========================================================
+
+/// The modules of a program, as far as binding names across them goes.
+/// The linker knows them from their source, the VM from running them.
+pub(crate) trait ModuleTable {
+    /// Dotted import path of module `id`; empty for the program itself.
+    fn module_name(&self, id: usize) -> &str;
+
+    /// Whether module `id` defines `name` itself.
+    fn defines(&self, id: usize, name: &str) -> bool;
+
+    /// Whether module `id` defines `name` itself and marked it `pub`.
+    fn exports(&self, id: usize, name: &str) -> bool;
+
+    /// Everything module `id` exports, sorted, so a whole-module import
+    /// binds them in a stable order.
+    fn exported(&self, id: usize) -> Vec<String>;
+}
+
+/// Why a name can't be bound at the top level of a module, or reached
+/// through another module.
+#[derive(Debug, Clone)]
+pub(crate) enum NameError {
+    /// The module defines `name`, which it already imported from `from`.
+    AlreadyImported { name: String, from: usize },
+    /// `name` imported from `from` is defined by the module itself.
+    DefinedHere {
+        name: String,
+        from: usize,
+        here: usize,
+    },
+    /// `name` is imported from two different modules.
+    ImportedTwice {
+        name: String,
+        first: usize,
+        second: usize,
+    },
+    /// Module `id` has no `pub` item (or struct) `name`.
+    Unexported {
+        id: usize,
+        name: String,
+        what: &'static str,
+    },
+}
+
+impl NameError {
+    pub(crate) fn message(&self, modules: &(impl ModuleTable + ?Sized)) -> String {
+        let describe = |id: usize| match modules.module_name(id) {
+            "" => "the main program".to_string(),
+            name => format!("`{name}`"),
+        };
+        match self {
+            NameError::AlreadyImported { name, from } => format!(
+                "`{name}` is already imported from {}; import it with `as` to keep both",
+                describe(*from)
+            ),
+            NameError::DefinedHere { name, from, here } => format!(
+                "import of `{name}` from {} conflicts with `{name}` defined in {}",
+                describe(*from),
+                describe(*here)
+            ),
+            NameError::ImportedTwice {
+                name,
+                first,
+                second,
+            } => format!(
+                "`{name}` is imported from both {} and {}; import one of them with `as`",
+                describe(*first),
+                describe(*second)
+            ),
+            NameError::Unexported { id, name, .. } if modules.defines(*id, name) => format!(
+                "`{name}` is private to module {}; mark it `pub` to use it from outside",
+                describe(*id)
+            ),
+            NameError::Unexported { id, name, what } => {
+                format!("module {} has no {what} `{name}`", describe(*id))
+            }
+        }
+    }
+}
+
+/// What the top level of one module binds, and the module each name comes
+/// from: the module itself for what it defines, another one for what it
+/// imports. The linker, the arity checker and the VM all bind names
+/// through this, so they agree on what a name refers to.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bindings {
+    here: usize,
+    origins: HashMap<String, usize>,
+    namespaces: HashMap<String, usize>,
+}
+
+/// The names one `import` made visible.
+pub(crate) struct Imported {
+    pub(crate) namespace: Option<String>,
+    pub(crate) items: Vec<String>,
+    pub(crate) errors: Vec<NameError>,
+}
+
+impl Bindings {
+    pub(crate) fn new(here: usize) -> Self {
+        Bindings {
+            here,
+            ..Bindings::default()
+        }
+    }
+
+    /// The module `name` comes from, if anything binds it here.
+    pub(crate) fn origin(&self, name: &str) -> Option<usize> {
+        self.origins.get(name).copied()
+    }
+
+    /// The module an imported namespace `ns` stands for.
+    pub(crate) fn namespace(&self, ns: &str) -> Option<usize> {
+        self.namespaces.get(ns).copied()
+    }
+
+    /// A function, struct or constant the module defines itself.
+    /// Redefining something it imported is a name collision.
+    pub(crate) fn define(&mut self, name: &str) -> Result<(), NameError> {
+        match self.origin(name) {
+            Some(from) if from != self.here => Err(NameError::AlreadyImported {
+                name: name.to_string(),
+                from,
+            }),
+            _ => {
+                self.origins.insert(name.to_string(), self.here);
+                Ok(())
+            }
+        }
+    }
+
+    /// `import a.b` binds the namespace `b` plus everything `a.b` exports,
+    /// `import a.b as x` binds only the namespace `x`, and
+    /// `import a.b (f, g)` binds only the listed items.
+    pub(crate) fn import(
+        &mut self,
+        id: usize,
+        alias: Option<&str>,
+        names: Option<&[String]>,
+        modules: &(impl ModuleTable + ?Sized),
+    ) -> Imported {
+        let mut imported = Imported {
+            namespace: None,
+            items: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        let items = match (alias, names) {
+            (_, Some(names)) => names.to_vec(),
+            (Some(alias), None) => {
+                imported.namespace = Some(alias.to_string());
+                Vec::new()
+            }
+            (None, None) => {
+                let ns = modules
+                    .module_name(id)
+                    .rsplit('.')
+                    .next()
+                    .unwrap_or_default();
+                imported.namespace = Some(ns.to_string());
+                modules.exported(id)
+            }
+        };
+
+        if let Some(ns) = &imported.namespace {
+            match self.bind(id, ns) {
+                Ok(()) => {
+                    self.namespaces.insert(ns.clone(), id);
+                }
+                Err(err) => imported.errors.push(err),
+            }
+        }
+        for name in items {
+            if !modules.exports(id, &name) {
+                imported.errors.push(NameError::Unexported {
+                    id,
+                    name,
+                    what: "item",
+                });
+                continue;
+            }
+            match self.bind(id, &name) {
+                Ok(()) => imported.items.push(name),
+                Err(err) => imported.errors.push(err),
+            }
+        }
+        imported
+    }
+
+    /// Makes `name` from module `id` visible here.
+    fn bind(&mut self, id: usize, name: &str) -> Result<(), NameError> {
+        let name = name.to_string();
+        match self.origin(&name) {
+            Some(origin) if origin == id => Ok(()),
+            Some(origin) if origin == self.here => Err(NameError::DefinedHere {
+                name,
+                from: id,
+                here: self.here,
+            }),
+            Some(first) => Err(NameError::ImportedTwice {
+                name,
+                first,
+                second: id,
+            }),
+            None => {
+                self.origins.insert(name, id);
+                Ok(())
+            }
+        }
+    }
+
+    /// If nothing binds `name` here but a module this one imports from
+    /// defines it without exporting it, an error saying so.
+    pub(crate) fn private_item(
+        &self,
+        name: &str,
+        modules: &(impl ModuleTable + ?Sized),
+    ) -> Option<NameError> {
+        if self.origins.contains_key(name) {
+            return None;
+        }
+        self.origins
+            .values()
+            .copied()
+            .filter(|&id| id != self.here)
+            .filter(|&id| modules.defines(id, name) && !modules.exports(id, name))
+            .min()
+            .map(|id| NameError::Unexported {
+                id,
+                name: name.to_string(),
+                what: "item",
+            })
+    }
+}
+
+// =========================================================
+// Checking a linked program
+// =========================================================
+
+/// The functions, structs and constants one module defines itself.
+#[derive(Debug)]
+pub(crate) struct Items {
+    // Dotted import path; empty for the program itself
+    name: String,
+    defined: HashSet<String>,
+    exported: BTreeSet<String>,
+}
+
+impl Items {
+    fn of(module: &LinkedModule) -> Self {
+        let mut items = Items {
+            name: module.name.clone(),
+            defined: HashSet::new(),
+            exported: BTreeSet::new(),
+        };
+        for stmt in top_level(&module.ast) {
+            let (item, public) = match &stmt.kind {
+                AST::Pub(item) => (&**item, true),
+                _ => (stmt, false),
+            };
+            if let Some(name) = item_name(&item.kind) {
+                items.defined.insert(name.clone());
+                if public {
+                    items.exported.insert(name.clone());
+                }
+            }
+        }
+        items
+    }
+}
+
+impl ModuleTable for HashMap<usize, Items> {
+    fn module_name(&self, id: usize) -> &str {
+        &self[&id].name
+    }
+
+    fn defines(&self, id: usize, name: &str) -> bool {
+        self[&id].defined.contains(name)
+    }
+
+    fn exports(&self, id: usize, name: &str) -> bool {
+        self[&id].exported.contains(name)
+    }
+
+    fn exported(&self, id: usize) -> Vec<String> {
+        self[&id].exported.iter().cloned().collect()
+    }
+}
+
+/// The top level of every module of a program, bound before it runs.
+pub(crate) struct ProgramScope {
+    pub(crate) items: HashMap<usize, Items>,
+    pub(crate) bindings: HashMap<usize, Bindings>,
+    pub(crate) errors: Vec<RxError>,
+}
+
+/// Binds the top level of every module of `program` in source order, the
+/// same way the VM does as each module runs. Name collisions and imports
+/// of private items come back as errors.
+pub(crate) fn bind_program(program: &Program) -> ProgramScope {
+    let items: HashMap<usize, Items> = program
+        .modules
+        .iter()
+        .map(|m| (m.id, Items::of(m)))
+        .collect();
+
+    let mut errors = Vec::new();
+    let mut bindings = HashMap::new();
+    for module in &program.modules {
+        let mut scope = Bindings::new(module.id);
+        for stmt in top_level(&module.ast) {
+            let item = match &stmt.kind {
+                AST::Pub(item) => &**item,
+                _ => stmt,
+            };
+
+            if let Some(name) = item_name(&item.kind) {
+                if let Err(err) = scope.define(name) {
+                    errors.push(RxError::compile(err.message(&items), item.span.clone()));
+                }
+                continue;
+            }
+            let AST::Import { path, alias, names } = &item.kind else {
+                continue;
+            };
+            let Some(&dep) = module.imports.get(path) else {
+                continue;
+            };
+            let imported = scope.import(dep, alias.as_deref(), names.as_deref(), &items);
+            for err in imported.errors {
+                errors.push(RxError::compile(err.message(&items), stmt.span.clone()));
+            }
+        }
+        bindings.insert(module.id, scope);
+    }
+
+    ProgramScope {
+        items,
+        bindings,
+        errors,
+    }
+}
+
+/// Reports, before anything runs, two items claiming one name at the top
+/// level of a module, and uses of an item another module keeps private.
+pub fn check_names(program: &Program) -> RxResult<()> {
+    let ProgramScope {
+        items,
+        bindings,
+        mut errors,
+    } = bind_program(program);
+
+    for module in &program.modules {
+        let mut uses = Uses {
+            bindings: &bindings[&module.id],
+            items: &items,
+            shadowed: HashSet::new(),
+            errors: &mut errors,
+        };
+
+        // a name also bound as a variable, parameter or field may be that
+        for stmt in top_level(&module.ast) {
+            collect_bindings(stmt, true, &mut uses.shadowed);
+            collect_fields(stmt, &mut uses.shadowed);
+        }
+        uses.check(&module.ast);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RxError::many(errors))
+    }
+}
+
+/// Checks the names one module uses against what its top level binds.
+struct Uses<'a> {
+    bindings: &'a Bindings,
+    items: &'a HashMap<usize, Items>,
+    shadowed: HashSet<String>,
+    errors: &'a mut Vec<RxError>,
+}
+
+impl Uses<'_> {
+    /// Reports items of other modules reached from here that aren't `pub`:
+    /// `ns.item`, `struct ns.Item`, and plain names that nothing here binds
+    /// but a private item of an imported module would match.
+    fn check(&mut self, node: &Node) {
+        match &node.kind {
+            AST::Var(name) => self.check_unqualified(name, &node.span),
+            AST::Call { name, .. } if !ARITH_BUILTINS.contains(&name.as_str()) => {
+                self.check_unqualified(name, &node.span)
+            }
+            AST::StructNew(name, _) => match name.split_once('.') {
+                Some((ns, item)) => self.check_member(ns, item, "struct", &node.span),
+                None => self.check_unqualified(name, &node.span),
+            },
+            AST::FieldAccess(base, name) | AST::MemberCall { base, name, .. } => {
+                if let AST::Var(ns) = &base.kind {
+                    self.check_member(ns, name, "item", &node.span);
+                }
+            }
+            _ => {}
+        }
+
+        for child in node.children() {
+            self.check(child);
+        }
+    }
+
+    fn check_member(&mut self, ns: &str, name: &str, what: &'static str, span: &Span) {
+        if self.shadowed.contains(ns) {
+            return;
+        }
+        if let Some(id) = self.bindings.namespace(ns)
+            && !self.items.exports(id, name)
+        {
+            let err = NameError::Unexported {
+                id,
+                name: name.to_string(),
+                what,
+            };
+            self.report(err, span);
+        }
+    }
+
+    fn check_unqualified(&mut self, name: &str, span: &Span) {
+        if self.shadowed.contains(name) {
+            return;
+        }
+        if let Some(err) = self.bindings.private_item(name, self.items) {
+            self.report(err, span);
+        }
+    }
+
+    fn report(&mut self, err: NameError, span: &Span) {
+        let msg = err.message(self.items);
+        self.errors.push(RxError::compile(msg, span.clone()));
+    }
+}
+
+/// The name a top-level function, struct or constant binds.
+fn item_name(kind: &AST) -> Option<&String> {
+    match kind {
+        AST::FuncDef { name, .. } | AST::StructDef { name, .. } | AST::ImmutableAssign(name, _) => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Field names of the structs declared in `node`, which their initializers
+/// can refer to.
+fn collect_fields(node: &Node, out: &mut HashSet<String>) {
+    if let AST::StructDef { fields, .. } = &node.kind {
+        out.extend(fields.iter().map(|(name, _)| name.clone()));
+    }
+    for child in node.children() {
+        collect_fields(child, out);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::sync::Arc;
+
+//
+// ----------------------------- SOURCES -----------------------------
+//
+
+/// A named piece of source text. Spans keep a shared handle to it so a
+/// diagnostic can always print the offending line.
+#[derive(Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            text: text.into(),
+        })
+    }
+
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text.lines().nth(line.checked_sub(1)?)
+    }
+}
+
+//
+// ----------------------------- SPANS -----------------------------
+//
+
+/// A location in a source file. `line` and `col` are 1-based, `len` is the
+/// number of characters to underline.
+#[derive(Clone)]
+pub struct Span {
+    pub source: Arc<Source>,
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(source: Arc<Source>, line: usize, col: usize, len: usize) -> Self {
+        Self {
+            source,
+            line,
+            col,
+            len,
+        }
+    }
+
+    /// Renders `label: message` with the source line and a caret under this span:
+    ///
+    /// ```text
+    /// error: expected RParen, got Comma
+    ///  --> project/main.rx:2:12
+    ///   |
+    /// 2 |     print(1, 2);
+    ///   |            ^
+    /// ```
+    pub fn render(&self, label: &str, message: &str) -> String {
+        let gutter = self.line.to_string().len();
+        let pad = " ".repeat(gutter);
+        let mut out = format!("{label}: {message}\n{pad}--> {self}");
+
+        if let Some(text) = self.source.line(self.line) {
+            // keep tabs so the caret lines up with the printed source
+            let lead: String = text
+                .chars()
+                .take(self.col.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let carets = "^".repeat(self.len.max(1));
+
+            out.push_str(&format!("\n{pad} |\n"));
+            out.push_str(&format!("{} | {}\n", self.line, text));
+            out.push_str(&format!("{pad} | {lead}{carets}"));
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source.name, self.line, self.col)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Attaches a span to a token or an instruction.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
@@ -1,125 +1,290 @@
-use crate::grammar::Token;
+use crate::error::{RxError, RxResult};
+use crate::grammar::{Operator, Token};
+use crate::span::{Source, Span, Spanned};
 use std::iter::Peekable;
 use std::str::Chars;
+use std::sync::Arc;
 
-pub fn tokenize(input: &str) -> Vec<Token> {
+struct Cursor<'a> {
+    source: &'a Arc<Source>,
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a Arc<Source>) -> Self {
+        Self {
+            source,
+            chars: source.text.chars().peekable(),
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    /// The character after the next one.
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+
+    fn pos(&self) -> (usize, usize) {
+        (self.line, self.col)
+    }
+
+    /// Span from `start` up to the current position. Multi-line tokens only
+    /// underline their first character.
+    fn span_from(&self, start: (usize, usize)) -> Span {
+        let len = if self.line == start.0 {
+            self.col - start.1
+        } else {
+            1
+        };
+        Span::new(self.source.clone(), start.0, start.1, len)
+    }
+
+    fn error(&self, start: (usize, usize), msg: &str) -> RxError {
+        RxError::compile(msg, self.span_from(start))
+    }
+}
+
+pub fn tokenize(source: &Arc<Source>) -> RxResult<Vec<Spanned<Token>>> {
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut chars = Cursor::new(source);
 
-    while let Some(c) = chars.next() {
-        match c {
-            '0'..='9' => tokens.push(read_number(c, &mut chars)),
-            'a'..='z' | 'A'..='Z' => tokens.push(read_ident(c, &mut chars)),
-            '.' => tokens.push(Token::Dot),
-            ',' => tokens.push(Token::Comma),
-            '?' => tokens.push(Token::Question),
-            '%' => tokens.push(Token::Modulo),
-            '{' => tokens.push(Token::LBrace),
-            '}' => tokens.push(Token::RBrace),
-            '[' => tokens.push(Token::LSquare),
-            ']' => tokens.push(Token::RSquare),
-            ';' => tokens.push(Token::Semicolon),
-            '(' => tokens.push(Token::LParen),
-            ')' => tokens.push(Token::RParen),
-            '+' => tokens.push(Token::Add),
-            '*' => tokens.push(Token::Mul),
-            '/' => tokens.push(Token::Div),
-            '-' => tokens.push(Token::Sub),
-
-            ':' => match chars.peek() {
-                Some(':') => {
-                    chars.next();
-                    match chars.next() {
-                        Some('=') => tokens.push(Token::ReactiveAssign),
-                        _ => panic!("Expected '=' after '::'"),
-                    }
-                }
-                Some('=') => {
-                    chars.next();
-                    tokens.push(Token::ImmutableAssign);
-                }
-                _ => tokens.push(Token::Colon),
-            },
+    // `#!` on the first line makes a script executable; `#` alone opens a comment
+    if source.text.starts_with("#!") {
+        while chars.peek().is_some_and(|c| c != '\n') {
+            chars.next();
+        }
+    }
 
-            '=' => match chars.peek() {
-                Some('=') => {
-                    chars.next();
-                    tokens.push(Token::Equal);
-                }
-                _ => tokens.push(Token::Assign),
-            },
+    loop {
+        let start = chars.pos();
+        let Some(c) = chars.next() else { break };
+        read_token(c, start, &mut chars, &mut tokens)?;
+    }
 
-            '|' => match chars.peek() {
-                Some('|') => {
-                    chars.next();
-                    tokens.push(Token::Or);
-                }
-                _ => panic!("Expected '||'"),
-            },
+    let end = chars.pos();
+    tokens.push(Spanned::new(Token::Eof, chars.span_from(end)));
+    Ok(tokens)
+}
 
-            '&' => match chars.peek() {
-                Some('&') => {
-                    chars.next();
-                    tokens.push(Token::And);
-                }
-                _ => panic!("Expected '&&'"),
-            },
+/// Reads the token that starts with `c` onto `tokens`. Whitespace and
+/// comments add nothing; an interpolated string adds several tokens.
+fn read_token(
+    c: char,
+    start: (usize, usize),
+    chars: &mut Cursor,
+    tokens: &mut Vec<Spanned<Token>>,
+) -> RxResult<()> {
+    let token = match c {
+        '0'..='9' => read_number(c, start, chars)?,
+        'a'..='z' | 'A'..='Z' => read_ident(c, chars),
+        '.' => match chars.peek() {
+            Some('.') => {
+                chars.next();
+                Token::DotDot
+            }
+            _ => Token::Dot,
+        },
+        ',' => Token::Comma,
+        '?' => Token::Question,
+        '{' => Token::LBrace,
+        '}' => Token::RBrace,
+        '[' => Token::LSquare,
+        ']' => Token::RSquare,
+        ';' => Token::Semicolon,
+        '(' => Token::LParen,
+        ')' => Token::RParen,
+        '~' => Token::Tilde,
+        '+' | '-' | '*' | '/' | '%' | '^' => read_arithmetic(c, chars),
 
-            '!' => match chars.peek() {
-                Some('=') => {
-                    chars.next();
-                    tokens.push(Token::NotEqual);
+        ':' => match chars.peek() {
+            Some(':') => {
+                chars.next();
+                match chars.next() {
+                    Some('=') => Token::ReactiveAssign,
+                    _ => return Err(chars.error(start, "expected '=' after '::'")),
                 }
-                _ => tokens.push(Token::Not),
-            },
+            }
+            Some('=') => {
+                chars.next();
+                Token::ImmutableAssign
+            }
+            _ => Token::Colon,
+        },
 
-            '>' => match chars.peek() {
-                Some('=') => {
+        '=' => match chars.peek() {
+            Some('=') => {
+                chars.next();
+                if chars.peek() == Some('=') {
                     chars.next();
-                    tokens.push(Token::GreaterEqual);
+                    Token::Identical
+                } else {
+                    Token::Equal
                 }
-                _ => tokens.push(Token::Greater),
-            },
+            }
+            _ => Token::Assign,
+        },
+
+        '|' => match chars.peek() {
+            Some('|') => {
+                chars.next();
+                Token::Or
+            }
+            _ => compound(Operator::BitOr, Token::BitOr, chars),
+        },
 
-            '<' => match chars.peek() {
-                Some('=') => {
+        '&' => match chars.peek() {
+            Some('&') => {
+                chars.next();
+                Token::And
+            }
+            _ => compound(Operator::BitAnd, Token::BitAnd, chars),
+        },
+
+        '!' => match chars.peek() {
+            Some('=') => {
+                chars.next();
+                if chars.peek() == Some('=') {
                     chars.next();
-                    tokens.push(Token::LessEqual);
+                    Token::NotIdentical
+                } else {
+                    Token::NotEqual
                 }
-                _ => tokens.push(Token::Less),
-            },
+            }
+            _ => Token::Not,
+        },
 
-            '#' => skip_comment(&mut chars),
+        '>' => match chars.peek() {
+            Some('=') => {
+                chars.next();
+                Token::GreaterEqual
+            }
+            Some('>') => {
+                chars.next();
+                compound(Operator::ShiftRight, Token::ShiftRight, chars)
+            }
+            _ => Token::Greater,
+        },
 
-            '\'' => tokens.push(read_char(&mut chars)),
-            '"' => tokens.push(read_string(&mut chars)),
+        '<' => match chars.peek() {
+            Some('=') => {
+                chars.next();
+                Token::LessEqual
+            }
+            Some('<') => {
+                chars.next();
+                compound(Operator::ShiftLeft, Token::ShiftLeft, chars)
+            }
+            _ => Token::Less,
+        },
+
+        '#' => {
+            skip_comment(chars);
+            return Ok(());
+        }
 
-            c if c.is_whitespace() => {}
-            _ => panic!("[tokenizer] invalid char: {c}"),
+        // `'outer` labels a loop; `'a'` is a char
+        '\'' if chars.peek().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.peek_second() != Some('\'') =>
+        {
+            read_label(chars)
         }
+        '\'' => read_char(start, chars)?,
+        '"' => return read_string(start, chars, tokens),
+
+        c if c.is_whitespace() => return Ok(()),
+        _ => return Err(chars.error(start, &format!("invalid char: {c}"))),
+    };
+
+    tokens.push(Spanned::new(token, chars.span_from(start)));
+    Ok(())
+}
+
+/// A decimal literal: an integer, which must fit in an `i32`, or a float
+/// with digits on both sides of its point. The minus sign is a separate
+/// operator, so the smallest integer is written `-2147483647 - 1`.
+fn read_number(first: char, start: (usize, usize), chars: &mut Cursor) -> RxResult<Token> {
+    let mut digits = String::from(first);
+    read_digits(chars, &mut digits);
+
+    // `1..5` is a range, not a float
+    if chars.peek() == Some('.') && chars.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+        chars.next();
+        digits.push('.');
+        read_digits(chars, &mut digits);
+        return digits
+            .parse()
+            .map(Token::Float)
+            .map_err(|_| chars.error(start, &format!("invalid float literal {digits}")));
     }
 
-    tokens
+    digits.parse().map(Token::Number).map_err(|_| {
+        chars.error(
+            start,
+            &format!(
+                "integer literal {digits} is too large (the largest is {})",
+                i32::MAX
+            ),
+        )
+    })
 }
 
-fn read_number(first: char, chars: &mut Peekable<Chars>) -> Token {
-    let mut value = first.to_digit(10).unwrap();
-    while let Some(c) = chars.peek().copied() {
-        if c.is_ascii_digit() {
+fn read_digits(chars: &mut Cursor, digits: &mut String) {
+    while let Some(c) = chars.peek().filter(char::is_ascii_digit) {
+        chars.next();
+        digits.push(c);
+    }
+}
+
+/// `+ - * / % ^` and `**`, or their compound assignments.
+fn read_arithmetic(first: char, chars: &mut Cursor) -> Token {
+    let (op, token) = match first {
+        '+' => (Operator::Addition, Token::Add),
+        '-' => (Operator::Subtraction, Token::Sub),
+        '*' if chars.peek() == Some('*') => {
             chars.next();
-            value = value * 10 + c.to_digit(10).unwrap();
-        } else {
-            break;
+            (Operator::Power, Token::Power)
         }
+        '*' => (Operator::Multiplication, Token::Mul),
+        '/' => (Operator::Division, Token::Div),
+        '%' => (Operator::Modulo, Token::Modulo),
+        _ => (Operator::BitXor, Token::BitXor),
+    };
+    compound(op, token, chars)
+}
+
+/// `token`, or `op=` when an `=` follows.
+fn compound(op: Operator, token: Token, chars: &mut Cursor) -> Token {
+    if chars.peek() == Some('=') {
+        chars.next();
+        Token::CompoundAssign(op)
+    } else {
+        token
     }
-    Token::Number(value as i32)
 }
 
-fn read_ident(first: char, chars: &mut Peekable<Chars>) -> Token {
+fn read_ident(first: char, chars: &mut Cursor) -> Token {
     let mut s = String::new();
     s.push(first);
 
-    while let Some(c) = chars.peek().copied() {
+    while let Some(c) = chars.peek() {
         if c.is_alphanumeric() || c == '_' {
             chars.next();
             s.push(c);
@@ -135,65 +300,187 @@ fn read_ident(first: char, chars: &mut Peekable<Chars>) -> Token {
         "else" => Token::Else,
         "loop" => Token::Loop,
         "break" => Token::Break,
+        "while" => Token::While,
+        "for" => Token::For,
+        "continue" => Token::Continue,
         "func" => Token::Func,
         "return" => Token::Return,
         "struct" => Token::Struct,
         "import" => Token::Import,
+        "pub" => Token::Pub,
         _ => Token::Ident(s),
     }
 }
 
-fn read_char(chars: &mut Peekable<Chars>) -> Token {
+fn read_label(chars: &mut Cursor) -> Token {
+    let mut name = String::new();
+    while let Some(c) = chars.peek() {
+        if c.is_alphanumeric() || c == '_' {
+            chars.next();
+            name.push(c);
+        } else {
+            break;
+        }
+    }
+    Token::LoopLabel(name)
+}
+
+fn read_char(start: (usize, usize), chars: &mut Cursor) -> RxResult<Token> {
     let ch = match chars.next() {
-        Some('\\') => read_escape(chars),
+        Some('\\') => read_escape(start, chars)?,
         Some(c) => c,
-        None => panic!("Unterminated char literal"),
+        None => return Err(chars.error(start, "unterminated char literal")),
     };
 
     match chars.next() {
-        Some('\'') => Token::Char(ch as u32),
-        _ => panic!("Unterminated char literal"),
+        Some('\'') => Ok(Token::Char(ch as u32)),
+        _ => Err(chars.error(start, "unterminated char literal")),
     }
 }
 
-fn read_string(chars: &mut Peekable<Chars>) -> Token {
+/// A plain string is a single `StringLiteral`. One with `{expr}` holes
+/// becomes `TemplateStart`, its text pieces and holes, then `TemplateEnd`.
+/// A hole is `InterpStart`, the expression's tokens, an optional
+/// `FormatSpec` and `InterpEnd`. `{{` and `}}` stand for literal braces.
+fn read_string(
+    start: (usize, usize),
+    chars: &mut Cursor,
+    tokens: &mut Vec<Spanned<Token>>,
+) -> RxResult<()> {
+    let mut parts = Vec::new();
     let mut s = String::new();
-    while let Some(c) = chars.next() {
+    let mut piece_start = start;
+    loop {
+        let pos = chars.pos();
+        match chars.next() {
+            Some('"') => break,
+            Some('\\') => s.push(read_escape(start, chars)?),
+            Some('{') if chars.peek() == Some('{') => {
+                chars.next();
+                s.push('{');
+            }
+            Some('}') if chars.peek() == Some('}') => {
+                chars.next();
+                s.push('}');
+            }
+            Some('}') => {
+                return Err(chars.error(pos, "unmatched `}` in string; write `}}` for a brace"));
+            }
+            Some('{') => {
+                let text = std::mem::take(&mut s);
+                parts.push(Spanned::new(
+                    Token::StringLiteral(text),
+                    chars.span_from(piece_start),
+                ));
+                parts.push(Spanned::new(Token::InterpStart, chars.span_from(pos)));
+                read_interpolation(pos, chars, &mut parts)?;
+                piece_start = chars.pos();
+            }
+            Some(c) => s.push(c),
+            None => return Err(chars.error(start, "unterminated string literal")),
+        }
+    }
+
+    if parts.is_empty() {
+        tokens.push(Spanned::new(
+            Token::StringLiteral(s),
+            chars.span_from(start),
+        ));
+        return Ok(());
+    }
+
+    parts.push(Spanned::new(
+        Token::StringLiteral(s),
+        chars.span_from(piece_start),
+    ));
+    parts.push(Spanned::new(
+        Token::TemplateEnd,
+        chars.span_from(piece_start),
+    ));
+    tokens.push(Spanned::new(Token::TemplateStart, chars.span_from(start)));
+    tokens.extend(parts);
+    Ok(())
+}
+
+/// Reads the tokens of a `{expr}` or `{expr:spec}` hole up to its closing
+/// `}`. A `:` outside brackets starts the format spec, unless it belongs
+/// to a `? :` in the expression. A `"` ends the string, so a hole can't
+/// hold a string literal.
+fn read_interpolation(
+    start: (usize, usize),
+    chars: &mut Cursor,
+    tokens: &mut Vec<Spanned<Token>>,
+) -> RxResult<()> {
+    let unclosed = "unclosed `{` in string; write `{{` for a brace";
+    let mut depth = 0usize;
+    let mut ternaries = 0usize;
+    loop {
+        let pos = chars.pos();
+        let Some(c) = chars.next() else {
+            return Err(chars.error(start, unclosed));
+        };
+
         match c {
-            '"' => break,
-            '\\' => s.push(read_escape(chars)),
-            c => s.push(c),
+            '"' => return Err(chars.error(start, unclosed)),
+            '}' if depth == 0 => break,
+            ':' if depth == 0 && ternaries == 0 && !matches!(chars.peek(), Some(':' | '=')) => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some('"') | None => return Err(chars.error(start, unclosed)),
+                        Some(c) => spec.push(c),
+                    }
+                }
+                tokens.push(Spanned::new(Token::FormatSpec(spec), chars.span_from(pos)));
+                break;
+            }
+            _ => {}
+        }
+
+        let before = tokens.len();
+        read_token(c, pos, chars, tokens)?;
+        for tok in &tokens[before..] {
+            match tok.node {
+                Token::LParen | Token::LSquare | Token::LBrace => depth += 1,
+                Token::RParen | Token::RSquare | Token::RBrace => depth = depth.saturating_sub(1),
+                Token::Question if depth == 0 => ternaries += 1,
+                Token::Colon if depth == 0 => ternaries = ternaries.saturating_sub(1),
+                _ => {}
+            }
         }
     }
-    Token::StringLiteral(s)
+
+    tokens.push(Spanned::new(Token::InterpEnd, chars.span_from(chars.pos())));
+    Ok(())
 }
 
-fn read_escape(chars: &mut Peekable<Chars>) -> char {
+fn read_escape(start: (usize, usize), chars: &mut Cursor) -> RxResult<char> {
     match chars.next() {
-        Some('n') => '\n',
-        Some('t') => '\t',
-        Some('r') => '\r',
-        Some('"') => '"',
-        Some('\'') => '\'',
-        Some('\\') => '\\',
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('"') => Ok('"'),
+        Some('\'') => Ok('\''),
+        Some('\\') => Ok('\\'),
         Some(c @ '0'..='7') => {
             let mut value = (c as u32) - ('0' as u32);
             for _ in 0..2 {
-                if let Some(d @ '0'..='7') = chars.peek().copied() {
+                if let Some(d @ '0'..='7') = chars.peek() {
                     chars.next();
                     value = value * 8 + (d as u32 - '0' as u32);
                 } else {
                     break;
                 }
             }
-            char::from_u32(value).expect("Invalid octal escape")
+            char::from_u32(value).ok_or_else(|| chars.error(start, "invalid octal escape"))
         }
-        Some(c) => panic!("Invalid escape sequence: \\{c}"),
-        None => panic!("Unterminated escape sequence"),
+        Some(c) => Err(chars.error(start, &format!("invalid escape sequence: \\{c}"))),
+        None => Err(chars.error(start, "unterminated escape sequence")),
     }
 }
 
-fn skip_comment(chars: &mut Peekable<Chars>) {
+fn skip_comment(chars: &mut Cursor) {
     while let Some(c) = chars.next() {
         if c == '#' {
             break;
@@ -0,0 +1,167 @@
+use super::display::format_float;
+use super::{NamedArgs, VM};
+use crate::arity::arity_message;
+use crate::error::RxResult;
+use crate::grammar::{Operator, Type, UnaryOperator};
+
+/// The functions every module can call without importing anything, unless
+/// it defines its own of the same name.
+pub(crate) const ARITH_BUILTINS: [&str; 7] = [
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "saturating_add",
+    "saturating_sub",
+    "saturating_mul",
+    "float_sqrt",
+];
+
+impl VM {
+    // =========================================================
+    // Integer arithmetic
+    // =========================================================
+
+    /// `a op b` for the arithmetic, bitwise and shift operators. Overflow
+    /// and division by zero are runtime errors. With a float on either
+    /// side, both operands are floats and so is the result.
+    pub(crate) fn arith(&mut self, op: Operator, a: Type, b: Type) -> RxResult<Type> {
+        let a = self.force(a)?;
+        let b = self.force(b)?;
+        if matches!(a, Type::Float(_)) || matches!(b, Type::Float(_)) {
+            let x = self.as_float(a)?;
+            let y = self.as_float(b)?;
+            return self.float_arith(op, x, y).map(Type::Float);
+        }
+        let a = self.as_int(a)?;
+        let b = self.as_int(b)?;
+
+        let n = match op {
+            Operator::Addition => a.checked_add(b),
+            Operator::Subtraction => a.checked_sub(b),
+            Operator::Multiplication => a.checked_mul(b),
+            Operator::Division | Operator::Modulo if b == 0 => {
+                return Err(self.error(format!("division by zero in `{a} {} 0`", symbol(op))));
+            }
+            Operator::Division => a.checked_div(b),
+            Operator::Modulo => a.checked_rem(b),
+            Operator::BitAnd => Some(a & b),
+            Operator::BitOr => Some(a | b),
+            Operator::BitXor => Some(a ^ b),
+            Operator::ShiftLeft | Operator::ShiftRight => {
+                let Ok(shift @ 0..32) = u32::try_from(b) else {
+                    return Err(self.error(format!("shift amount {b} is not in 0..32")));
+                };
+                // `>>` keeps the sign
+                if matches!(op, Operator::ShiftLeft) {
+                    Some(a << shift)
+                } else {
+                    Some(a >> shift)
+                }
+            }
+            Operator::Power => {
+                let Ok(exp) = u32::try_from(b) else {
+                    return Err(self.error(format!("negative exponent {b} in `**`")));
+                };
+                a.checked_pow(exp)
+            }
+            other => unreachable!("{other:?} is not an arithmetic operator"),
+        };
+
+        n.map(Type::Integer)
+            .ok_or_else(|| self.error(format!("integer overflow in `{a} {} {b}`", symbol(op))))
+    }
+
+    /// `-a` or `~a`. Negating a float flips its sign, so `-0.0` stays
+    /// distinct from `0.0`; negating the smallest integer overflows.
+    pub(crate) fn unary(&mut self, op: UnaryOperator, a: Type) -> RxResult<Type> {
+        let a = self.force(a)?;
+        if let Type::Float(f) = a {
+            return match op {
+                UnaryOperator::Negate => Ok(Type::Float(-f)),
+                UnaryOperator::BitNot => Err(self.error("`~` needs integers, not floats")),
+            };
+        }
+        let a = self.as_int(a)?;
+        match op {
+            UnaryOperator::Negate => a
+                .checked_neg()
+                .map(Type::Integer)
+                .ok_or_else(|| self.error(format!("integer overflow in `-({a})`"))),
+            UnaryOperator::BitNot => Ok(Type::Integer(!a)),
+        }
+    }
+
+    /// Float arithmetic follows IEEE 754, so it can give `inf` or `NaN`,
+    /// except that dividing by zero is still an error.
+    fn float_arith(&mut self, op: Operator, a: f64, b: f64) -> RxResult<f64> {
+        match op {
+            Operator::Addition => Ok(a + b),
+            Operator::Subtraction => Ok(a - b),
+            Operator::Multiplication => Ok(a * b),
+            Operator::Division | Operator::Modulo if b == 0.0 => Err(self.error(format!(
+                "division by zero in `{} {} {}`",
+                format_float(a),
+                symbol(op),
+                format_float(b)
+            ))),
+            Operator::Division => Ok(a / b),
+            Operator::Modulo => Ok(a % b),
+            Operator::Power => Ok(a.powf(b)),
+            _ => Err(self.error(format!("`{}` needs integers, not floats", symbol(op)))),
+        }
+    }
+
+    /// Calls one of `ARITH_BUILTINS`: `wrapping_*` wraps around on overflow,
+    /// `saturating_*` stops at the smallest or largest integer, and
+    /// `float_sqrt` is the IEEE 754 square root.
+    pub(crate) fn call_arith_builtin(
+        &mut self,
+        name: &str,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        if !named.is_empty() {
+            return Err(self.error(format!("`{name}` takes no named arguments")));
+        }
+        if name == "float_sqrt" {
+            let [x] = <[Type; 1]>::try_from(args)
+                .map_err(|args| self.error(arity_message(Some(name), 1, 1, args.len())))?;
+            let x = self.as_float(x)?;
+            return Ok(Type::Float(x.sqrt()));
+        }
+
+        let [a, b] = <[Type; 2]>::try_from(args)
+            .map_err(|args| self.error(arity_message(Some(name), 2, 2, args.len())))?;
+        let a = self.force(a)?;
+        let a = self.as_int(a)?;
+        let b = self.force(b)?;
+        let b = self.as_int(b)?;
+
+        Ok(Type::Integer(match name {
+            "wrapping_add" => a.wrapping_add(b),
+            "wrapping_sub" => a.wrapping_sub(b),
+            "wrapping_mul" => a.wrapping_mul(b),
+            "saturating_add" => a.saturating_add(b),
+            "saturating_sub" => a.saturating_sub(b),
+            _ => a.saturating_mul(b),
+        }))
+    }
+}
+
+/// How `op` is written, for error messages.
+fn symbol(op: Operator) -> &'static str {
+    match op {
+        Operator::Addition => "+",
+        Operator::Subtraction => "-",
+        Operator::Multiplication => "*",
+        Operator::Division => "/",
+        Operator::Modulo => "%",
+        Operator::Power => "**",
+        Operator::BitAnd => "&",
+        Operator::BitOr => "|",
+        Operator::BitXor => "^",
+        Operator::ShiftLeft => "<<",
+        Operator::ShiftRight => ">>",
+        other => unreachable!("{other:?} is not an arithmetic operator"),
+    }
+}
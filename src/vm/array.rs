@@ -0,0 +1,120 @@
+use super::{NamedArgs, VM};
+use crate::arity::arity_message;
+use crate::error::RxResult;
+use crate::grammar::Type;
+
+impl VM {
+    // =========================================================
+    // Array methods
+    // =========================================================
+
+    /// `arr.name(args)`: the built-in operations on arrays. Elements keep
+    /// their immutability and reactive relations as they move around. A
+    /// slice only has `len`, since its length is fixed. Strings also have
+    /// the string methods.
+    pub(crate) fn call_array_method(
+        &mut self,
+        arr: Type,
+        name: &str,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        let expected = match name {
+            "len" | "pop" => 0,
+            "push" | "remove" | "resize" => 1,
+            "insert" => 2,
+            "substring" | "find" | "replace" | "split" | "join" | "trim" | "upper" | "lower"
+            | "compare" | "parse" => return self.call_string_method(arr, name, args, named),
+            _ => return Err(self.error(format!("arrays have no method `{name}`"))),
+        };
+        if !named.is_empty() {
+            return Err(self.error(format!("`{name}` takes no named arguments")));
+        }
+        if args.len() != expected {
+            return Err(self.error(arity_message(Some(name), expected, expected, args.len())));
+        }
+
+        let id = match arr {
+            Type::ArrayRef(id) => id,
+            slice => {
+                return match self.array_view(&slice) {
+                    Some((_, _, len)) if name == "len" => Ok(Type::Integer(len as i32)),
+                    _ => Err(self.error(format!(
+                        "cannot `{name}` a slice: only its array can change length"
+                    ))),
+                };
+            }
+        };
+
+        let mut args = args.into_iter();
+        let mut arg = || args.next().unwrap_or(Type::Integer(0));
+        match name {
+            "len" => Ok(self.array_len(id)),
+            "push" => {
+                let value = self.force_to_storable(arg())?;
+                self.array_heap[id].push(value);
+                Ok(self.array_len(id))
+            }
+            "pop" => {
+                let len = self.array_heap[id].len();
+                if len == 0 {
+                    return Err(self.error("pop from an empty array"));
+                }
+                self.array_take(id, len - 1)
+            }
+            "insert" => {
+                let index = self.as_usize_nonneg(arg(), "array index")?;
+                let value = self.force_to_storable(arg())?;
+                let len = self.array_heap[id].len();
+                if index > len {
+                    return Err(self.error(format!(
+                        "insert index out of bounds: index {index}, length {len}"
+                    )));
+                }
+                self.array_heap[id].insert(index, value);
+                self.array_immutables[id] = self.array_immutables[id]
+                    .iter()
+                    .map(|&i| if i >= index { i + 1 } else { i })
+                    .collect();
+                Ok(self.array_len(id))
+            }
+            "remove" => {
+                let index = self.as_usize_nonneg(arg(), "array index")?;
+                let len = self.array_heap[id].len();
+                if index >= len {
+                    return Err(self.error(format!(
+                        "array index out of bounds: index {index}, length {len}"
+                    )));
+                }
+                self.array_take(id, index)
+            }
+            _ => {
+                let size = self.as_usize_nonneg(arg(), "array size")?;
+                self.array_heap[id].resize(size, Type::Integer(0));
+                self.array_immutables[id].retain(|&i| i < size);
+                Ok(self.array_len(id))
+            }
+        }
+    }
+
+    fn array_len(&self, id: usize) -> Type {
+        Type::Integer(self.array_heap[id].len() as i32)
+    }
+
+    /// Removes element `index` and returns its current value. Later
+    /// elements, and their immutability, shift down by one.
+    fn array_take(&mut self, id: usize, index: usize) -> RxResult<Type> {
+        // force while the element is still in place, so a relation on it
+        // sees the array as it was
+        let value = self.array_heap[id][index].clone();
+        let value = self.force(value)?;
+
+        self.array_heap[id].remove(index);
+        self.array_immutables[id] = self.array_immutables[id]
+            .iter()
+            .filter(|&&i| i != index)
+            .map(|&i| if i > index { i - 1 } else { i })
+            .collect();
+        Ok(value)
+    }
+}
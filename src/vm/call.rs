@@ -1,123 +1,360 @@
-use super::VM;
-use crate::grammar::{Instruction, Type};
+use super::arith::ARITH_BUILTINS;
+use super::{NamedArgs, VM};
+use crate::arity::{ArgSource, bind_args};
+use crate::error::RxResult;
+use crate::grammar::{CallArgs, Instruction, Node, Param, Type};
+use crate::span::Spanned;
 use std::collections::HashMap;
 
 impl VM {
     // =========================================================
     // Instruction entry point
     // =========================================================
-    pub(crate) fn exec_call(&mut self, name: String, argc: usize) {
-        let args = self.pop_args(argc);
-
-        let f = self.global_env.get(&name).cloned().unwrap_or_else(|| {
-            panic!(
-                "call error: `{}` is not defined (attempted to call with {} argument(s))",
-                name, argc
-            )
-        });
-
-        let ret = match f {
-            Type::Function { .. } => self.call_function(f, args),
-            other => panic!(
-                "call error: `{}` is not a function (found {:?})",
-                name, other
-            ),
+    pub(crate) fn exec_call(&mut self, name: String, args: CallArgs) -> RxResult<()> {
+        let argc = args.count;
+        let (args, named) = self.pop_args(&args)?;
+
+        let f = match self.lookup_var(&name).cloned() {
+            Some(f) => f,
+            None if ARITH_BUILTINS.contains(&name.as_str()) => {
+                let ret = self.call_arith_builtin(&name, args, named)?;
+                self.stack.push(ret);
+                return Ok(());
+            }
+            None => {
+                return Err(self.private_item(&name).unwrap_or_else(|| {
+                    self.error(format!(
+                        "call error: `{}` is not defined (attempted to call with {} argument(s))",
+                        name, argc
+                    ))
+                }));
+            }
+        };
+
+        let ret = match self.force(f)? {
+            f @ Type::Function { .. } => self.call_function(f, args, named)?,
+            other => {
+                return Err(self.error(format!(
+                    "call error: `{}` is not a function (found {})",
+                    name,
+                    self.type_name(&other)
+                )));
+            }
         };
 
         self.stack.push(ret);
+        Ok(())
+    }
+
+    /// `callee(args)` where the callee is any expression, e.g. `arr[i](x)`.
+    pub(crate) fn exec_call_value(&mut self, args: CallArgs) -> RxResult<()> {
+        let (args, named) = self.pop_args(&args)?;
+        let f = self.pop()?;
+        let ret = self.call_value(f, args, named)?;
+        self.stack.push(ret);
+        Ok(())
+    }
+
+    /// Calls a function value.
+    pub(crate) fn call_value(
+        &mut self,
+        f: Type,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        match self.force(f)? {
+            f @ Type::Function { .. } => self.call_function(f, args, named),
+            other => Err(self.error(format!(
+                "call error: value is not a function (found {})",
+                self.type_name(&other)
+            ))),
+        }
+    }
+
+    /// `base.name(args)`: calls a function exported by an imported module or
+    /// stored in a struct field, or one of the built-in array methods.
+    pub(crate) fn exec_call_member(&mut self, name: String, args: CallArgs) -> RxResult<()> {
+        let (args, named) = self.pop_args(&args)?;
+        let base = self.pop()?;
+        let ret = self.call_member(base, &name, args, named)?;
+        self.stack.push(ret);
+        Ok(())
+    }
+
+    pub(crate) fn call_member(
+        &mut self,
+        base: Type,
+        name: &str,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        match self.force(base)? {
+            Type::Module(id) => {
+                let f = self.module_member(id, name)?;
+                if !matches!(f, Type::Function { .. }) {
+                    return Err(self.error(format!("call error: `{name}` is not a function")));
+                }
+                self.call_function(f, args, named)
+            }
+            arr @ (Type::ArrayRef(_) | Type::ArraySlice { .. }) => {
+                self.call_array_method(arr, name, args, named)
+            }
+            // a struct field holding a function
+            Type::StructRef(id) => {
+                let field = self
+                    .heap
+                    .get(id)
+                    .and_then(|s| s.fields.get(name))
+                    .cloned()
+                    .ok_or_else(|| self.error(format!("missing struct field `{name}`")))?;
+                let f = self.force_struct_field(id, field)?;
+                self.call_value(f, args, named)
+            }
+            other => Err(self.error(format!(
+                "call error: cannot call `.{name}(...)` on {}",
+                self.type_name(&other)
+            ))),
+        }
+    }
+
+    /// Calls `main`, passing the program arguments as an array of strings
+    /// when it takes a parameter. Its result becomes the exit status.
+    pub(crate) fn exec_call_main(&mut self) -> RxResult<()> {
+        let f = self
+            .module()
+            .globals
+            .get("main")
+            .cloned()
+            .ok_or_else(|| self.error("no `main` function defined"))?;
+
+        let args = match &f {
+            Type::Function { params, .. } if params.is_empty() => Vec::new(),
+            Type::Function { params, .. } if params.len() == 1 => {
+                let args = self.args.clone();
+                let strings = args.iter().map(|a| self.alloc_string(a)).collect();
+                vec![self.alloc_array(strings)]
+            }
+            Type::Function { params, .. } => {
+                return Err(self.error(format!(
+                    "`main` must take no parameters or a single `args` array (found {})",
+                    params.len()
+                )));
+            }
+            other => {
+                return Err(self.error(format!(
+                    "`main` is not a function (found {})",
+                    self.type_name(other)
+                )));
+            }
+        };
+
+        let ret = self.call_function(f, args, Vec::new())?;
+        self.set_exit_status(ret, "`main`")
+    }
+
+    /// The value `main` or a script returned becomes the exit status, and
+    /// must be an integer.
+    pub(crate) fn set_exit_status(&mut self, v: Type, what: &str) -> RxResult<()> {
+        match self.force(v)? {
+            Type::Integer(n) => {
+                self.exit_status = n;
+                Ok(())
+            }
+            other => Err(self.error(format!(
+                "{what} must give an integer exit status (found {})",
+                self.type_name(&other)
+            ))),
+        }
+    }
+
+    // =========================================================
+    // Function values
+    // =========================================================
+
+    /// A top-level `func` becomes a module global. Inside a function body
+    /// it is a closure bound as a local, like an anonymous `func`.
+    pub(crate) fn exec_store_function(
+        &mut self,
+        name: String,
+        params: Vec<Param>,
+        body: Vec<Node>,
+    ) -> RxResult<()> {
+        if self.local_env.is_some() {
+            let f = self.make_closure(params, body, Some(name.clone()));
+            if let Some(locals) = self.local_env.as_mut() {
+                locals.insert(name, f);
+            }
+            return Ok(());
+        }
+
+        self.define(&name)?;
+        let module = self.current_module;
+        self.module_mut().globals.insert(
+            name.clone(),
+            Type::Function {
+                params,
+                body,
+                module,
+                env: HashMap::new(),
+                name: Some(name),
+            },
+        );
+        Ok(())
+    }
+
+    /// A function capturing the locals and immutables visible right now.
+    pub(crate) fn make_closure(
+        &self,
+        params: Vec<Param>,
+        body: Vec<Node>,
+        name: Option<String>,
+    ) -> Type {
+        Type::Function {
+            params,
+            body,
+            module: self.current_module,
+            env: self.capture_env(),
+            name,
+        }
     }
 
     // =========================================================
     // Function execution
     // =========================================================
-    pub(crate) fn call_function(&mut self, f: Type, args: Vec<Type>) -> Type {
+    pub(crate) fn call_function(
+        &mut self,
+        f: Type,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        // a named function can call itself by name, even as a closure
+        let this = matches!(&f, Type::Function { name: Some(_), .. }).then(|| f.clone());
         match f {
-            Type::Function { params, body } => {
+            Type::Function {
+                params,
+                body,
+                module,
+                env,
+                name,
+            } => {
+                let names: Vec<String> = named.iter().map(|(n, _)| n.clone()).collect();
+                let sources = bind_args(name.as_deref(), &params, args.len(), &names)
+                    .map_err(|msg| self.error(msg))?;
+
+                // Compile function body; the implicit `return 0` reports at the call site
+                let call_span = self.current_span();
+                let mut code = Vec::new();
+                let mut lg = crate::compiler::LabelGenerator::new();
+                let mut break_stack = Vec::new();
+
+                for stmt in body {
+                    crate::compiler::compile(stmt, &mut code, &mut lg, &mut break_stack)?;
+                }
+                if let Some(span) = call_span.or_else(|| code.last().map(|i| i.span.clone())) {
+                    code.push(Spanned::new(Instruction::Push(0), span.clone()));
+                    code.push(Spanned::new(Instruction::Return, span));
+                }
+
                 // Save VM state
                 let saved_local = self.local_env.take();
                 let saved_immutables = self.immutable_stack.clone();
+                let saved_module = std::mem::replace(&mut self.current_module, module);
 
                 // Start function with:
-                // - existing immutable stack (global immutables preserved)
+                // - existing immutable stack (global immutables preserved), or
+                //   just a root frame when calling into another module, whose
+                //   constants are found through its namespace instead
+                // - plus the environment the function captured, if any
                 // - plus a fresh param frame
+                if module != saved_module {
+                    self.immutable_stack = vec![HashMap::new()];
+                }
+                self.immutable_stack.push(env);
                 self.immutable_stack.push(HashMap::new());
-                self.local_env = Some(HashMap::new());
 
-                // Bind parameters as immutables
+                if let (Some(name), Some(this), Some(scope)) =
+                    (name, this, self.immutable_stack.last_mut())
                 {
-                    let scope = self.immutable_stack.last_mut().unwrap();
-                    for (p, v) in params.into_iter().zip(args) {
-                        scope.insert(p, v);
-                    }
-                }
-
-                // Compile function body
-                let mut code = Vec::new();
-                let mut lg = crate::compiler::LabelGenerator::new();
-                let mut break_stack = Vec::new();
-
-                for stmt in body {
-                    crate::compiler::compile(stmt, &mut code, &mut lg, &mut break_stack);
+                    scope.insert(name, this);
                 }
-                code.push(Instruction::Return);
-
-                // Swap execution context
-                let saved_code = std::mem::replace(&mut self.code, code);
-                let saved_labels =
-                    std::mem::replace(&mut self.labels, Self::build_labels(&self.code));
-                let saved_ptr = self.pointer;
-                let saved_stack_len = self.stack.len();
 
-                self.pointer = 0;
-                self.run();
-
-                // Retrieve return value
-                let ret = if self.stack.len() > saved_stack_len {
-                    self.pop()
-                } else {
-                    Type::Integer(0)
-                };
+                let result = self.nested(|vm| {
+                    vm.bind_params(params, sources, args, named)?;
+                    vm.local_env = Some(HashMap::new());
+                    vm.run_function_code(code)
+                });
 
                 // Restore VM state
-                self.code = saved_code;
-                self.labels = saved_labels;
-                self.pointer = saved_ptr;
                 self.immutable_stack = saved_immutables;
                 self.local_env = saved_local;
+                self.current_module = saved_module;
 
-                ret
+                result
             }
-            _ => panic!("attempted to call non-function"),
+            _ => Err(self.error("attempted to call non-function")),
         }
     }
 
-    // =========================================================
-    // Module imports
-    // =========================================================
-    pub(crate) fn import_module(&mut self, path: Vec<String>) {
-        let file_path = format!("project/{}.rx", path.join("/"));
-
-        let source = std::fs::read_to_string(&file_path)
-            .unwrap_or_else(|_| panic!("could not import module `{}`", file_path));
-
-        let tokens = crate::tokenizer::tokenize(&source);
-        let ast = crate::parser::parse(tokens);
+    /// Binds parameters as immutables in the innermost frame. A default is
+    /// evaluated only when its argument is left out, and sees the
+    /// parameters before it.
+    fn bind_params(
+        &mut self,
+        params: Vec<Param>,
+        sources: Vec<ArgSource>,
+        mut args: Vec<Type>,
+        mut named: NamedArgs,
+    ) -> RxResult<()> {
+        for (param, source) in params.into_iter().zip(sources) {
+            let value = match (source, param.default) {
+                (ArgSource::Positional(i), _) => {
+                    std::mem::replace(&mut args[i], Type::Uninitialized)
+                }
+                (ArgSource::Named(i), _) => std::mem::replace(&mut named[i].1, Type::Uninitialized),
+                (ArgSource::Default, Some(default)) => self.eval_default(default)?,
+                (ArgSource::Default, None) => Type::Uninitialized,
+            };
+            if let Some(scope) = self.immutable_stack.last_mut() {
+                scope.insert(param.name, value);
+            }
+        }
+        Ok(())
+    }
 
+    /// Compiles a parameter default and runs it in the callee's scope, so
+    /// it can be any expression a function body could contain.
+    fn eval_default(&mut self, default: Node) -> RxResult<Type> {
         let mut code = Vec::new();
         let mut lg = crate::compiler::LabelGenerator::new();
-        let mut break_stack = Vec::new();
-
-        crate::compiler::compile_module(ast, &mut code, &mut lg, &mut break_stack);
+        crate::compiler::compile(default, &mut code, &mut lg, &mut Vec::new())?;
+        self.run_function_code(code)
+    }
 
+    /// Runs a compiled function body in place of the current code and
+    /// returns the value on top of the stack when it stops.
+    fn run_function_code(&mut self, code: Vec<Spanned<Instruction>>) -> RxResult<Type> {
+        // Swap execution context
         let saved_code = std::mem::replace(&mut self.code, code);
         let saved_labels = std::mem::replace(&mut self.labels, Self::build_labels(&self.code));
         let saved_ptr = self.pointer;
+        let saved_stack_len = self.stack.len();
 
         self.pointer = 0;
-        self.run();
+        let result = self.run();
+
+        // Retrieve return value, dropping whatever expression statements left
+        let ret = if result.is_ok() && self.stack.len() > saved_stack_len {
+            self.pop()
+        } else {
+            Ok(Type::Integer(0))
+        };
+        self.stack.truncate(saved_stack_len);
 
         self.code = saved_code;
         self.labels = saved_labels;
         self.pointer = saved_ptr;
+
+        result?;
+        ret
     }
 }
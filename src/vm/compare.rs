@@ -0,0 +1,149 @@
+use super::VM;
+use crate::error::RxResult;
+use crate::grammar::{Operator, Type};
+use std::cmp::Ordering;
+
+/// An array view or struct instance already being compared, so values
+/// that contain themselves don't recurse forever.
+#[derive(PartialEq)]
+enum Slot {
+    Array(usize, usize, usize),
+    Struct(usize),
+}
+
+impl VM {
+    // =========================================================
+    // Comparison
+    // =========================================================
+
+    /// `a op b` for the comparison and identity operators, as `1` or `0`.
+    pub(crate) fn compare(&mut self, op: Operator, a: Type, b: Type) -> RxResult<Type> {
+        let result = match op {
+            Operator::Equal => self.values_equal(a, b, &mut Vec::new())?,
+            Operator::NotEqual => !self.values_equal(a, b, &mut Vec::new())?,
+            Operator::Identical => self.identical(a, b)?,
+            Operator::NotIdentical => !self.identical(a, b)?,
+            // `None` when a NaN is involved, for which every ordering is false
+            _ => {
+                let ord = self.order(a, b)?;
+                match op {
+                    Operator::Greater => ord == Some(Ordering::Greater),
+                    Operator::Less => ord == Some(Ordering::Less),
+                    Operator::GreaterEqual => {
+                        matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                    Operator::LessEqual => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    _ => return Err(self.error(format!("{:?} is not a comparison", op))),
+                }
+            }
+        };
+        Ok(Type::Integer(result as i32))
+    }
+
+    /// `==`: arrays and strings are equal when their elements are, structs
+    /// when they are the same struct with equal fields. An array compared
+    /// with a number stands for its length, as everywhere else.
+    fn values_equal(&mut self, a: Type, b: Type, open: &mut Vec<(Slot, Slot)>) -> RxResult<bool> {
+        let a = self.force(a)?;
+        let b = self.force(b)?;
+
+        if let (Some(va), Some(vb)) = (self.array_view(&a), self.array_view(&b)) {
+            if va.2 != vb.2 {
+                return Ok(false);
+            }
+            let slots = (Slot::Array(va.0, va.1, va.2), Slot::Array(vb.0, vb.1, vb.2));
+            if va == vb || open.contains(&slots) {
+                return Ok(true);
+            }
+
+            open.push(slots);
+            let pairs: Vec<_> = self
+                .array_elems(&a)
+                .into_iter()
+                .zip(self.array_elems(&b))
+                .collect();
+            let mut equal = true;
+            for (x, y) in pairs {
+                if !self.values_equal(x, y, open)? {
+                    equal = false;
+                    break;
+                }
+            }
+            open.pop();
+            return Ok(equal);
+        }
+
+        match (a, b) {
+            (Type::StructRef(x), Type::StructRef(y)) => {
+                if x == y || open.contains(&(Slot::Struct(x), Slot::Struct(y))) {
+                    return Ok(true);
+                }
+                if self.heap[x].name != self.heap[y].name {
+                    return Ok(false);
+                }
+
+                open.push((Slot::Struct(x), Slot::Struct(y)));
+                let mut equal = true;
+                for field in self.heap[x].field_order.clone() {
+                    let fx = self.heap[x].fields.get(&field).cloned();
+                    let fy = self.heap[y].fields.get(&field).cloned();
+                    let fx = self.force_struct_field(x, fx.unwrap_or(Type::Uninitialized))?;
+                    let fy = self.force_struct_field(y, fy.unwrap_or(Type::Uninitialized))?;
+                    let both_unset =
+                        matches!((&fx, &fy), (Type::Uninitialized, Type::Uninitialized));
+                    if !both_unset && !self.values_equal(fx, fy, open)? {
+                        equal = false;
+                        break;
+                    }
+                }
+                open.pop();
+                Ok(equal)
+            }
+            (Type::StructRef(_), _) | (_, Type::StructRef(_)) => Ok(false),
+            (Type::Module(x), Type::Module(y)) => Ok(x == y),
+            (a, b) if is_float(&a) || is_float(&b) => Ok(self.as_float(a)? == self.as_float(b)?),
+            (a, b) => Ok(self.as_int(a)? == self.as_int(b)?),
+        }
+    }
+
+    /// `<`, `>`, `<=`, `>=`: two strings order lexicographically by
+    /// character code, anything else as numbers.
+    fn order(&mut self, a: Type, b: Type) -> RxResult<Option<Ordering>> {
+        let a = self.force(a)?;
+        let b = self.force(b)?;
+        if let (Some(x), Some(y)) = (self.string_value(&a)?, self.string_value(&b)?) {
+            return Ok(Some(x.chars().cmp(y.chars())));
+        }
+        if is_float(&a) || is_float(&b) {
+            return Ok(self.as_float(a)?.partial_cmp(&self.as_float(b)?));
+        }
+        Ok(Some(self.as_int(a)?.cmp(&self.as_int(b)?)))
+    }
+
+    /// `===`: whether two values are the same array, slice or struct
+    /// instance rather than equal copies. Numbers and chars compare by value.
+    fn identical(&mut self, a: Type, b: Type) -> RxResult<bool> {
+        match (self.force(a)?, self.force(b)?) {
+            (Type::ArrayRef(x), Type::ArrayRef(y)) => Ok(x == y),
+            (
+                Type::ArraySlice { id, start, len },
+                Type::ArraySlice {
+                    id: id2,
+                    start: start2,
+                    len: len2,
+                },
+            ) => Ok((id, start, len) == (id2, start2, len2)),
+            (Type::StructRef(x), Type::StructRef(y)) => Ok(x == y),
+            (Type::Module(x), Type::Module(y)) => Ok(x == y),
+            (
+                a @ (Type::Integer(_) | Type::Float(_) | Type::Char(_)),
+                b @ (Type::Integer(_) | Type::Float(_) | Type::Char(_)),
+            ) => self.values_equal(a, b, &mut Vec::new()),
+            _ => Ok(false),
+        }
+    }
+}
+
+fn is_float(v: &Type) -> bool {
+    matches!(v, Type::Float(_))
+}
@@ -9,20 +9,27 @@ impl VM {
     pub(crate) fn dbg_short_type(&self, v: &Type) -> String {
         match v {
             Type::Integer(n) => format!("Int({})", n),
+            Type::Float(f) => format!("Float({})", f),
             Type::Char(c) => format!("Char({})", c),
             Type::ArrayRef(id) => format!("ArrayRef({})", id),
+            Type::ArraySlice { id, start, len } => {
+                format!("ArraySlice({}, {}..{})", id, start, start + len)
+            }
             Type::StructRef(id) => format!("StructRef({})", id),
-            Type::Function { params, .. } => format!("Function(params={:?})", params),
+            Type::Function { params, env, .. } => {
+                format!("Function(params={:?}, env={:?})", params, env.keys())
+            }
             Type::LValue(lv) => format!("LValue({:?})", lv),
-            Type::LazyValue(ast, captured) => {
+            Type::LazyValue(ast, captured, _) => {
                 format!("Lazy({:?}, cap={:?})", ast, captured.keys())
             }
+            Type::Module(id) => format!("Module({})", id),
             Type::Uninitialized => "Uninitialized".to_string(),
         }
     }
 
     pub(crate) fn dump_env_keys(&self) -> Vec<String> {
-        let mut keys: Vec<_> = self.global_env.keys().cloned().collect();
+        let mut keys: Vec<_> = self.module().globals.keys().cloned().collect();
         keys.sort();
         keys
     }
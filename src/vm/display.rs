@@ -0,0 +1,173 @@
+use super::VM;
+use crate::error::RxResult;
+use crate::grammar::Type;
+
+/// An array or struct being written out, to spot one that contains itself.
+#[derive(PartialEq)]
+enum Open {
+    Array(usize),
+    Struct(usize),
+}
+
+impl VM {
+    // =========================================================
+    // Printing
+    // =========================================================
+
+    pub(crate) fn print_value(&mut self, v: Type, newline: bool) -> RxResult<()> {
+        let text = self.display_value(v)?;
+        if newline {
+            println!("{text}");
+        } else {
+            print!("{text}");
+        }
+        Ok(())
+    }
+
+    /// A value as `print` shows it. A string is its text and a char the
+    /// char itself; other arrays and structs are written out in full.
+    pub(crate) fn display_value(&mut self, v: Type) -> RxResult<String> {
+        let v = self.force(v)?;
+        if let Type::Char(c) = v {
+            return Ok(char::from_u32(c)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .to_string());
+        }
+        if let Some(text) = self.string_value(&v)? {
+            return Ok(text);
+        }
+
+        let mut out = String::new();
+        self.write_value(v, &mut Vec::new(), &mut out)?;
+        Ok(out)
+    }
+
+    /// Writes `v` the way it appears inside an array or struct, where
+    /// strings and chars are quoted. An empty array is `[]`, or `""` next
+    /// to strings. Reactive elements and fields are forced; one that is
+    /// already being written shows as `[...]` or `Name { ... }`.
+    fn write_value(&mut self, v: Type, open: &mut Vec<Open>, out: &mut String) -> RxResult<()> {
+        match self.force(v)? {
+            Type::Integer(n) => out.push_str(&n.to_string()),
+            Type::Float(f) => out.push_str(&format_float(f)),
+            Type::Char(c) => {
+                let c = char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER);
+                out.push_str(&format!("{c:?}"));
+            }
+
+            arr @ (Type::ArrayRef(_) | Type::ArraySlice { .. }) => {
+                let elems = self.array_elems(&arr);
+                if !elems.is_empty()
+                    && let Some(text) = self.string_value(&arr)?
+                {
+                    out.push_str(&format!("{text:?}"));
+                    return Ok(());
+                }
+
+                let Some((id, _, _)) = self.array_view(&arr) else {
+                    return Ok(());
+                };
+                if open.contains(&Open::Array(id)) {
+                    out.push_str("[...]");
+                    return Ok(());
+                }
+
+                let elems = elems
+                    .into_iter()
+                    .map(|elem| self.force(elem))
+                    .collect::<RxResult<Vec<_>>>()?;
+                let mut among_strings = false;
+                for elem in &elems {
+                    among_strings |= self.string_value(elem)?.is_some_and(|t| !t.is_empty());
+                }
+
+                open.push(Open::Array(id));
+                out.push('[');
+                for (i, elem) in elems.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    if among_strings && self.array_view(&elem).is_some_and(|(_, _, len)| len == 0) {
+                        out.push_str("\"\"");
+                    } else {
+                        self.write_value(elem, open, out)?;
+                    }
+                }
+                out.push(']');
+                open.pop();
+            }
+
+            Type::StructRef(id) => {
+                let name = self.heap[id].name.clone();
+                if open.contains(&Open::Struct(id)) {
+                    out.push_str(&format!("{name} {{ ... }}"));
+                    return Ok(());
+                }
+
+                let fields = self.heap[id].field_order.clone();
+                if fields.is_empty() {
+                    out.push_str(&format!("{name} {{}}"));
+                    return Ok(());
+                }
+
+                open.push(Open::Struct(id));
+                out.push_str(&format!("{name} {{ "));
+                for (i, field) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(&format!("{field}: "));
+                    let value = self.heap[id].fields.get(&field).cloned();
+                    let value = value.unwrap_or(Type::Uninitialized);
+                    let value = self.force_struct_field(id, value)?;
+                    self.write_value(value, open, out)?;
+                }
+                out.push_str(" }");
+                open.pop();
+            }
+
+            Type::Function {
+                name: Some(name), ..
+            } => out.push_str(&format!("<func {name}>")),
+            Type::Function { name: None, .. } => out.push_str("<func>"),
+            Type::Module(id) => out.push_str(&format!("<module {}>", self.modules[id].name)),
+            Type::Uninitialized => out.push_str("<uninit>"),
+            other => {
+                return Err(self.error(format!("cannot print {}", self.type_name(&other))));
+            }
+        }
+        Ok(())
+    }
+
+    /// What kind of value `v` is, for error messages: `int`, `float`,
+    /// `char`, `string`, `array`, `struct P`, `function` or `module`.
+    /// An array is a string when it has elements and all of them are chars.
+    pub(crate) fn type_name(&self, v: &Type) -> String {
+        match v {
+            Type::Integer(_) => "int".to_string(),
+            Type::Float(_) => "float".to_string(),
+            Type::Char(_) => "char".to_string(),
+            Type::ArrayRef(_) | Type::ArraySlice { .. } => {
+                let elems = self.array_elems(v);
+                if !elems.is_empty() && elems.iter().all(|e| matches!(e, Type::Char(_))) {
+                    "string".to_string()
+                } else {
+                    "array".to_string()
+                }
+            }
+            Type::StructRef(id) => format!("struct {}", self.heap[*id].name),
+            Type::Function { .. } => "function".to_string(),
+            Type::Module(_) => "module".to_string(),
+            Type::LazyValue(..) => "reactive value".to_string(),
+            Type::LValue(_) => "reference".to_string(),
+            Type::Uninitialized => "uninitialized value".to_string(),
+        }
+    }
+}
+
+/// A float as `print` shows it: always with a point or an exponent, so
+/// `1.0` doesn't read as the integer `1`, and with as few digits as read
+/// back to the same value.
+pub(crate) fn format_float(f: f64) -> String {
+    format!("{f:?}")
+}
@@ -1,5 +1,7 @@
 use super::VM;
+use crate::error::RxResult;
 use crate::grammar::Type;
+use std::collections::HashMap;
 
 impl VM {
     pub(crate) fn lookup_var(&self, name: &str) -> Option<&Type> {
@@ -7,7 +9,22 @@ impl VM {
             .as_ref()
             .and_then(|e| e.get(name))
             .or_else(|| self.find_immutable(name))
-            .or_else(|| self.global_env.get(name))
+            .or_else(|| self.module().constants.get(name))
+            .or_else(|| self.module().globals.get(name))
+    }
+
+    /// Snapshot of the local and immutable bindings visible here, for a
+    /// closure created at this point. Module globals and constants are not
+    /// captured; the closure keeps resolving those through its module.
+    pub(crate) fn capture_env(&self) -> HashMap<String, Type> {
+        let mut env = HashMap::new();
+        for frame in &self.immutable_stack {
+            env.extend(frame.clone());
+        }
+        if let Some(locals) = &self.local_env {
+            env.extend(locals.clone());
+        }
+        env
     }
 
     pub(crate) fn find_immutable(&self, name: &str) -> Option<&Type> {
@@ -18,17 +35,18 @@ impl VM {
         self.find_immutable(name).is_some()
     }
 
-    pub(crate) fn ensure_mutable_binding(&self, name: &str) {
+    pub(crate) fn ensure_mutable_binding(&self, name: &str) -> RxResult<()> {
         // If we are inside a function (local_env exists),
         // then assignments create / modify locals and must NOT
         // be blocked by outer immutable bindings.
         if self.local_env.is_some() {
-            return;
+            return Ok(());
         }
 
         // Only block mutation when assigning in the global scope
-        if self.immutable_exists(name) {
-            panic!("cannot assign to immutable variable `{name}`");
+        if self.immutable_exists(name) || self.module().constants.contains_key(name) {
+            return Err(self.error(format!("cannot assign to immutable variable `{name}`")));
         }
+        Ok(())
     }
 }
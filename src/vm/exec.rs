@@ -1,223 +1,263 @@
 use super::VM;
-use crate::grammar::{AST, CastType, Instruction, Type};
+use super::module::StructDef;
+use crate::error::RxResult;
+use crate::grammar::{Instruction, Node, Operator, Type, UnaryOperator};
 
 impl VM {
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> RxResult<()> {
         while self.pointer < self.code.len() {
-            let instr = self.code[self.pointer].clone();
+            let instr = self.code[self.pointer].node.clone();
 
             match instr {
                 Instruction::Push(n) => self.stack.push(Type::Integer(n)),
+                Instruction::PushFloat(f) => self.stack.push(Type::Float(f)),
                 Instruction::PushChar(c) => self.stack.push(Type::Char(c)),
+                Instruction::Dup => {
+                    let top = self.pop()?;
+                    self.stack.push(top.clone());
+                    self.stack.push(top);
+                }
                 Instruction::Load(name) => {
-                    let v = self
-                        .lookup_var(&name)
-                        .cloned()
-                        .unwrap_or_else(|| panic!("undefined variable: {name}"));
+                    let v = self.lookup_var(&name).cloned().ok_or_else(|| {
+                        self.private_item(&name)
+                            .unwrap_or_else(|| self.error(format!("undefined variable: {name}")))
+                    })?;
 
-                    let value = self.force(v);
+                    let value = self.force(v)?;
                     self.stack.push(value);
                 }
-                Instruction::Store(name) => self.exec_store(name),
-                Instruction::StoreImmutable(name) => self.exec_store_immutable(name),
-                Instruction::StoreReactive(name, ast) => self.exec_store_reactive(name, ast),
-                Instruction::Add => self.exec_add(),
-                Instruction::Sub => self.exec_sub(),
-                Instruction::Mul => self.exec_mul(),
-                Instruction::Div => self.exec_div(),
-                Instruction::Modulo => self.exec_modulo(),
-                Instruction::Greater => self.exec_cmp(|b, a| (b > a) as i32),
-                Instruction::Less => self.exec_cmp(|b, a| (b < a) as i32),
-                Instruction::Equal => self.exec_cmp(|b, a| (b == a) as i32),
-                Instruction::NotEqual => self.exec_cmp(|b, a| (b != a) as i32),
-                Instruction::GreaterEqual => self.exec_cmp(|b, a| (b >= a) as i32),
-                Instruction::LessEqual => self.exec_cmp(|b, a| (b <= a) as i32),
-                Instruction::And => self.exec_cmp(|b, a| ((b > 0) && (a > 0)) as i32),
-                Instruction::Or => self.exec_cmp(|b, a| ((b > 0) || (a > 0)) as i32),
+                Instruction::Store(name) => self.exec_store(name)?,
+                Instruction::StoreImmutable(name) => self.exec_store_immutable(name)?,
+                Instruction::StoreReactive(name, ast) => self.exec_store_reactive(name, *ast)?,
+                Instruction::Add => self.exec_add()?,
+                Instruction::Sub => self.exec_arith(Operator::Subtraction)?,
+                Instruction::Mul => self.exec_arith(Operator::Multiplication)?,
+                Instruction::Div => self.exec_arith(Operator::Division)?,
+                Instruction::Modulo => self.exec_arith(Operator::Modulo)?,
+                Instruction::Power => self.exec_arith(Operator::Power)?,
+                Instruction::Negate => self.exec_unary(UnaryOperator::Negate)?,
+                Instruction::BitAnd => self.exec_arith(Operator::BitAnd)?,
+                Instruction::BitOr => self.exec_arith(Operator::BitOr)?,
+                Instruction::BitXor => self.exec_arith(Operator::BitXor)?,
+                Instruction::BitNot => self.exec_unary(UnaryOperator::BitNot)?,
+                Instruction::ShiftLeft => self.exec_arith(Operator::ShiftLeft)?,
+                Instruction::ShiftRight => self.exec_arith(Operator::ShiftRight)?,
+                Instruction::Greater => self.exec_compare(Operator::Greater)?,
+                Instruction::Less => self.exec_compare(Operator::Less)?,
+                Instruction::Equal => self.exec_compare(Operator::Equal)?,
+                Instruction::NotEqual => self.exec_compare(Operator::NotEqual)?,
+                Instruction::GreaterEqual => self.exec_compare(Operator::GreaterEqual)?,
+                Instruction::LessEqual => self.exec_compare(Operator::LessEqual)?,
+                Instruction::Identical => self.exec_compare(Operator::Identical)?,
+                Instruction::NotIdentical => self.exec_compare(Operator::NotIdentical)?,
                 Instruction::Print => {
-                    let v = self.pop();
-                    self.print_value(v, false);
+                    let v = self.pop()?;
+                    self.print_value(v, false)?;
                 }
                 Instruction::Println => {
-                    let v = self.pop();
-                    self.print_value(v, true);
+                    let v = self.pop()?;
+                    self.print_value(v, true)?;
                 }
-                Instruction::ArrayNew => self.exec_array_new(),
-                Instruction::ArrayGet => self.exec_array_get(),
-                Instruction::StoreIndex(name) => self.exec_store_index(name),
+                Instruction::ArrayNew(dims) => self.exec_array_new(dims)?,
+                Instruction::ArrayLiteral(n) => self.exec_array_literal(n)?,
+                Instruction::ArrayGet => self.exec_array_get()?,
+                Instruction::StoreIndex(name) => self.exec_store_index(name)?,
                 Instruction::StoreIndexReactive(name, ast) => {
-                    self.exec_store_index_reactive(name, ast)
+                    self.exec_store_index_reactive(name, *ast)?
                 }
                 Instruction::StoreFunction(name, params, body) => {
-                    self.global_env
-                        .insert(name, Type::Function { params, body });
+                    self.exec_store_function(name, params, body)?
+                }
+                Instruction::MakeClosure(params, body) => {
+                    let f = self.make_closure(params, body, None);
+                    self.stack.push(f);
+                }
+                Instruction::Call(name, argc) => self.exec_call(name, argc)?,
+                Instruction::CallValue(argc) => self.exec_call_value(argc)?,
+                Instruction::CallMember(name, argc) => self.exec_call_member(name, argc)?,
+                Instruction::CallMain => self.exec_call_main()?,
+                Instruction::Exit => {
+                    let v = self.pop()?;
+                    self.set_exit_status(v, "a script's `return`")?;
+                    return Ok(());
                 }
-                Instruction::Call(name, argc) => self.exec_call(name, argc),
                 Instruction::StoreStruct(name, fields) => {
-                    self.struct_defs.insert(name, fields);
-                }
-                Instruction::NewStruct(name) => {
-                    let def = self
-                        .struct_defs
-                        .get(&name)
-                        .cloned()
-                        .unwrap_or_else(|| panic!("unknown struct type `{name}`"));
-                    let inst = self.instantiate_struct(def);
-                    self.stack.push(inst);
-                }
-                Instruction::FieldGet(field) => self.exec_field_get(field),
-                Instruction::FieldSet(field) => self.exec_field_set(field),
+                    self.define(&name)?;
+                    let module = self.current_module;
+                    self.module_mut()
+                        .structs
+                        .insert(name, StructDef { module, fields });
+                }
+                Instruction::NewStruct(name, names) => self.exec_new_struct(name, names)?,
+                Instruction::FieldGet(field) => self.exec_field_get(field)?,
+                Instruction::FieldSet(field) => self.exec_field_set(field)?,
                 Instruction::FieldSetReactive(field, ast) => {
-                    self.exec_field_set_reactive(field, ast)
+                    self.exec_field_set_reactive(field, *ast)?
                 }
                 Instruction::PushImmutableContext => {
                     self.immutable_stack.push(std::collections::HashMap::new());
                 }
                 Instruction::PopImmutableContext => {
                     if self.immutable_stack.len() <= 1 {
-                        panic!("internal error: cannot pop root immutable context");
+                        return Err(self.error("internal error: cannot pop root immutable context"));
                     }
                     self.immutable_stack.pop();
                 }
-                Instruction::ClearImmutableContext => {
-                    self.immutable_stack
-                        .last_mut()
-                        .expect("internal error: no immutable scope")
-                        .clear();
-                }
+                Instruction::ClearImmutableContext => match self.immutable_stack.last_mut() {
+                    Some(scope) => scope.clear(),
+                    None => return Err(self.error("internal error: no immutable scope")),
+                },
                 Instruction::Label(_) => {}
                 Instruction::Jump(label) => {
                     self.pointer = *self
                         .labels
                         .get(&label)
-                        .unwrap_or_else(|| panic!("unknown label `{label}`"));
+                        .ok_or_else(|| self.error(format!("unknown label `{label}`")))?;
                     continue;
                 }
                 Instruction::JumpIfZero(label) => {
-                    let n = self.pop_int();
-                    if n == 0 {
+                    let cond = self.pop()?;
+                    if !self.truthy(cond)? {
                         self.pointer = *self
                             .labels
                             .get(&label)
-                            .unwrap_or_else(|| panic!("unknown label `{label}`"));
+                            .ok_or_else(|| self.error(format!("unknown label `{label}`")))?;
                         continue;
                     }
                 }
-                Instruction::Return => return,
-                Instruction::ArrayLValue => self.exec_array_lvalue(),
-                Instruction::FieldLValue(field) => self.exec_field_lvalue(field),
-                Instruction::StoreThrough => self.exec_store_through(),
-                Instruction::StoreThroughReactive(ast) => self.exec_store_through_reactive(ast),
-                Instruction::StoreThroughImmutable => self.store_through_immutable(),
-                Instruction::Import(path) => {
-                    let module_name = path.join(".");
-                    if !self.imported_modules.contains(&module_name) {
-                        self.imported_modules.insert(module_name.clone());
-                        self.import_module(path);
+                Instruction::Return => return Ok(()),
+                Instruction::ArrayLValue => self.exec_array_lvalue()?,
+                Instruction::ArraySlice { has_end } => self.exec_array_slice(has_end)?,
+                Instruction::FieldLValue(field) => self.exec_field_lvalue(field)?,
+                Instruction::StoreThrough => self.exec_store_through()?,
+                Instruction::StoreThroughReactive(ast) => self.exec_store_through_reactive(*ast)?,
+                Instruction::StoreThroughImmutable => self.store_through_immutable()?,
+                Instruction::EnterModule(id, name) => self.enter_module(id, name),
+                Instruction::Import {
+                    module,
+                    alias,
+                    names,
+                } => self.import_module(module, alias, names)?,
+                Instruction::Export(name) => {
+                    self.module_mut().exports.insert(name);
+                }
+                Instruction::Format(spec) => {
+                    let v = self.pop()?;
+                    let text = self.format_value(v, spec.as_ref())?;
+                    let s = self.alloc_string(&text);
+                    self.stack.push(s);
+                }
+                Instruction::Concat(n) => {
+                    let mut text = String::new();
+                    for part in self.pop_n(n)? {
+                        text += &self.expect_string(&part, "a string template part")?;
                     }
+                    let s = self.alloc_string(&text);
+                    self.stack.push(s);
                 }
                 Instruction::Cast(target) => {
-                    let v = self.pop();
-                    match target {
-                        CastType::Int => {
-                            let n = self.as_int(v);
-                            self.stack.push(Type::Integer(n));
-                        }
-                        CastType::Char => {
-                            let n = self.as_int(v);
-                            if n < 0 || n > 0x10FFFF {
-                                panic!("invalid char code {}", n);
-                            }
-                            self.stack.push(Type::Char(n as u32));
-                        }
-                    }
+                    let v = self.pop()?;
+                    let cast = self.cast(target, v)?;
+                    self.stack.push(cast);
                 }
             }
 
             self.pointer += 1;
         }
+        Ok(())
     }
 
     // =========================================================
     // Store handlers
     // =========================================================
-    fn exec_store(&mut self, name: String) {
-        self.ensure_mutable_binding(&name);
-        let v = self.pop();
+    fn exec_store(&mut self, name: String) -> RxResult<()> {
+        self.ensure_mutable_binding(&name)?;
+        let v = self.pop()?;
         match &mut self.local_env {
             Some(env) => {
                 env.insert(name, v);
             }
             None => {
-                self.global_env.insert(name, v);
+                self.module_mut().globals.insert(name, v);
             }
         }
+        Ok(())
     }
 
-    fn exec_store_immutable(&mut self, name: String) {
-        let v = self.pop();
-        let scope = self
+    fn exec_store_immutable(&mut self, name: String) -> RxResult<()> {
+        let v = self.pop()?;
+        if self
             .immutable_stack
-            .last_mut()
-            .expect("internal error: no immutable scope");
-        if scope.contains_key(&name) {
-            panic!("cannot reassign immutable variable `{name}`");
+            .last()
+            .is_some_and(|scope| scope.contains_key(&name))
+        {
+            return Err(self.error(format!("cannot reassign immutable variable `{name}`")));
+        }
+
+        // top-level constants belong to the module and can be imported
+        if self.local_env.is_none() && self.immutable_stack.len() == 1 {
+            self.define(&name)?;
+            self.module_mut().constants.insert(name.clone(), v.clone());
+        }
+
+        match self.immutable_stack.last_mut() {
+            Some(scope) => {
+                scope.insert(name, v);
+                Ok(())
+            }
+            None => Err(self.error("internal error: no immutable scope")),
         }
-        scope.insert(name, v);
     }
 
-    fn exec_store_reactive(&mut self, name: String, ast: Box<AST>) {
-        self.ensure_mutable_binding(&name);
+    fn exec_store_reactive(&mut self, name: String, ast: Node) -> RxResult<()> {
+        self.ensure_mutable_binding(&name)?;
         let frozen = self.freeze_ast(ast);
         let captured = self.capture_immutables_for_ast(&frozen);
+        let lazy = Type::LazyValue(frozen, captured, self.current_module);
 
         match &mut self.local_env {
             Some(env) => {
-                env.insert(name, Type::LazyValue(frozen, captured));
+                env.insert(name, lazy);
             }
             None => {
-                self.global_env
-                    .insert(name, Type::LazyValue(frozen, captured));
+                self.module_mut().globals.insert(name, lazy);
             }
         }
+        Ok(())
     }
 
     // =========================================================
     // Arithmetic / comparisons
     // =========================================================
 
-    fn exec_add(&mut self) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(b + a));
-    }
-
-    fn exec_sub(&mut self) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(b - a));
+    fn exec_add(&mut self) -> RxResult<()> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+        let sum = self.add_values(b, a)?;
+        self.stack.push(sum);
+        Ok(())
     }
 
-    fn exec_modulo(&mut self) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(b % a));
-    }
-    fn exec_mul(&mut self) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(b * a));
+    fn exec_arith(&mut self, op: Operator) -> RxResult<()> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+        let result = self.arith(op, b, a)?;
+        self.stack.push(result);
+        Ok(())
     }
 
-    fn exec_div(&mut self) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(b / a));
+    fn exec_unary(&mut self, op: UnaryOperator) -> RxResult<()> {
+        let a = self.pop()?;
+        let result = self.unary(op, a)?;
+        self.stack.push(result);
+        Ok(())
     }
 
-    fn exec_cmp<F: FnOnce(i32, i32) -> i32>(&mut self, f: F) {
-        let a = self.pop_int();
-        let b = self.pop_int();
-        self.stack.push(Type::Integer(f(b, a)));
+    fn exec_compare(&mut self, op: Operator) -> RxResult<()> {
+        let a = self.pop()?;
+        let b = self.pop()?;
+        let result = self.compare(op, b, a)?;
+        self.stack.push(result);
+        Ok(())
     }
 }
@@ -1,20 +1,34 @@
+pub mod arith;
+pub mod array;
 pub mod call;
+pub mod compare;
 pub mod debug;
+pub mod display;
 pub mod env;
 pub mod exec;
+pub mod module;
 pub mod reactive;
 pub mod runtime;
+pub mod string;
 
-use crate::grammar::{Instruction, StructFieldInit, StructInstance, Type};
+use crate::error::{RxError, RxResult};
+use crate::grammar::{Instruction, StructInstance, Type};
+use crate::span::{Span, Spanned};
+use module::Module;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Arguments passed by name, in call order.
+pub(crate) type NamedArgs = Vec<(String, Type)>;
+
+/// How deep calls and reactive evaluations may nest before the program is
+/// stopped, well before the host's own stack runs out.
+const MAX_DEPTH: usize = 1000;
 
 pub struct VM {
     // Operand stack
     stack: Vec<Type>,
 
-    // Global mutable environment (top-level only)
-    global_env: HashMap<String, Type>,
-
     // Local mutable environment (function scope)
     local_env: Option<HashMap<String, Type>>,
 
@@ -23,17 +37,27 @@ pub struct VM {
 
     // Bytecode execution state
     pointer: usize,
-    code: Vec<Instruction>,
+    code: Vec<Spanned<Instruction>>,
     labels: HashMap<String, usize>,
 
+    // Spans of the AST nodes being evaluated by the reactive interpreter
+    eval_spans: Vec<Span>,
+
+    // Calls and reactive evaluations in progress, limited to MAX_DEPTH
+    depth: usize,
+
+    // Module namespaces: 0 is the program itself, then imports in load order
+    modules: Vec<Module>,
+    current_module: usize,
+
     // Runtime heaps
-    struct_defs: HashMap<String, Vec<(String, Option<StructFieldInit>)>>,
     heap: Vec<StructInstance>,
     array_heap: Vec<Vec<Type>>,
     array_immutables: Vec<HashSet<usize>>,
 
-    // Module import memoization
-    imported_modules: HashSet<String>,
+    // Command-line arguments handed to `main(args)`, and what `main` returned
+    args: Vec<String>,
+    exit_status: i32,
 
     // Debugging
     debug: bool,
@@ -41,33 +65,82 @@ pub struct VM {
 }
 
 impl VM {
-    pub fn new(code: Vec<Instruction>) -> Self {
+    pub fn new(code: Vec<Spanned<Instruction>>) -> Self {
         let labels = Self::build_labels(&code);
         Self {
             stack: Vec::new(),
-            global_env: HashMap::new(),
             local_env: None,
             immutable_stack: vec![HashMap::new()],
             pointer: 0,
             code,
             labels,
-            struct_defs: HashMap::new(),
+            eval_spans: Vec::new(),
+            depth: 0,
+            modules: vec![Module::default()],
+            current_module: 0,
             heap: Vec::new(),
             array_heap: Vec::new(),
             array_immutables: Vec::new(),
-            imported_modules: HashSet::new(),
-            debug: true,
+            args: Vec::new(),
+            exit_status: 0,
+            debug: false,
             debug_reactive_ctx: Vec::new(),
         }
     }
 
-    fn build_labels(code: &[Instruction]) -> HashMap<String, usize> {
+    /// Arguments passed to `main` when it declares a parameter.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Dumps the VM state to stderr when the reactive evaluator fails to
+    /// find a variable. Off unless asked for, since it shows VM internals.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// The integer returned from `main` or from a script's top level, or 0
+    /// if neither returned one.
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status
+    }
+
+    fn build_labels(code: &[Spanned<Instruction>]) -> HashMap<String, usize> {
         let mut labels = HashMap::new();
         for (i, instr) in code.iter().enumerate() {
-            if let Instruction::Label(name) = instr {
+            if let Instruction::Label(name) = &instr.node {
                 labels.insert(name.clone(), i);
             }
         }
         labels
     }
+
+    /// Location of whatever is executing right now: the innermost AST node
+    /// under reactive evaluation, otherwise the current instruction.
+    pub(crate) fn current_span(&self) -> Option<Span> {
+        self.eval_spans
+            .last()
+            .or_else(|| self.code.get(self.pointer).map(|i| &i.span))
+            .cloned()
+    }
+
+    /// Builds a runtime error pointing at whatever is executing right now.
+    pub(crate) fn error(&self, msg: impl fmt::Display) -> RxError {
+        RxError::runtime(msg.to_string(), self.current_span())
+    }
+
+    /// Runs `f` one level deeper, or fails once `MAX_DEPTH` levels are in
+    /// progress, so runaway recursion is a runtime error rather than a
+    /// crash of the host.
+    pub(crate) fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> RxResult<T>) -> RxResult<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(format!(
+                "stack overflow: more than {MAX_DEPTH} nested calls or reactive evaluations"
+            )));
+        }
+        self.depth += 1;
+        let out = f(self);
+        self.depth -= 1;
+        out
+    }
 }
@@ -0,0 +1,215 @@
+use super::VM;
+use crate::error::{RxError, RxResult};
+use crate::grammar::{StructFieldInit, Type};
+use crate::resolve::{Bindings, ModuleTable, NameError};
+use std::collections::{HashMap, HashSet};
+
+/// A struct definition together with the module that declared it, so its
+/// field initializers resolve names there.
+#[derive(Debug, Clone)]
+pub(crate) struct StructDef {
+    pub(crate) module: usize,
+    pub(crate) fields: Vec<(String, Option<StructFieldInit>)>,
+}
+
+/// One namespace: the program itself (module 0) or an imported file.
+#[derive(Debug, Default)]
+pub(crate) struct Module {
+    // Dotted import path (`std.maths`); empty for the program itself
+    pub(crate) name: String,
+
+    // Top-level mutable variables and functions
+    pub(crate) globals: HashMap<String, Type>,
+    pub(crate) structs: HashMap<String, StructDef>,
+
+    // Top-level `:=` bindings, own and imported
+    pub(crate) constants: HashMap<String, Type>,
+
+    // Module every function, struct, constant and namespace visible here comes from
+    pub(crate) bindings: Bindings,
+
+    // Own items marked `pub`; only these can be imported
+    pub(crate) exports: HashSet<String>,
+}
+
+impl ModuleTable for [Module] {
+    fn module_name(&self, id: usize) -> &str {
+        &self[id].name
+    }
+
+    fn defines(&self, id: usize, name: &str) -> bool {
+        self[id].bindings.origin(name) == Some(id)
+    }
+
+    fn exports(&self, id: usize, name: &str) -> bool {
+        self.defines(id, name) && self[id].exports.contains(name)
+    }
+
+    fn exported(&self, id: usize) -> Vec<String> {
+        let mut names: Vec<String> = self[id]
+            .exports
+            .iter()
+            .filter(|name| self.defines(id, name))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+impl VM {
+    // =========================================================
+    // Current module
+    // =========================================================
+    pub(crate) fn module(&self) -> &Module {
+        &self.modules[self.current_module]
+    }
+
+    pub(crate) fn module_mut(&mut self) -> &mut Module {
+        &mut self.modules[self.current_module]
+    }
+
+    fn name_error(&self, err: NameError) -> RxError {
+        self.error(err.message(&*self.modules))
+    }
+
+    /// Records a function, struct or constant defined by the current module.
+    /// The linker reports name collisions before the program starts.
+    pub(crate) fn define(&mut self, name: &str) -> RxResult<()> {
+        self.module_mut()
+            .bindings
+            .define(name)
+            .map_err(|err| self.name_error(err))
+    }
+
+    // =========================================================
+    // Exports
+    // =========================================================
+
+    /// If `name` is undefined here only because an imported module keeps it
+    /// private, an error saying so.
+    pub(crate) fn private_item(&self, name: &str) -> Option<RxError> {
+        self.module()
+            .bindings
+            .private_item(name, &*self.modules)
+            .map(|err| self.name_error(err))
+    }
+
+    fn unexported(&self, id: usize, name: &str, what: &'static str) -> RxError {
+        self.name_error(NameError::Unexported {
+            id,
+            name: name.to_string(),
+            what,
+        })
+    }
+
+    /// A `pub` function or constant defined by module `id` itself.
+    pub(crate) fn export_value(&self, id: usize, name: &str) -> Option<Type> {
+        if !self.modules.exports(id, name) {
+            return None;
+        }
+        let module = &self.modules[id];
+        module.constants.get(name).cloned().or_else(|| {
+            module
+                .globals
+                .get(name)
+                .filter(|v| matches!(v, Type::Function { .. }))
+                .cloned()
+        })
+    }
+
+    /// A `pub` struct declared by module `id` itself.
+    pub(crate) fn export_struct(&self, id: usize, name: &str) -> Option<StructDef> {
+        if !self.modules.exports(id, name) {
+            return None;
+        }
+        self.modules[id].structs.get(name).cloned()
+    }
+
+    /// Resolves `ns.Name` or a plain struct name visible in the current module.
+    pub(crate) fn lookup_struct(&self, name: &str) -> RxResult<StructDef> {
+        if let Some((ns, item)) = name.split_once('.') {
+            let id = self.lookup_namespace(ns)?;
+            return self
+                .export_struct(id, item)
+                .ok_or_else(|| self.unexported(id, item, "struct"));
+        }
+
+        self.module().structs.get(name).cloned().ok_or_else(|| {
+            self.private_item(name)
+                .unwrap_or_else(|| self.error(format!("unknown struct type `{name}`")))
+        })
+    }
+
+    fn lookup_namespace(&self, ns: &str) -> RxResult<usize> {
+        match self.module().globals.get(ns) {
+            Some(Type::Module(id)) => Ok(*id),
+            _ => Err(self.error(format!("`{ns}` is not an imported module"))),
+        }
+    }
+
+    /// Reads `ns.name` where `ns` is a module namespace.
+    pub(crate) fn module_member(&self, id: usize, name: &str) -> RxResult<Type> {
+        self.export_value(id, name)
+            .ok_or_else(|| self.unexported(id, name, "item"))
+    }
+
+    // =========================================================
+    // Module imports
+    // =========================================================
+
+    /// Binds what an `import` of module `id` makes visible, following
+    /// `Bindings::import`. The linker has already run module `id` by the
+    /// time this executes.
+    pub(crate) fn import_module(
+        &mut self,
+        id: usize,
+        alias: Option<String>,
+        names: Option<Vec<String>>,
+    ) -> RxResult<()> {
+        // module `id` is never the current one, so the table doesn't miss
+        // the bindings taken out of it here
+        let here = self.current_module;
+        let mut bindings = std::mem::take(&mut self.modules[here].bindings);
+        let imported = bindings.import(id, alias.as_deref(), names.as_deref(), &*self.modules);
+        self.modules[here].bindings = bindings;
+
+        if let Some(err) = imported.errors.into_iter().next() {
+            return Err(self.name_error(err));
+        }
+        if let Some(ns) = imported.namespace {
+            self.module_mut().globals.insert(ns, Type::Module(id));
+        }
+        for name in imported.items {
+            self.bind_import(id, name);
+        }
+        Ok(())
+    }
+
+    /// Makes item `name` of module `id` visible unqualified in the current module.
+    fn bind_import(&mut self, id: usize, name: String) {
+        if let Some(def) = self.export_struct(id, &name) {
+            self.module_mut().structs.insert(name.clone(), def);
+        }
+        if let Some(v) = self.export_value(id, &name) {
+            let module = self.module_mut();
+            match v {
+                Type::Function { .. } => module.globals.insert(name, v),
+                _ => module.constants.insert(name, v),
+            };
+        }
+    }
+
+    /// Starts running the top level of module `id`, in its own namespace
+    /// and a fresh scope. The linker emits one of these per module.
+    pub(crate) fn enter_module(&mut self, id: usize, name: String) {
+        if self.modules.len() <= id {
+            self.modules.resize_with(id + 1, Module::default);
+        }
+        self.modules[id].name = name;
+        self.modules[id].bindings = Bindings::new(id);
+        self.current_module = id;
+        self.immutable_stack = vec![HashMap::new()];
+        self.local_env = None;
+    }
+}
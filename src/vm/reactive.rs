@@ -1,5 +1,7 @@
-use super::VM;
-use crate::grammar::{AST, LValue, Operator, Type};
+use super::arith::ARITH_BUILTINS;
+use super::{NamedArgs, VM};
+use crate::error::RxResult;
+use crate::grammar::{AST, LValue, Node, Operator, TemplatePart, Type};
 use std::collections::{HashMap, HashSet};
 
 impl VM {
@@ -11,13 +13,15 @@ impl VM {
     /// - LazyValue is evaluated
     /// - LValue is dereferenced
     /// - Everything else is returned as-is
-    pub(crate) fn force(&mut self, v: Type) -> Type {
+    pub(crate) fn force(&mut self, v: Type) -> RxResult<Type> {
         match v {
-            Type::LazyValue(ast, captured) => {
+            Type::LazyValue(ast, captured, module) => {
+                let saved_module = std::mem::replace(&mut self.current_module, module);
                 self.immutable_stack.push(captured);
                 let out = self.eval_value(*ast);
                 self.immutable_stack.pop();
-                self.force(out)
+                self.current_module = saved_module;
+                self.force(out?)
             }
 
             Type::LValue(lv) => match lv {
@@ -27,30 +31,32 @@ impl VM {
                         .fields
                         .get(&field)
                         .cloned()
-                        .unwrap_or_else(|| panic!("missing struct field `{}`", field));
+                        .ok_or_else(|| self.error(format!("missing struct field `{}`", field)))?;
 
                     self.force_struct_field(struct_id, val)
                 }
 
                 LValue::ArrayElem { array_id, index } => {
-                    let val = self.read_lvalue(LValue::ArrayElem { array_id, index });
+                    let val = self.read_lvalue(LValue::ArrayElem { array_id, index })?;
                     self.force(val)
                 }
             },
 
-            other => other,
+            other => Ok(other),
         }
     }
 
     /// Like force, but when the LazyValue originates from a struct field, it evaluates
     /// with a struct-local immutable frame binding all fields as LValues.
-    pub(crate) fn force_struct_field(&mut self, struct_id: usize, v: Type) -> Type {
+    pub(crate) fn force_struct_field(&mut self, struct_id: usize, v: Type) -> RxResult<Type> {
         match v {
-            Type::LazyValue(ast, captured) => {
+            Type::LazyValue(ast, captured, module) => {
+                let saved_module = std::mem::replace(&mut self.current_module, module);
                 self.immutable_stack.push(captured);
                 let out = self.eval_reactive_field_in_struct(struct_id, *ast);
                 self.immutable_stack.pop();
-                self.force(out)
+                self.current_module = saved_module;
+                self.force(out?)
             }
             other => self.force(other),
         }
@@ -59,24 +65,34 @@ impl VM {
     // =========================================================
     // Lazy/reactive evaluation (AST interpreter)
     // =========================================================
-    pub(crate) fn eval_value(&mut self, ast: AST) -> Type {
+    pub(crate) fn eval_value(&mut self, node: Node) -> RxResult<Type> {
+        self.eval_spans.push(node.span);
+        let out = self.nested(|vm| vm.eval_kind(node.kind));
+        self.eval_spans.pop();
+        out
+    }
+
+    fn eval_kind(&mut self, ast: AST) -> RxResult<Type> {
         match ast {
-            AST::Number(n) => Type::Integer(n),
-            AST::Char(c) => Type::Char(c),
+            AST::Number(n) => Ok(Type::Integer(n)),
+            AST::Float(f) => Ok(Type::Float(f)),
+            AST::Char(c) => Ok(Type::Char(c)),
 
             AST::Var(name) => {
                 if let Some(v) = self.find_immutable(&name).cloned() {
-                    return v;
+                    return Ok(v);
                 }
                 if let Some(v) = self.lookup_var(&name).cloned() {
-                    return v;
+                    return Ok(v);
                 }
 
                 self.dbg_dump_state(&format!(
                     "UNBOUND VAR lookup failed for `{}` while eval_value(Var)",
                     name
                 ));
-                panic!("undefined variable: {name}");
+                Err(self
+                    .private_item(&name)
+                    .unwrap_or_else(|| self.error(format!("undefined variable: {name}"))))
             }
 
             AST::Ternary {
@@ -84,37 +100,61 @@ impl VM {
                 then_expr,
                 else_expr,
             } => {
-                let value = self.eval_value(*cond);
-                let c = self.as_int(value);
-                if c != 0 {
+                let value = self.eval_value(*cond)?;
+                if self.truthy(value)? {
                     self.eval_value(*then_expr)
                 } else {
                     self.eval_value(*else_expr)
                 }
             }
 
-            AST::ArrayNew(size_ast) => {
-                let value = self.eval_value(*size_ast);
-                let n = self.as_usize_nonneg(value, "array size");
-                let id = self.array_heap.len();
-                self.array_heap.push(vec![Type::Integer(0); n]);
-                self.array_immutables.push(HashSet::new());
-                Type::ArrayRef(id)
+            AST::ArrayNew(dims) => {
+                let mut sizes = Vec::with_capacity(dims.len());
+                for d in dims {
+                    let value = self.eval_value(d)?;
+                    sizes.push(self.as_usize_nonneg(value, "array size")?);
+                }
+                Ok(self.alloc_zeroed(&sizes))
             }
 
-            AST::StringLiteral(s) => {
-                let id = self.array_heap.len();
-                let mut arr = Vec::with_capacity(s.chars().count());
-                for c in s.chars() {
-                    arr.push(Type::Char(c as u32));
+            AST::ArrayLiteral(elems) => {
+                let mut vals = Vec::with_capacity(elems.len());
+                for e in elems {
+                    let v = self.eval_value(e)?;
+                    vals.push(self.force_to_storable(v)?);
                 }
-                self.array_heap.push(arr);
-                Type::ArrayRef(id)
+                Ok(self.alloc_array(vals))
+            }
+
+            AST::StructNew(name, fields) => {
+                let mut names = Vec::with_capacity(fields.len());
+                let mut values = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    names.push(field);
+                    values.push(self.eval_value(value)?);
+                }
+                self.new_struct(&name, names, values)
+            }
+
+            AST::StringLiteral(s) => Ok(self.alloc_string(&s)),
+
+            AST::Template(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        TemplatePart::Text(t) => text += &t,
+                        TemplatePart::Value(value, spec) => {
+                            let v = self.eval_value(value)?;
+                            text += &self.format_value(v, spec.as_ref())?;
+                        }
+                    }
+                }
+                Ok(self.alloc_string(&text))
             }
 
             AST::FieldAccess(base, field) => {
-                let value = self.eval_value(*base);
-                let obj = self.force(value);
+                let value = self.eval_value(*base)?;
+                let obj = self.force(value)?;
                 match obj {
                     Type::StructRef(id) => {
                         let v = self
@@ -122,83 +162,143 @@ impl VM {
                             .get(id)
                             .and_then(|s| s.fields.get(&field))
                             .cloned()
-                            .unwrap_or_else(|| panic!("missing struct field `{field}`"));
+                            .ok_or_else(|| self.error(format!("missing struct field `{field}`")))?;
                         self.force_struct_field(id, v)
                     }
-                    other => panic!("type error: field access on non-struct {:?}", other),
+                    Type::Module(id) => {
+                        let v = self.module_member(id, &field)?;
+                        self.force(v)
+                    }
+                    other => Err(self.error(format!(
+                        "type error: cannot read field `{field}` of {}",
+                        self.type_name(&other)
+                    ))),
                 }
             }
 
             AST::Index(base, index) => {
-                let idx_val = self.eval_value(*index);
-                let idx = self.as_usize_nonneg(idx_val, "array index");
+                let idx_val = self.eval_value(*index)?;
+                let idx = self.as_usize_nonneg(idx_val, "array index")?;
 
-                let base_val = self.eval_value(*base);
-                let arr = self.force(base_val);
+                let base_val = self.eval_value(*base)?;
+                let arr = self.force(base_val)?;
 
-                match arr {
-                    Type::ArrayRef(id) => {
-                        let len = self.array_heap[id].len();
-                        if idx >= len {
-                            panic!("array index out of bounds: index {idx}, length {len}");
-                        }
-                        let elem = self.array_heap[id][idx].clone();
-                        self.force(elem)
-                    }
-                    other => panic!("type error: indexing non-array {:?}", other),
-                }
+                let (id, index) = self.array_element(&arr, idx)?;
+                let elem = self.array_heap[id][index].clone();
+                self.force(elem)
+            }
+
+            AST::Slice { base, start, end } => {
+                let base = self.eval_value(*base)?;
+                let start = match start {
+                    Some(start) => self.eval_value(*start)?,
+                    None => Type::Integer(0),
+                };
+                let end = match end {
+                    Some(end) => Some(self.eval_value(*end)?),
+                    None => None,
+                };
+                self.slice(base, start, end)
             }
 
             AST::Call { name, args } => {
-                let mut vals = Vec::with_capacity(args.len());
-                for a in args {
-                    vals.push(self.eval_value(a));
+                let (vals, named) = self.eval_args(args)?;
+                let f = match self.lookup_var(&name).cloned() {
+                    Some(f) => f,
+                    None if ARITH_BUILTINS.contains(&name.as_str()) => {
+                        return self.call_arith_builtin(&name, vals, named);
+                    }
+                    None => {
+                        return Err(self.private_item(&name).unwrap_or_else(|| {
+                            self.error(format!("call error: `{name}` is not defined"))
+                        }));
+                    }
+                };
+
+                self.call_value(f, vals, named)
+            }
+
+            AST::Invoke { callee, args } => {
+                let f = self.eval_value(*callee)?;
+                let (vals, named) = self.eval_args(args)?;
+                self.call_value(f, vals, named)
+            }
+
+            AST::Lambda { params, body } => Ok(self.make_closure(params, body, None)),
+
+            AST::MemberCall { base, name, args } => {
+                let base = self.eval_value(*base)?;
+                let (vals, named) = self.eval_args(args)?;
+                self.call_member(base, &name, vals, named)
+            }
+
+            AST::Cast { target, expr } => {
+                let v = self.eval_value(*expr)?;
+                self.cast(target, v)
+            }
+
+            // the right side only runs when the left one doesn't decide
+            AST::Operation(l, op @ (Operator::And | Operator::Or), r) => {
+                let lv = self.eval_value(*l)?;
+                let left = self.truthy(lv)?;
+                if left == matches!(op, Operator::Or) {
+                    return Ok(Type::Integer(left as i32));
                 }
-                let f = self
-                    .global_env
-                    .get(&name)
-                    .cloned()
-                    .unwrap_or_else(|| panic!("call error: `{name}` is not defined"));
+                let rv = self.eval_value(*r)?;
+                Ok(Type::Integer(self.truthy(rv)? as i32))
+            }
 
-                self.call_function(f, vals)
+            AST::Unary(op, expr) => {
+                let v = self.eval_value(*expr)?;
+                self.unary(op, v)
             }
 
             AST::Operation(l, op, r) => {
-                let lv = self.eval_value(*l);
-                let rv = self.eval_value(*r);
-
-                let v = self.force(lv);
-                let a = self.as_int(v);
-                let v1 = self.force(rv);
-                let b = self.as_int(v1);
-
-                Type::Integer(match op {
-                    Operator::Addition => a + b,
-                    Operator::Subtraction => a - b,
-                    Operator::Multiplication => a * b,
-                    Operator::Division => a / b,
-                    Operator::Modulo => a % b,
-                    Operator::Greater => (a > b) as i32,
-                    Operator::Less => (a < b) as i32,
-                    Operator::Equal => (a == b) as i32,
-                    Operator::NotEqual => (a != b) as i32,
-                    Operator::GreaterEqual => (a >= b) as i32,
-                    Operator::LessEqual => (a <= b) as i32,
-                    Operator::And => ((a > 0) && (b > 0)) as i32,
-                    Operator::Or => ((a > 0) || (b > 0)) as i32,
-                })
+                let lv = self.eval_value(*l)?;
+                let rv = self.eval_value(*r)?;
+                match op {
+                    Operator::Addition => self.add_values(lv, rv),
+                    Operator::Greater
+                    | Operator::Less
+                    | Operator::GreaterEqual
+                    | Operator::LessEqual
+                    | Operator::Equal
+                    | Operator::NotEqual
+                    | Operator::Identical
+                    | Operator::NotIdentical => self.compare(op, lv, rv),
+                    _ => self.arith(op, lv, rv),
+                }
             }
 
-            other => panic!("eval_value(): unsupported AST variant: {:?}", other),
+            other => Err(self.error(format!(
+                "eval_value(): unsupported AST variant: {:?}",
+                other
+            ))),
         }
     }
 
+    /// Evaluates the arguments of a call, splitting off the named ones.
+    fn eval_args(&mut self, args: Vec<Node>) -> RxResult<(Vec<Type>, NamedArgs)> {
+        let mut vals = Vec::with_capacity(args.len());
+        let mut named = Vec::new();
+        for a in args {
+            match a.kind {
+                AST::NamedArg(name, value) => {
+                    let v = self.eval_value(*value)?;
+                    named.push((name, v));
+                }
+                _ => vals.push(self.eval_value(a)?),
+            }
+        }
+        Ok((vals, named))
+    }
+
     // =========================================================
     // Reactive capture utilities
     // =========================================================
 
-    pub(crate) fn ast_free_vars(&self, ast: &AST, out: &mut HashSet<String>) {
-        match ast {
+    pub(crate) fn ast_free_vars(&self, ast: &Node, out: &mut HashSet<String>) {
+        match &ast.kind {
             AST::Var(n) => {
                 out.insert(n.clone());
             }
@@ -210,9 +310,15 @@ impl VM {
                 self.ast_free_vars(b, out);
                 self.ast_free_vars(i, out);
             }
-            AST::FieldAccess(b, _) => {
+            AST::FieldAccess(b, _) | AST::Cast { expr: b, .. } | AST::Unary(_, b) => {
                 self.ast_free_vars(b, out);
             }
+            AST::Slice { base, start, end } => {
+                self.ast_free_vars(base, out);
+                for bound in start.iter().chain(end) {
+                    self.ast_free_vars(bound, out);
+                }
+            }
             AST::Ternary {
                 cond,
                 then_expr,
@@ -222,17 +328,44 @@ impl VM {
                 self.ast_free_vars(then_expr, out);
                 self.ast_free_vars(else_expr, out);
             }
-            AST::Call { args, .. } => {
+            AST::Call { name, args } => {
+                // the callee may be a local holding a function
+                out.insert(name.clone());
+                for a in args {
+                    self.ast_free_vars(a, out);
+                }
+            }
+            AST::NamedArg(_, value) => self.ast_free_vars(value, out),
+            AST::Template(parts) => {
+                for part in parts {
+                    if let TemplatePart::Value(value, _) = part {
+                        self.ast_free_vars(value, out);
+                    }
+                }
+            }
+            AST::ArrayNew(elems) | AST::ArrayLiteral(elems) => {
+                for e in elems {
+                    self.ast_free_vars(e, out);
+                }
+            }
+            AST::Invoke { callee, args } => {
+                self.ast_free_vars(callee, out);
+                for a in args {
+                    self.ast_free_vars(a, out);
+                }
+            }
+            AST::MemberCall { base, args, .. } => {
+                self.ast_free_vars(base, out);
                 for a in args {
                     self.ast_free_vars(a, out);
                 }
             }
-            AST::Number(_) | AST::Char(_) | AST::StringLiteral(_) => {}
+            AST::Number(_) | AST::Float(_) | AST::Char(_) | AST::StringLiteral(_) => {}
             _ => {}
         }
     }
 
-    pub(crate) fn capture_immutables_for_ast(&self, ast: &AST) -> HashMap<String, Type> {
+    pub(crate) fn capture_immutables_for_ast(&self, ast: &Node) -> HashMap<String, Type> {
         let mut names = HashSet::new();
         self.ast_free_vars(ast, &mut names);
 
@@ -245,34 +378,55 @@ impl VM {
         cap
     }
 
-    /// Freeze immutables that are integers by replacing Var(x) with Number(n) when x
-    /// resolves to an immutable integer in the current immutable stack.
-    pub(crate) fn freeze_ast(&self, ast: Box<AST>) -> Box<AST> {
-        match *ast {
-            AST::Var(name) => {
-                if let Some(Type::Integer(n)) = self.find_immutable(&name) {
-                    Box::new(AST::Number(*n))
-                } else {
-                    Box::new(AST::Var(name))
-                }
-            }
-            AST::Number(n) => Box::new(AST::Number(n)),
-            AST::Char(c) => Box::new(AST::Char(c)),
-            AST::Operation(l, o, r) => {
-                Box::new(AST::Operation(self.freeze_ast(l), o, self.freeze_ast(r)))
-            }
-            AST::Index(b, i) => Box::new(AST::Index(self.freeze_ast(b), self.freeze_ast(i))),
-            AST::FieldAccess(b, f) => Box::new(AST::FieldAccess(self.freeze_ast(b), f)),
+    /// Freeze immutables that are numbers by replacing Var(x) with Number(n)
+    /// or Float(f) when x resolves to an immutable number in the current
+    /// immutable stack.
+    pub(crate) fn freeze_ast(&self, ast: Node) -> Box<Node> {
+        let span = ast.span;
+        let kind = match ast.kind {
+            AST::Var(name) => match self.find_immutable(&name) {
+                Some(Type::Integer(n)) => AST::Number(*n),
+                Some(Type::Float(f)) => AST::Float(*f),
+                _ => AST::Var(name),
+            },
+            AST::Number(n) => AST::Number(n),
+            AST::Float(f) => AST::Float(f),
+            AST::Char(c) => AST::Char(c),
+            AST::Operation(l, o, r) => AST::Operation(self.freeze_ast(*l), o, self.freeze_ast(*r)),
+            AST::Index(b, i) => AST::Index(self.freeze_ast(*b), self.freeze_ast(*i)),
+            AST::Slice { base, start, end } => AST::Slice {
+                base: self.freeze_ast(*base),
+                start: start.map(|s| self.freeze_ast(*s)),
+                end: end.map(|e| self.freeze_ast(*e)),
+            },
+            AST::FieldAccess(b, f) => AST::FieldAccess(self.freeze_ast(*b), f),
+            AST::Template(parts) => AST::Template(
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        TemplatePart::Value(value, spec) => {
+                            TemplatePart::Value(*self.freeze_ast(value), spec)
+                        }
+                        text => text,
+                    })
+                    .collect(),
+            ),
+            AST::Cast { target, expr } => AST::Cast {
+                target,
+                expr: self.freeze_ast(*expr),
+            },
+            AST::Unary(op, expr) => AST::Unary(op, self.freeze_ast(*expr)),
             AST::Ternary {
                 cond,
                 then_expr,
                 else_expr,
-            } => Box::new(AST::Ternary {
-                cond: self.freeze_ast(cond),
-                then_expr: self.freeze_ast(then_expr),
-                else_expr: self.freeze_ast(else_expr),
-            }),
-            other => Box::new(other),
-        }
+            } => AST::Ternary {
+                cond: self.freeze_ast(*cond),
+                then_expr: self.freeze_ast(*then_expr),
+                else_expr: self.freeze_ast(*else_expr),
+            },
+            other => other,
+        };
+        Box::new(Node::new(kind, span))
     }
 }
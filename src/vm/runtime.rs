@@ -1,5 +1,10 @@
-use super::VM;
-use crate::grammar::{AST, LValue, StructFieldInit, StructInstance, Type};
+use super::display::format_float;
+use super::module::StructDef;
+use super::{NamedArgs, VM};
+use crate::error::RxResult;
+use crate::grammar::{
+    AST, CallArgs, CastType, LValue, Node, StructFieldInit, StructInstance, Type,
+};
 use std::collections::{HashMap, HashSet};
 
 impl VM {
@@ -7,156 +12,279 @@ impl VM {
     // Stack helpers
     // =========================================================
 
-    pub(crate) fn pop(&mut self) -> Type {
-        self.stack.pop().expect("stack underflow")
+    pub(crate) fn pop(&mut self) -> RxResult<Type> {
+        self.stack
+            .pop()
+            .ok_or_else(|| self.error("internal error: stack underflow"))
     }
 
-    pub(crate) fn pop_int(&mut self) -> i32 {
-        let v = self.pop();
-        self.as_int(v)
+    /// Pops the top `n` values, returning them in the order they were pushed.
+    pub(crate) fn pop_n(&mut self, n: usize) -> RxResult<Vec<Type>> {
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            values.push(self.pop()?);
+        }
+        values.reverse();
+        Ok(values)
     }
 
-    pub(crate) fn pop_args(&mut self, argc: usize) -> Vec<Type> {
-        let mut args = Vec::with_capacity(argc);
-        for _ in 0..argc {
-            args.push(self.pop());
-        }
-        args.reverse();
-        args
+    /// Pops the arguments of a call: the positional values, then the named ones.
+    pub(crate) fn pop_args(&mut self, call: &CallArgs) -> RxResult<(Vec<Type>, NamedArgs)> {
+        let mut args = self.pop_n(call.count)?;
+        let named = args.split_off(call.count - call.names.len());
+        Ok((args, call.names.iter().cloned().zip(named).collect()))
     }
 
     // =========================================================
     // Coercions / bounds
     // =========================================================
 
-    pub(crate) fn as_int(&mut self, v: Type) -> i32 {
-        match self.force(v) {
-            Type::Integer(n) => n,
-            Type::Char(c) => c as i32,
-            Type::ArrayRef(id) => self.array_heap[id].len() as i32,
-            other => panic!("type error: cannot coerce {:?} to int", other),
+    pub(crate) fn as_int(&mut self, v: Type) -> RxResult<i32> {
+        match self.force(v)? {
+            Type::Integer(n) => Ok(n),
+            Type::Char(c) => Ok(c as i32),
+            Type::Float(f) => Err(self.error(format!(
+                "type error: expected an integer, found float {}; use (int) to truncate it",
+                format_float(f)
+            ))),
+            other => match self.array_view(&other) {
+                Some((_, _, len)) => Ok(len as i32),
+                None => Err(self.error(format!(
+                    "type error: expected an integer, found {}",
+                    self.type_name(&other)
+                ))),
+            },
         }
     }
 
-    pub(crate) fn as_usize_nonneg(&mut self, v: Type, what: &str) -> usize {
-        let i = self.as_int(v);
-        if i < 0 {
-            panic!("{what} out of bounds: {i} is negative");
+    /// A number as a float, for arithmetic with at least one float operand.
+    pub(crate) fn as_float(&mut self, v: Type) -> RxResult<f64> {
+        match self.force(v)? {
+            Type::Float(f) => Ok(f),
+            other => Ok(self.as_int(other)? as f64),
         }
-        i as usize
     }
 
-    // =========================================================
-    // Printing
-    // =========================================================
+    /// Whether a condition holds: anything but `0` or `0.0`.
+    pub(crate) fn truthy(&mut self, v: Type) -> RxResult<bool> {
+        match self.force(v)? {
+            Type::Float(f) => Ok(f != 0.0),
+            other => Ok(self.as_int(other)? != 0),
+        }
+    }
 
-    pub(crate) fn print_value(&mut self, v: Type, newline: bool) {
-        match self.force(v) {
-            Type::Char(c) => {
-                print!("{}", char::from_u32(c).unwrap());
-            }
-            Type::Integer(n) => {
-                print!("{n}");
-            }
-            Type::ArrayRef(id) => {
-                // Attempt to treat as string (array of chars). If not, print length
-                let elems = self.array_heap[id].clone();
-                let mut all_chars = true;
-                let mut chars = Vec::with_capacity(elems.len());
-
-                for elem in elems {
-                    match self.force(elem) {
-                        Type::Char(c) => chars.push(c),
-                        _ => {
-                            all_chars = false;
-                            break;
-                        }
+    /// `(int) v`, `(float) v`, `(char) v` and `(str) v`. A float becomes an
+    /// integer by dropping its fraction.
+    pub(crate) fn cast(&mut self, target: CastType, v: Type) -> RxResult<Type> {
+        match target {
+            CastType::Int => match self.force(v)? {
+                Type::Float(f) => {
+                    let n = f.trunc();
+                    if !(i32::MIN as f64..=i32::MAX as f64).contains(&n) {
+                        return Err(self.error(format!(
+                            "float {} does not fit in an integer",
+                            format_float(f)
+                        )));
                     }
+                    Ok(Type::Integer(n as i32))
                 }
-
-                if all_chars {
-                    for c in chars {
-                        print!("{}", char::from_u32(c).unwrap());
-                    }
-                } else {
-                    print!("{}", self.array_heap[id].len());
+                other => Ok(Type::Integer(self.as_int(other)?)),
+            },
+            CastType::Float => Ok(Type::Float(self.as_float(v)?)),
+            CastType::Char => {
+                let n = self.as_int(v)?;
+                if !(0..=0x10FFFF).contains(&n) {
+                    return Err(self.error(format!("invalid char code {}", n)));
                 }
+                Ok(Type::Char(n as u32))
             }
-            other => panic!("cannot print value {:?}", other),
+            CastType::Str => self.cast_to_str(v),
         }
+    }
 
-        if newline {
-            println!();
+    pub(crate) fn as_usize_nonneg(&mut self, v: Type, what: &str) -> RxResult<usize> {
+        let i = self.as_int(v)?;
+        if i < 0 {
+            return Err(self.error(format!("{what} out of bounds: {i} is negative")));
         }
+        Ok(i as usize)
     }
 
     // =========================================================
     // Arrays
     // =========================================================
 
-    pub(crate) fn exec_array_new(&mut self) {
-        let size_val = self.pop();
-        let n = self.as_usize_nonneg(size_val, "array size");
-
+    pub(crate) fn alloc_array(&mut self, elems: Vec<Type>) -> Type {
         let id = self.array_heap.len();
-        self.array_heap.push(vec![Type::Integer(0); n]);
+        self.array_heap.push(elems);
         self.array_immutables.push(HashSet::new());
-        self.stack.push(Type::ArrayRef(id));
+        Type::ArrayRef(id)
     }
 
-    pub(crate) fn exec_array_get(&mut self) {
-        let idx_val = self.pop();
-        let idx = self.as_usize_nonneg(idx_val, "array index");
+    pub(crate) fn alloc_string(&mut self, s: &str) -> Type {
+        let chars = s.chars().map(|c| Type::Char(c as u32)).collect();
+        self.alloc_array(chars)
+    }
 
-        let arr_val = self.pop();
-        let arr = self.force(arr_val);
+    /// Where the elements of an array or slice live: the array id, the index
+    /// of the first element in it, and how many elements there are. A slice
+    /// whose array has shrunk since covers only the elements still there.
+    pub(crate) fn array_view(&self, v: &Type) -> Option<(usize, usize, usize)> {
+        match *v {
+            Type::ArrayRef(id) => Some((id, 0, self.array_heap[id].len())),
+            Type::ArraySlice { id, start, len } => {
+                let backing = self.array_heap[id].len();
+                let start = start.min(backing);
+                Some((id, start, len.min(backing - start)))
+            }
+            _ => None,
+        }
+    }
 
-        match arr {
-            Type::ArrayRef(id) => {
-                let len = self.array_heap[id].len();
-                if idx >= len {
-                    panic!("array index out of bounds: index {idx}, length {len}");
+    /// The current elements of an array or slice.
+    pub(crate) fn array_elems(&self, v: &Type) -> Vec<Type> {
+        match self.array_view(v) {
+            Some((id, start, len)) => self.array_heap[id]
+                .iter()
+                .skip(start)
+                .take(len)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Locates element `idx` of an array or slice as (array id, index).
+    pub(crate) fn array_element(&self, arr: &Type, idx: usize) -> RxResult<(usize, usize)> {
+        let Some((id, start, len)) = self.array_view(arr) else {
+            return Err(self.error(format!("type error: cannot index {}", self.type_name(arr))));
+        };
+        if idx >= len {
+            return Err(self.error(format!(
+                "array index out of bounds: index {idx}, length {len}"
+            )));
+        }
+        Ok((id, start + idx))
+    }
+
+    /// `arr[a..b]`: base, start and, unless it's left open, end are on the
+    /// stack.
+    pub(crate) fn exec_array_slice(&mut self, has_end: bool) -> RxResult<()> {
+        let end = if has_end { Some(self.pop()?) } else { None };
+        let start = self.pop()?;
+        let base = self.pop()?;
+        let slice = self.slice(base, start, end)?;
+        self.stack.push(slice);
+        Ok(())
+    }
+
+    /// A view of elements `start..end` of an array or slice. Reads and
+    /// writes through it reach the original elements.
+    pub(crate) fn slice(&mut self, base: Type, start: Type, end: Option<Type>) -> RxResult<Type> {
+        let arr = self.force(base)?;
+        let Some((id, offset, len)) = self.array_view(&arr) else {
+            return Err(self.error(format!("type error: cannot slice {}", self.type_name(&arr))));
+        };
+
+        let start = self.as_usize_nonneg(start, "slice start")?;
+        let end = match end {
+            Some(end) => self.as_usize_nonneg(end, "slice end")?,
+            None => len,
+        };
+        if start > end {
+            return Err(self.error(format!("slice start {start} is past its end {end}")));
+        }
+        if end > len {
+            return Err(self.error(format!("slice out of bounds: {start}..{end}, length {len}")));
+        }
+
+        Ok(Type::ArraySlice {
+            id,
+            start: offset + start,
+            len: end - start,
+        })
+    }
+
+    /// `[n]` or `[n][m]...`: the sizes are on the stack, outermost first.
+    pub(crate) fn exec_array_new(&mut self, dims: usize) -> RxResult<()> {
+        let sizes = self.pop_n(dims)?;
+        let mut dims = Vec::with_capacity(sizes.len());
+        for size_val in sizes {
+            dims.push(self.as_usize_nonneg(size_val, "array size")?);
+        }
+
+        let arr = self.alloc_zeroed(&dims);
+        self.stack.push(arr);
+        Ok(())
+    }
+
+    /// An array of zeros, or for several dimensions an array of separately
+    /// allocated subarrays.
+    pub(crate) fn alloc_zeroed(&mut self, dims: &[usize]) -> Type {
+        match dims {
+            [] => Type::Integer(0),
+            [n] => self.alloc_array(vec![Type::Integer(0); *n]),
+            [n, rest @ ..] => {
+                let mut elems = Vec::with_capacity(*n);
+                for _ in 0..*n {
+                    elems.push(self.alloc_zeroed(rest));
                 }
-                let elem = self.array_heap[id][idx].clone();
-                let f = self.force(elem);
-                self.stack.push(f);
+                self.alloc_array(elems)
             }
-            other => panic!("type error: attempted to index non-array value {:?}", other),
         }
     }
 
-    pub(crate) fn exec_store_index(&mut self, name: String) {
-        self.ensure_mutable_binding(&name);
+    /// `[a, b, c]`: the elements are on the stack in order.
+    pub(crate) fn exec_array_literal(&mut self, n: usize) -> RxResult<()> {
+        let mut elems = Vec::with_capacity(n);
+        for v in self.pop_n(n)? {
+            elems.push(self.force_to_storable(v)?);
+        }
+
+        let arr = self.alloc_array(elems);
+        self.stack.push(arr);
+        Ok(())
+    }
+
+    pub(crate) fn exec_array_get(&mut self) -> RxResult<()> {
+        let idx_val = self.pop()?;
+        let idx = self.as_usize_nonneg(idx_val, "array index")?;
+
+        let arr_val = self.pop()?;
+        let arr = self.force(arr_val)?;
+
+        let (id, index) = self.array_element(&arr, idx)?;
+        let elem = self.array_heap[id][index].clone();
+        let f = self.force(elem)?;
+        self.stack.push(f);
+        Ok(())
+    }
+
+    pub(crate) fn exec_store_index(&mut self, name: String) -> RxResult<()> {
+        self.ensure_mutable_binding(&name)?;
 
-        let val = self.pop();
+        let val = self.pop()?;
 
-        let idx_val = self.pop();
-        let idx = self.as_usize_nonneg(idx_val, "array index");
+        let idx_val = self.pop()?;
+        let idx = self.as_usize_nonneg(idx_val, "array index")?;
 
         let target = self
             .lookup_var(&name)
             .cloned()
-            .unwrap_or_else(|| panic!("undefined variable: {name}"));
+            .ok_or_else(|| self.error(format!("undefined variable: {name}")))?;
 
-        let arr = self.force(target);
+        let arr = self.force(target)?;
 
-        match arr {
-            Type::ArrayRef(id) => {
-                let len = self.array_heap[id].len();
-                if idx >= len {
-                    panic!("array assignment out of bounds: index {idx}, length {len}");
-                }
-                self.array_heap[id][idx] = val;
-            }
-            other => panic!("type error: StoreIndex on non-array {:?}", other),
-        }
+        let (id, index) = self.array_element(&arr, idx)?;
+        self.array_heap[id][index] = val;
+        Ok(())
     }
 
-    pub(crate) fn exec_store_index_reactive(&mut self, name: String, ast: Box<AST>) {
-        self.ensure_mutable_binding(&name);
+    pub(crate) fn exec_store_index_reactive(&mut self, name: String, ast: Node) -> RxResult<()> {
+        self.ensure_mutable_binding(&name)?;
 
-        let idx_val = self.pop();
-        let idx = self.as_usize_nonneg(idx_val, "array index");
+        let idx_val = self.pop()?;
+        let idx = self.as_usize_nonneg(idx_val, "array index")?;
 
         let frozen = self.freeze_ast(ast);
         let captured = self.capture_immutables_for_ast(&frozen);
@@ -164,110 +292,67 @@ impl VM {
         let target = self
             .lookup_var(&name)
             .cloned()
-            .unwrap_or_else(|| panic!("undefined variable: {name}"));
+            .ok_or_else(|| self.error(format!("undefined variable: {name}")))?;
 
-        let arr = self.force(target);
+        let arr = self.force(target)?;
 
-        match arr {
-            Type::ArrayRef(id) => {
-                let len = self.array_heap[id].len();
-                if idx >= len {
-                    panic!("reactive array assignment out of bounds: index {idx}, length {len}");
-                }
-                self.array_heap[id][idx] = Type::LazyValue(frozen, captured);
-            }
-            other => panic!("type error: StoreIndexReactive on non-array {:?}", other),
-        }
+        let (id, index) = self.array_element(&arr, idx)?;
+        self.array_heap[id][index] = Type::LazyValue(frozen, captured, self.current_module);
+        Ok(())
     }
 
     // =========================================================
     // LValues
     // =========================================================
 
-    pub(crate) fn read_lvalue(&mut self, lv: LValue) -> Type {
+    pub(crate) fn read_lvalue(&mut self, lv: LValue) -> RxResult<Type> {
         match lv {
             LValue::ArrayElem { array_id, index } => {
                 let len = self.array_heap[array_id].len();
                 if index >= len {
-                    panic!("array lvalue read out of bounds: index {index}, length {len}");
+                    return Err(self.error(format!(
+                        "array lvalue read out of bounds: index {index}, length {len}"
+                    )));
                 }
-                self.array_heap[array_id][index].clone()
+                Ok(self.array_heap[array_id][index].clone())
             }
             LValue::StructField { struct_id, field } => self.heap[struct_id]
                 .fields
                 .get(&field)
                 .cloned()
-                .unwrap_or_else(|| panic!("missing struct field `{field}`")),
+                .ok_or_else(|| self.error(format!("missing struct field `{field}`"))),
         }
     }
 
-    pub(crate) fn force_to_storable(&mut self, v: Type) -> Type {
+    pub(crate) fn force_to_storable(&mut self, v: Type) -> RxResult<Type> {
         match v {
             Type::LValue(lv) => {
-                let l = self.read_lvalue(lv);
+                let l = self.read_lvalue(lv)?;
                 self.force_to_storable(l)
             }
 
-            Type::LazyValue(_, _) => v, // keep relationships attached to locations
-            other => other,
+            Type::LazyValue(..) => Ok(v), // keep relationships attached to locations
+            other => Ok(other),
         }
     }
 
-    pub(crate) fn exec_array_lvalue(&mut self) {
-        let idx_val = self.pop();
-        let idx = self.as_usize_nonneg(idx_val, "array index");
+    pub(crate) fn exec_array_lvalue(&mut self) -> RxResult<()> {
+        let idx_val = self.pop()?;
+        let idx = self.as_usize_nonneg(idx_val, "array index")?;
 
-        let base = self.pop();
-        let base_val = self.force(base);
+        // forcing `a[i]` or `s.data` as a base reads the array stored there
+        let base = self.pop()?;
+        let arr = self.force(base)?;
 
-        match base_val {
-            Type::ArrayRef(id) => {
-                self.stack.push(Type::LValue(LValue::ArrayElem {
-                    array_id: id,
-                    index: idx,
-                }));
-            }
-
-            Type::LValue(LValue::ArrayElem { array_id, index }) => {
-                let nested_val = self.array_heap[array_id][index].clone();
-                let nested = self.force(nested_val);
-                match nested {
-                    Type::ArrayRef(nested_id) => {
-                        self.stack.push(Type::LValue(LValue::ArrayElem {
-                            array_id: nested_id,
-                            index: idx,
-                        }));
-                    }
-                    other => panic!("indexing non-array (found {:?})", other),
-                }
-            }
-
-            Type::LValue(LValue::StructField { struct_id, field }) => {
-                let field_val = self.heap[struct_id]
-                    .fields
-                    .get(&field)
-                    .cloned()
-                    .unwrap_or_else(|| panic!("missing struct field `{field}`"));
-
-                let arr_val = self.force(field_val);
-                match arr_val {
-                    Type::ArrayRef(array_id) => {
-                        self.stack.push(Type::LValue(LValue::ArrayElem {
-                            array_id,
-                            index: idx,
-                        }));
-                    }
-                    other => panic!("indexing non-array struct field (found {:?})", other),
-                }
-            }
-
-            other => panic!("invalid ArrayLValue base {:?}", other),
-        }
+        let (array_id, index) = self.array_element(&arr, idx)?;
+        self.stack
+            .push(Type::LValue(LValue::ArrayElem { array_id, index }));
+        Ok(())
     }
 
-    pub(crate) fn exec_field_lvalue(&mut self, field: String) {
-        let base = self.pop();
-        match self.force(base) {
+    pub(crate) fn exec_field_lvalue(&mut self, field: String) -> RxResult<()> {
+        let base = self.pop()?;
+        match self.force(base)? {
             Type::StructRef(id) => {
                 self.stack.push(Type::LValue(LValue::StructField {
                     struct_id: id,
@@ -276,7 +361,7 @@ impl VM {
             }
 
             Type::LValue(LValue::ArrayElem { array_id, index }) => {
-                let elem = self.force(self.array_heap[array_id][index].clone());
+                let elem = self.force(self.array_heap[array_id][index].clone())?;
                 match elem {
                     Type::StructRef(id) => {
                         self.stack.push(Type::LValue(LValue::StructField {
@@ -284,29 +369,40 @@ impl VM {
                             field,
                         }));
                     }
-                    other => panic!("FieldLValue on non-struct array element {:?}", other),
+                    other => {
+                        return Err(self.error(format!(
+                            "type error: cannot set field `{field}` on {}",
+                            self.type_name(&other)
+                        )));
+                    }
                 }
             }
 
-            other => panic!("invalid FieldLValue base {:?}", other),
+            other => {
+                return Err(self.error(format!(
+                    "type error: cannot set field `{field}` on {}",
+                    self.type_name(&other)
+                )));
+            }
         }
+        Ok(())
     }
 
-    pub(crate) fn exec_store_through(&mut self) {
-        let value = self.pop();
-        let target = self.pop();
+    pub(crate) fn exec_store_through(&mut self) -> RxResult<()> {
+        let value = self.pop()?;
+        let target = self.pop()?;
 
-        let stored = self.force_to_storable(value);
+        let stored = self.force_to_storable(value)?;
 
         match target {
             Type::LValue(LValue::ArrayElem { array_id, index }) => {
                 if self.array_immutables[array_id].contains(&index) {
-                    panic!("cannot reassign immutable array element");
+                    return Err(self.error("cannot reassign immutable array element"));
                 }
 
                 let len = self.array_heap[array_id].len();
                 if index >= len {
-                    panic!("array assignment out of bounds");
+                    return Err(self.error("array assignment out of bounds"));
                 }
 
                 self.array_heap[array_id][index] = stored;
@@ -316,25 +412,28 @@ impl VM {
                 let inst = &mut self.heap[struct_id];
 
                 if !inst.fields.contains_key(&field) {
-                    panic!("unknown struct field `{}`", field);
+                    return Err(self.error(format!("unknown struct field `{}`", field)));
                 }
 
                 if inst.immutables.contains(&field) {
-                    panic!("cannot assign to immutable field `{}`", field);
+                    return Err(self.error(format!("cannot assign to immutable field `{}`", field)));
                 }
 
                 inst.fields.insert(field, stored);
             }
 
-            other => panic!(
-                "internal error: StoreThrough target is not an lvalue (got {:?})",
-                other
-            ),
+            other => {
+                return Err(self.error(format!(
+                    "internal error: StoreThrough target is not an lvalue (got {})",
+                    self.type_name(&other)
+                )));
+            }
         }
+        Ok(())
     }
 
-    pub(crate) fn exec_store_through_reactive(&mut self, ast: Box<AST>) {
-        let target = self.pop();
+    pub(crate) fn exec_store_through_reactive(&mut self, ast: Node) -> RxResult<()> {
+        let target = self.pop()?;
 
         let frozen = self.freeze_ast(ast);
         let captured = self.capture_immutables_for_ast(&frozen);
@@ -342,43 +441,50 @@ impl VM {
         match target {
             Type::LValue(LValue::ArrayElem { array_id, index }) => {
                 if self.array_immutables[array_id].contains(&index) {
-                    panic!("cannot reassign immutable array element");
+                    return Err(self.error("cannot reassign immutable array element"));
                 }
 
                 let len = self.array_heap[array_id].len();
                 if index >= len {
-                    panic!("reactive array assignment out of bounds");
+                    return Err(self.error("reactive array assignment out of bounds"));
                 }
 
-                self.array_heap[array_id][index] = Type::LazyValue(frozen, captured);
+                self.array_heap[array_id][index] =
+                    Type::LazyValue(frozen, captured, self.current_module);
             }
 
             Type::LValue(LValue::StructField { struct_id, field }) => {
                 let inst = &mut self.heap[struct_id];
 
                 if !inst.fields.contains_key(&field) {
-                    panic!("unknown struct field `{}`", field);
+                    return Err(self.error(format!("unknown struct field `{}`", field)));
                 }
 
                 if inst.immutables.contains(&field) {
-                    panic!("cannot reassign immutable field `{}`", field);
+                    return Err(self.error(format!("cannot reassign immutable field `{}`", field)));
                 }
 
                 inst.immutables.insert(field.clone());
-                inst.fields.insert(field, Type::LazyValue(frozen, captured));
+                inst.fields.insert(
+                    field,
+                    Type::LazyValue(frozen, captured, self.current_module),
+                );
             }
 
-            other => panic!(
-                "StoreThroughReactive target is not an lvalue (got {:?})",
-                other
-            ),
+            other => {
+                return Err(self.error(format!(
+                    "internal error: StoreThroughReactive target is not an lvalue (got {})",
+                    self.type_name(&other)
+                )));
+            }
         }
+        Ok(())
     }
 
-    pub(crate) fn store_through_immutable(&mut self) {
-        let value = self.pop();
-        let target = self.pop();
-        let stored = self.force_to_storable(value);
+    pub(crate) fn store_through_immutable(&mut self) -> RxResult<()> {
+        let value = self.pop()?;
+        let target = self.pop()?;
+        let stored = self.force_to_storable(value)?;
 
         match target {
             Type::LValue(LValue::StructField { struct_id, field }) => {
@@ -386,8 +492,12 @@ impl VM {
 
                 match inst.fields.get(&field) {
                     Some(Type::Uninitialized) => {}
-                    Some(_) => panic!("cannot reassign immutable field `{}`", field),
-                    None => panic!("unknown struct field `{}`", field),
+                    Some(_) => {
+                        return Err(
+                            self.error(format!("cannot reassign immutable field `{}`", field))
+                        );
+                    }
+                    None => return Err(self.error(format!("unknown struct field `{}`", field))),
                 }
 
                 inst.fields.insert(field.clone(), stored);
@@ -398,97 +508,192 @@ impl VM {
                 let imm = &mut self.array_immutables[array_id];
 
                 if imm.contains(&index) {
-                    panic!("cannot reassign immutable array element");
+                    return Err(self.error("cannot reassign immutable array element"));
                 }
 
                 self.array_heap[array_id][index] = stored;
                 imm.insert(index);
             }
 
-            _ => panic!("immutable assignment only allowed on lvalues"),
+            _ => return Err(self.error("immutable assignment only allowed on lvalues")),
         }
+        Ok(())
     }
 
     // =========================================================
     // Structs
     // =========================================================
 
-    pub(crate) fn exec_field_get(&mut self, field: String) {
-        let obj = self.pop();
-        match self.force(obj) {
+    pub(crate) fn exec_field_get(&mut self, field: String) -> RxResult<()> {
+        let obj = self.pop()?;
+        match self.force(obj)? {
             Type::StructRef(id) => {
                 let v = self
                     .heap
                     .get(id)
-                    .unwrap_or_else(|| panic!("invalid StructRef id={id}"))
+                    .ok_or_else(|| self.error(format!("invalid StructRef id={id}")))?
                     .fields
                     .get(&field)
                     .cloned()
-                    .unwrap_or_else(|| panic!("missing struct field `{field}`"));
+                    .ok_or_else(|| self.error(format!("missing struct field `{field}`")))?;
 
                 if matches!(v, Type::Uninitialized) {
-                    panic!("use of uninitialized struct field `{}`", field);
+                    return Err(
+                        self.error(format!("use of uninitialized struct field `{}`", field))
+                    );
                 }
 
-                let out = self.force_struct_field(id, v);
+                let out = self.force_struct_field(id, v)?;
                 self.stack.push(out);
             }
-            other => panic!("type error: FieldGet on non-struct {:?}", other),
+            Type::Module(id) => {
+                let v = self.module_member(id, &field)?;
+                let out = self.force(v)?;
+                self.stack.push(out);
+            }
+            other => {
+                return Err(self.error(format!(
+                    "type error: cannot read field `{field}` of {}",
+                    self.type_name(&other)
+                )));
+            }
         }
+        Ok(())
     }
 
-    pub(crate) fn exec_field_set(&mut self, field: String) {
-        let val = self.pop();
-        let obj = self.pop();
+    pub(crate) fn exec_field_set(&mut self, field: String) -> RxResult<()> {
+        let val = self.pop()?;
+        let obj = self.pop()?;
 
-        let struct_id = match self.force(obj) {
+        let struct_id = match self.force(obj)? {
             Type::StructRef(id) => id,
-            other => panic!("type error: FieldSet on non-struct {:?}", other),
+            other => {
+                return Err(self.error(format!(
+                    "type error: cannot set field `{field}` on {}",
+                    self.type_name(&other)
+                )));
+            }
         };
 
         {
             let inst = &self.heap[struct_id];
 
             if !inst.fields.contains_key(&field) {
-                panic!("unknown struct field `{}`", field);
+                return Err(self.error(format!("unknown struct field `{}`", field)));
             }
 
             if inst.immutables.contains(&field) {
-                panic!("cannot assign to immutable field `{}`", field);
+                return Err(self.error(format!("cannot assign to immutable field `{}`", field)));
             }
         }
 
-        let stored = self.force_to_storable(val);
+        let stored = self.force_to_storable(val)?;
         self.heap[struct_id].fields.insert(field, stored);
+        Ok(())
     }
 
-    pub(crate) fn exec_field_set_reactive(&mut self, field: String, ast: Box<AST>) {
-        let obj = self.pop();
+    pub(crate) fn exec_field_set_reactive(&mut self, field: String, ast: Node) -> RxResult<()> {
+        let obj = self.pop()?;
 
-        match self.force(obj) {
+        match self.force(obj)? {
             Type::StructRef(id) => {
                 if self.heap[id].immutables.contains(&field) {
-                    panic!("cannot reactively assign to immutable field `{}`", field);
+                    return Err(self.error(format!(
+                        "cannot reactively assign to immutable field `{}`",
+                        field
+                    )));
                 }
                 let frozen = self.freeze_ast(ast);
                 let captured = self.capture_immutables_for_ast(&frozen);
-                self.heap[id]
-                    .fields
-                    .insert(field, Type::LazyValue(frozen, captured));
+                self.heap[id].fields.insert(
+                    field,
+                    Type::LazyValue(frozen, captured, self.current_module),
+                );
+            }
+            other => {
+                return Err(self.error(format!(
+                    "type error: cannot set field `{field}` on {}",
+                    self.type_name(&other)
+                )));
             }
-            other => panic!("type error: FieldSetReactive on non-struct {:?}", other),
         }
+        Ok(())
+    }
+
+    /// `struct Name { ... }`: the literal's field values are on the stack,
+    /// in the order of `names`.
+    pub(crate) fn exec_new_struct(&mut self, name: String, names: Vec<String>) -> RxResult<()> {
+        let values = self.pop_n(names.len())?;
+        let inst = self.new_struct(&name, names, values)?;
+        self.stack.push(inst);
+        Ok(())
+    }
+
+    /// An instance of struct `name` with the fields in `names` set to
+    /// `values` and the rest initialized from the definition.
+    pub(crate) fn new_struct(
+        &mut self,
+        name: &str,
+        names: Vec<String>,
+        values: Vec<Type>,
+    ) -> RxResult<Type> {
+        let def = self.lookup_struct(name)?;
+
+        let mut given = HashMap::new();
+        for (field, value) in names.into_iter().zip(values) {
+            match def.fields.iter().find(|(f, _)| f == &field) {
+                None => {
+                    return Err(self.error(format!("struct `{name}` has no field `{field}`")));
+                }
+                Some((_, Some(StructFieldInit::Reactive(_)))) => {
+                    return Err(self.error(format!(
+                        "field `{field}` of `{name}` is reactive and can't be set in a struct literal"
+                    )));
+                }
+                Some(_) => {}
+            }
+            let stored = self.force_to_storable(value)?;
+            given.insert(field, stored);
+        }
+
+        self.instantiate_struct(name, def, given)
     }
 
+    /// Creates an instance of `def`, running field initializers in the
+    /// module that declared the struct. Fields in `given` take that value
+    /// instead of running their initializer.
     pub(crate) fn instantiate_struct(
         &mut self,
-        fields: Vec<(String, Option<StructFieldInit>)>,
-    ) -> Type {
+        name: &str,
+        def: StructDef,
+        given: HashMap<String, Type>,
+    ) -> RxResult<Type> {
+        let saved_module = std::mem::replace(&mut self.current_module, def.module);
+        let result = self.instantiate_fields(name, def.fields, given);
+        self.current_module = saved_module;
+        result
+    }
+
+    fn instantiate_fields(
+        &mut self,
+        name: &str,
+        mut fields: Vec<(String, Option<StructFieldInit>)>,
+        mut given: HashMap<String, Type>,
+    ) -> RxResult<Type> {
         let mut map = HashMap::new();
         let mut imm = HashSet::new();
 
         // Initialize all declared fields
-        for (name, init) in &fields {
+        for (name, init) in &mut fields {
+            if let Some(value) = given.remove(name.as_str()) {
+                // a literal's value replaces the initializer, but not the field kind
+                if let Some(StructFieldInit::Immutable(_)) = init {
+                    imm.insert(name.clone());
+                }
+                map.insert(name.clone(), value);
+                *init = None;
+                continue;
+            }
             match init {
                 Some(StructFieldInit::Immutable(_)) => {
                     // immutable-with-initializer: the initializer will run later, but we want the slot
@@ -496,11 +701,12 @@ impl VM {
                     imm.insert(name.clone());
                     map.insert(name.clone(), Type::Uninitialized);
                 }
-                Some(StructFieldInit::Reactive(_)) => {
+                Some(StructFieldInit::Reactive(init)) => {
                     // reactive initializer stored later, slot exists now
+                    let zero = Node::new(AST::Number(0), init.span.clone());
                     map.insert(
                         name.clone(),
-                        Type::LazyValue(Box::new(AST::Number(0)), HashMap::new()),
+                        Type::LazyValue(Box::new(zero), HashMap::new(), self.current_module),
                     );
                 }
                 Some(StructFieldInit::Mutable(_)) => {
@@ -516,6 +722,9 @@ impl VM {
 
         let id = self.heap.len();
         self.heap.push(StructInstance {
+            // `ns.Name` prints as `Name`
+            name: name.rsplit('.').next().unwrap_or(name).to_string(),
+            field_order: fields.iter().map(|(field, _)| field.clone()).collect(),
             fields: map,
             immutables: imm.clone(),
         });
@@ -525,51 +734,49 @@ impl VM {
             if let Some(init) = init {
                 let value = match init {
                     StructFieldInit::Mutable(ast) | StructFieldInit::Immutable(ast) => {
-                        self.eval_reactive_field_in_struct(id, ast)
+                        self.eval_reactive_field_in_struct(id, ast)?
                     }
                     StructFieldInit::Reactive(ast) => {
                         let frozen = Box::new(ast);
-                        Type::LazyValue(frozen, HashMap::new())
+                        Type::LazyValue(frozen, HashMap::new(), self.current_module)
                     }
                 };
 
-                let stored = self.force_to_storable(value);
-                let cloned = self.clone_value(stored);
+                let stored = self.force_to_storable(value)?;
+                let cloned = self.clone_value(stored)?;
                 self.heap[id].fields.insert(name, cloned);
             }
         }
 
-        Type::StructRef(id)
+        Ok(Type::StructRef(id))
     }
 
-    pub(crate) fn eval_reactive_field_in_struct(&mut self, struct_id: usize, ast: AST) -> Type {
+    pub(crate) fn eval_reactive_field_in_struct(
+        &mut self,
+        struct_id: usize,
+        ast: Node,
+    ) -> RxResult<Type> {
         // Each evaluation creates a fresh immutable frame and binds all fields as LValues.
-        self.immutable_stack.push(HashMap::new());
-
-        {
-            let scope = self
-                .immutable_stack
-                .last_mut()
-                .expect("internal error: no immutable scope for struct eval");
-            let keys: Vec<String> = self.heap[struct_id].fields.keys().cloned().collect();
-            for key in keys {
-                scope.insert(
-                    key.clone(),
-                    Type::LValue(LValue::StructField {
-                        struct_id,
-                        field: key,
-                    }),
-                );
-            }
-        }
+        let scope = self.heap[struct_id]
+            .fields
+            .keys()
+            .map(|key| {
+                let field = Type::LValue(LValue::StructField {
+                    struct_id,
+                    field: key.clone(),
+                });
+                (key.clone(), field)
+            })
+            .collect();
+        self.immutable_stack.push(scope);
 
         let result = self.eval_value(ast);
         self.immutable_stack.pop();
         result
     }
 
-    pub(crate) fn clone_value(&mut self, v: Type) -> Type {
-        match v {
+    pub(crate) fn clone_value(&mut self, v: Type) -> RxResult<Type> {
+        Ok(match v {
             Type::ArrayRef(id) => {
                 let new_id = self.array_heap.len();
                 self.array_heap.push(self.array_heap[id].clone());
@@ -584,12 +791,16 @@ impl VM {
                 self.heap.push(inst);
                 Type::StructRef(new_id)
             }
-            Type::LazyValue(ast, captured) => Type::LazyValue(ast, captured),
+            Type::LazyValue(ast, captured, module) => Type::LazyValue(ast, captured, module),
             Type::Integer(n) => Type::Integer(n),
-            Type::Function { params, body } => Type::Function { params, body },
-            Type::LValue(_) => panic!("cannot clone lvalue"),
+            Type::Float(f) => Type::Float(f),
+            // a slice stays a view of the same elements
+            s @ Type::ArraySlice { .. } => s,
+            f @ Type::Function { .. } => f,
+            Type::Module(id) => Type::Module(id),
+            Type::LValue(_) => return Err(self.error("cannot clone lvalue")),
             Type::Char(c) => Type::Char(c),
             Type::Uninitialized => Type::Uninitialized,
-        }
+        })
     }
 }
@@ -0,0 +1,244 @@
+use super::display::format_float;
+use super::{NamedArgs, VM};
+use crate::arity::arity_message;
+use crate::error::RxResult;
+use crate::grammar::{Align, FormatKind, FormatSpec, Operator, Type};
+use std::cmp::Ordering;
+
+impl VM {
+    // =========================================================
+    // Strings
+    // =========================================================
+
+    /// The text of an array or slice whose elements are all chars, or
+    /// `None` for any other value. Reactive elements are forced.
+    pub(crate) fn string_value(&mut self, v: &Type) -> RxResult<Option<String>> {
+        if self.array_view(v).is_none() {
+            return Ok(None);
+        }
+
+        let mut text = String::new();
+        for elem in self.array_elems(v) {
+            match self.force(elem)? {
+                Type::Char(c) => {
+                    text.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(text))
+    }
+
+    pub(crate) fn expect_string(&mut self, v: &Type, what: &str) -> RxResult<String> {
+        let v = self.force(v.clone())?;
+        match self.string_value(&v)? {
+            Some(text) => Ok(text),
+            None => Err(self.error(format!(
+                "{what} must be a string (found {})",
+                self.type_name(&v)
+            ))),
+        }
+    }
+
+    /// `a + b`: two arrays (strings included) concatenate into a new array;
+    /// anything else adds as integers, failing on overflow.
+    pub(crate) fn add_values(&mut self, a: Type, b: Type) -> RxResult<Type> {
+        let a = self.force(a)?;
+        let b = self.force(b)?;
+
+        if self.array_view(&a).is_some() && self.array_view(&b).is_some() {
+            let mut elems = Vec::new();
+            for elem in self.array_elems(&a).into_iter().chain(self.array_elems(&b)) {
+                elems.push(self.force(elem)?);
+            }
+            return Ok(self.alloc_array(elems));
+        }
+
+        self.arith(Operator::Addition, a, b)
+    }
+
+    /// `(str) v`: a number as `print` shows it, a char as a one-char
+    /// string, or a copy of a string.
+    pub(crate) fn cast_to_str(&mut self, v: Type) -> RxResult<Type> {
+        let text = match self.force(v)? {
+            Type::Integer(n) => n.to_string(),
+            Type::Float(f) => format_float(f),
+            Type::Char(c) => char::from_u32(c).map(String::from).unwrap_or_default(),
+            other => self.expect_string(&other, "the operand of `(str)`")?,
+        };
+        Ok(self.alloc_string(&text))
+    }
+
+    /// `s.name(args)` for the string methods, and `parts.join(sep)`. Every
+    /// method leaves its receiver alone; those producing text return a new
+    /// string.
+    pub(crate) fn call_string_method(
+        &mut self,
+        receiver: Type,
+        name: &str,
+        args: Vec<Type>,
+        named: NamedArgs,
+    ) -> RxResult<Type> {
+        let expected = match name {
+            "trim" | "upper" | "lower" | "parse" => 0,
+            "find" | "split" | "join" | "compare" => 1,
+            _ => 2, // substring, replace
+        };
+        if !named.is_empty() {
+            return Err(self.error(format!("`{name}` takes no named arguments")));
+        }
+        if args.len() != expected {
+            return Err(self.error(arity_message(Some(name), expected, expected, args.len())));
+        }
+
+        if name == "join" {
+            return self.join_strings(&receiver, &args[0]);
+        }
+        let text = self.expect_string(&receiver, &format!("the receiver of `{name}`"))?;
+
+        match name {
+            "trim" => Ok(self.alloc_string(text.trim())),
+            "upper" => Ok(self.alloc_string(&text.to_uppercase())),
+            "lower" => Ok(self.alloc_string(&text.to_lowercase())),
+            "parse" => text
+                .trim()
+                .parse::<i32>()
+                .map(Type::Integer)
+                .map_err(|_| self.error(format!("cannot parse {text:?} as an integer"))),
+            "find" => {
+                let needle = self.expect_string(&args[0], "the argument of `find`")?;
+                // an index into the string, counted in chars
+                Ok(Type::Integer(match text.find(&needle) {
+                    Some(byte) => text[..byte].chars().count() as i32,
+                    None => -1,
+                }))
+            }
+            "compare" => {
+                let other = self.expect_string(&args[0], "the argument of `compare`")?;
+                Ok(Type::Integer(match text.chars().cmp(other.chars()) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                }))
+            }
+            "split" => {
+                let sep = self.expect_string(&args[0], "the separator of `split`")?;
+                if sep.is_empty() {
+                    return Err(self.error("`split` needs a non-empty separator"));
+                }
+                let parts = text
+                    .split(sep.as_str())
+                    .map(|p| self.alloc_string(p))
+                    .collect();
+                Ok(self.alloc_array(parts))
+            }
+            "replace" => {
+                let from = self.expect_string(&args[0], "the pattern of `replace`")?;
+                let to = self.expect_string(&args[1], "the replacement of `replace`")?;
+                if from.is_empty() {
+                    return Err(self.error("`replace` needs a non-empty pattern"));
+                }
+                Ok(self.alloc_string(&text.replace(&from, &to)))
+            }
+            _ => {
+                // a copy of `s[start..end]`
+                let view = self.slice(receiver, args[0].clone(), Some(args[1].clone()))?;
+                let substring = self.expect_string(&view, "the receiver of `substring`")?;
+                Ok(self.alloc_string(&substring))
+            }
+        }
+    }
+
+    /// `parts.join(sep)`: the strings in `parts` with `sep` between them.
+    fn join_strings(&mut self, parts: &Type, sep: &Type) -> RxResult<Type> {
+        let sep = self.expect_string(sep, "the separator of `join`")?;
+        let mut joined = Vec::new();
+        for part in self.array_elems(parts) {
+            let part = self.force(part)?;
+            joined.push(self.expect_string(&part, "each element joined")?);
+        }
+        Ok(self.alloc_string(&joined.join(&sep)))
+    }
+
+    // =========================================================
+    // Formatting
+    // =========================================================
+
+    /// A template hole's value as text: what `print` shows, or as `spec`
+    /// says.
+    pub(crate) fn format_value(&mut self, v: Type, spec: Option<&FormatSpec>) -> RxResult<String> {
+        let v = self.force(v)?;
+        let Some(spec) = spec else {
+            return self.display_value(v);
+        };
+
+        let (text, numeric) = match spec.kind {
+            FormatKind::Display => match (v, spec.precision) {
+                (v @ (Type::Integer(_) | Type::Float(_)), Some(digits)) => {
+                    (format!("{:.digits$}", self.as_float(v)?), true)
+                }
+                (v, Some(_)) => {
+                    return Err(self.error(format!(
+                        "a format precision needs a number (found {})",
+                        self.type_name(&v)
+                    )));
+                }
+                (v, None) => {
+                    let numeric = matches!(v, Type::Integer(_) | Type::Float(_));
+                    (self.display_value(v)?, numeric)
+                }
+            },
+            FormatKind::Int { radix, upper } => {
+                let n = self.as_int(v)?;
+                let m = n.unsigned_abs();
+                let digits = match (radix, upper) {
+                    (16, true) => format!("{m:X}"),
+                    (16, false) => format!("{m:x}"),
+                    (8, _) => format!("{m:o}"),
+                    (2, _) => format!("{m:b}"),
+                    _ => m.to_string(),
+                };
+                (if n < 0 { format!("-{digits}") } else { digits }, true)
+            }
+            FormatKind::Char => {
+                let n = self.as_int(v)?;
+                let c = u32::try_from(n)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error(format!("invalid char code {n}")))?;
+                (c.to_string(), false)
+            }
+        };
+
+        Ok(pad(text, spec, numeric))
+    }
+}
+
+/// Pads `text` to the spec's width. Numbers align right and others left
+/// unless told otherwise; `0` pads a number with zeros after its sign.
+fn pad(text: String, spec: &FormatSpec, numeric: bool) -> String {
+    let len = text.chars().count();
+    if len >= spec.width {
+        return text;
+    }
+    let missing = spec.width - len;
+
+    if spec.zero && numeric {
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text.as_str()),
+        };
+        return format!("{sign}{}{digits}", "0".repeat(missing));
+    }
+
+    let fill = if spec.zero { '0' } else { spec.fill };
+    let fill = |n: usize| fill.to_string().repeat(n);
+    match spec
+        .align
+        .unwrap_or(if numeric { Align::Right } else { Align::Left })
+    {
+        Align::Left => text + &fill(missing),
+        Align::Right => fill(missing) + &text,
+        Align::Center => fill(missing / 2) + &text + &fill(missing - missing / 2),
+    }
+}
@@ -0,0 +1,258 @@
+mod common;
+
+use common::{assert_fails, assert_prints, run, stderr, stdout};
+
+// =========================================================
+// Literals and allocation
+// =========================================================
+
+#[test]
+fn array_literals_and_sizes() {
+    let out = run("\
+println [2];
+println [2, 3, 5];
+println [[], [1,]];
+println [42,];
+println [[1, 2], [3, 4]][1][0];
+");
+    assert_prints(&out, "[0, 0]\n[2, 3, 5]\n[[], [1]]\n[42]\n3\n");
+}
+
+#[test]
+fn nested_allocation_gives_independent_rows() {
+    let out = run("\
+m = [2][3];
+m[1][2] = 5;
+println m;
+c = [2][1][2];
+println c;
+");
+    assert_prints(&out, "[[0, 0, 0], [0, 0, 5]]\n[[[0, 0]], [[0, 0]]]\n");
+}
+
+#[test]
+fn negative_size_is_an_error() {
+    assert_fails(
+        &run("a = [0 - 1];"),
+        "array size out of bounds: -1 is negative",
+    );
+}
+
+#[test]
+fn struct_literal_sets_fields() {
+    let out = run("\
+struct Rect {
+    w = 1;
+    h = 1;
+    id := 0;
+    area ::= w * h;
+}
+r := struct Rect { w: 3, h: 4, id: 7 };
+println r;
+r.w = 5;
+println r.area;
+");
+    assert_prints(&out, "Rect { w: 3, h: 4, id: 7, area: 12 }\n20\n");
+}
+
+#[test]
+fn struct_literal_keeps_field_kinds() {
+    let out = run("struct R { id := 0; }\nr := struct R { id: 7 };\nr.id = 1;");
+    assert_fails(&out, "immutable");
+}
+
+#[test]
+fn struct_literal_errors() {
+    assert_fails(
+        &run("struct R { w = 1; }\nr := struct R { z: 1 };"),
+        "struct `R` has no field `z`",
+    );
+    assert_fails(
+        &run("struct R { w = 1; a ::= w; }\nr := struct R { a: 1 };"),
+        "field `a` of `R` is reactive and can't be set in a struct literal",
+    );
+    assert_fails(&run("r := struct Nope;"), "unknown struct type `Nope`");
+}
+
+#[test]
+fn struct_literal_syntax_errors() {
+    let out = run("\
+struct V { x = 0; y = 0; }
+v := struct V { x: 1, x: 2 };
+w := struct V(3, 4);
+println v;
+");
+    assert_fails(&out, "field `x` is given more than once");
+    assert_fails(
+        &out,
+        "struct fields are given by name: write `struct V { field: value }`, not `struct V(...)`",
+    );
+    assert!(stderr(&out).contains("aborting due to 2 previous errors"));
+}
+
+#[test]
+fn struct_literal_in_reactive_relation() {
+    let out = run("\
+struct V { x = 0; y = 2; }
+func main() {
+    n = 1;
+    r ::= struct V { x: n };
+    println r;
+    n = 5;
+    println r.x;
+    bad ::= struct V { z: n };
+    println bad;
+}
+");
+    assert_fails(&out, "struct `V` has no field `z`");
+    assert_eq!(stdout(&out), "V { x: 1, y: 2 }\n5\n");
+}
+
+// =========================================================
+// Growing and shrinking
+// =========================================================
+
+#[test]
+fn methods_change_length_in_place() {
+    let out = run("\
+a = [];
+println a.push(1);
+println a.push(2);
+println a.insert(0, 9);
+println a;
+println a.remove(1);
+println a.pop();
+println a.resize(3);
+println a;
+b = a;
+b.push(4);
+println a.len();
+");
+    assert_prints(&out, "1\n2\n3\n[9, 1, 2]\n1\n2\n3\n[9, 0, 0]\n4\n");
+}
+
+#[test]
+fn elements_keep_their_kind_when_moved() {
+    let out = run("a = [1, 2];\na[0] := 5;\na.insert(0, 9);\nprintln a;\na[1] = 3;");
+    assert_fails(&out, "cannot reassign immutable array element");
+    assert_eq!(stdout(&out), "[9, 5, 2]\n");
+
+    let out = run("\
+func main() {
+    a = [1, 0];
+    a[1] ::= a[0] * 10;
+    a.insert(0, 7);
+    println a;
+    a.remove(0);
+    println a;
+}
+");
+    assert_prints(&out, "[7, 1, 70]\n[1, 10]\n");
+}
+
+#[test]
+fn std_containers_grow_when_full() {
+    let out = run("\
+import std.stack as st;
+import std.queue as qu;
+s := st.stack(1);
+q := qu.queue(1);
+for i in 0..5 { st.push(s, i); qu.enqueue(q, i); }
+println st.pop(s) + qu.dequeue(q);
+");
+    assert_prints(&out, "4\n");
+}
+
+#[test]
+fn growing_errors() {
+    assert_fails(&run("a = [];\na.pop();"), "pop from an empty array");
+    assert_fails(
+        &run("a = [1];\na.remove(3);"),
+        "array index out of bounds: index 3, length 1",
+    );
+    assert_fails(
+        &run("a = [1];\na.insert(3, 1);"),
+        "insert index out of bounds: index 3, length 1",
+    );
+    assert_fails(
+        &run("a = [1];\na.resize(0 - 1);"),
+        "array size out of bounds: -1 is negative",
+    );
+}
+
+// =========================================================
+// Slices
+// =========================================================
+
+#[test]
+fn slices_share_storage() {
+    let out = run("\
+func fill(s, v) {
+    for i in 0..s.len() {
+        s[i] = v;
+    }
+}
+
+func main() {
+    a := [1, 2, 3, 4, 5];
+    fill(a[2..], 0);
+    println a;
+
+    s := a[..2];
+    s[1] ::= a[0] * 10;
+    a[0] = 7;
+    println a[1];
+
+    t := a[1..4][1..];
+    t[0] = 9;
+    println \"{t} {t.len()} {a}\";
+    println \"hello world\"[6..];
+}
+");
+    assert_prints(
+        &out,
+        "[1, 2, 0, 0, 0]\n70\n[9, 0] 2 [7, 70, 9, 0, 0]\nworld\n",
+    );
+}
+
+#[test]
+fn slice_of_shrunk_array_covers_remaining_elements() {
+    let out = run("\
+func main() {
+    a = [1, 2, 3, 4, 5];
+    s = a[1..4];
+    a.pop();
+    a.pop();
+    println s.len();
+    println s;
+    println s[1];
+    println s == [2, 3];
+    a.resize(1);
+    println s.len();
+    println s;
+    println s[0];
+}
+");
+    assert_fails(&out, "array index out of bounds: index 0, length 0");
+    assert_eq!(stdout(&out), "2\n[2, 3]\n3\n1\n0\n\n");
+}
+
+#[test]
+fn slice_bounds_errors() {
+    assert_fails(
+        &run("a = [1, 2];\ns = a[1..3];"),
+        "slice out of bounds: 1..3, length 2",
+    );
+    assert_fails(
+        &run("a = [1, 2];\ns = a[2..1];"),
+        "slice start 2 is past its end 1",
+    );
+    assert_fails(
+        &run("a = [1, 2];\ns = a[..1];\ns[1] = 0;"),
+        "array index out of bounds: index 1, length 1",
+    );
+    assert_fails(
+        &run("a = [1, 2];\na[..1].push(3);"),
+        "cannot `push` a slice: only its array can change length",
+    );
+}
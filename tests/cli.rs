@@ -0,0 +1,165 @@
+mod common;
+
+use common::{Project, assert_fails, assert_prints, stderr, stdout};
+
+const ECHO: &str = "\
+func main(args) {
+    i = 0;
+    while i < args.len() {
+        println args[i];
+        i += 1;
+    }
+    return args.len();
+}
+";
+
+#[test]
+fn run_passes_arguments_and_returns_the_exit_status() {
+    let out = Project::new()
+        .file("echo.rx", ECHO)
+        .rx(&["run", "echo.rx", "a", "b c"]);
+    assert_eq!(stdout(&out), "a\nb c\n");
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn bare_path_is_short_for_run() {
+    let out = Project::new().file("echo.rx", ECHO).rx(&["echo.rx", "x"]);
+    assert_eq!(stdout(&out), "x\n");
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn program_in_another_directory() {
+    let project = Project::new().file("sub/dir/echo.rx", ECHO);
+    let path = project.dir.join("sub/dir/echo.rx");
+    let out = project.rx(&[path.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(0));
+}
+
+#[test]
+fn shebang_line_is_ignored() {
+    let out = Project::new()
+        .file("tool.rx", "#!/usr/bin/env rx\nprintln \"hello\";\n")
+        .rx(&["tool.rx"]);
+    assert_prints(&out, "hello\n");
+}
+
+#[test]
+fn check_does_not_run_anything() {
+    let project = Project::new().file("main.rx", "println \"side effect\";\n");
+    assert_prints(&project.rx(&["check", "main.rx"]), "");
+}
+
+#[test]
+fn check_rejects_program_arguments() {
+    let out = Project::new()
+        .file("main.rx", "")
+        .rx(&["check", "main.rx", "extra"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(stderr(&out).contains("`check` takes a single <path>"));
+}
+
+#[test]
+fn usage_errors_exit_with_status_2() {
+    let project = Project::new();
+    for args in [
+        &[][..],
+        &["run"],
+        &["run", "--bogus", "main.rx"],
+        &["run", "-I"],
+    ] {
+        let out = project.rx(args);
+        assert_eq!(out.status.code(), Some(2), "rx {args:?}");
+        assert!(stderr(&out).contains("usage: rx"), "rx {args:?}");
+    }
+}
+
+#[test]
+fn help_exits_successfully() {
+    let out = Project::new().rx(&["help"]);
+    assert!(out.status.success());
+    assert!(stdout(&out).starts_with("usage: rx"));
+}
+
+#[test]
+fn missing_file_is_an_error() {
+    assert_fails(
+        &Project::new().rx(&["run", "nope.rx"]),
+        "error: failed to read `nope.rx`",
+    );
+}
+
+#[test]
+fn main_with_too_many_parameters_is_an_error() {
+    let out = Project::new()
+        .file("main.rx", "func main(a, b) { }")
+        .rx(&["main.rx"]);
+    assert_fails(
+        &out,
+        "`main` must take no parameters or a single `args` array",
+    );
+}
+
+#[test]
+fn main_without_return_exits_with_status_0() {
+    let out = Project::new()
+        .file(
+            "main.rx",
+            "func f() { 5; }\nfunc main() { println f(); 3 + 4; }\n",
+        )
+        .rx(&["main.rx"]);
+    assert_prints(&out, "0\n");
+}
+
+#[test]
+fn script_return_sets_the_exit_status() {
+    let out = Project::new()
+        .file("main.rx", "println 1;\nif 1 { return 5; }\nprintln 2;\n")
+        .rx(&["main.rx"]);
+    assert_eq!(stdout(&out), "1\n");
+    assert_eq!(out.status.code(), Some(5));
+
+    let out = Project::new()
+        .file("main.rx", "func main() { return 1; }\nreturn 4;\n")
+        .rx(&["run", "--script", "main.rx"]);
+    assert_eq!(out.status.code(), Some(4));
+}
+
+#[test]
+fn exit_status_must_be_an_integer() {
+    let cases = [
+        (
+            "func main() { return \"x\"; }",
+            "`main` must give an integer exit status (found string)",
+        ),
+        (
+            "return 2.5;",
+            "a script's `return` must give an integer exit status (found float)",
+        ),
+    ];
+    for (src, message) in cases {
+        let out = Project::new().file("main.rx", src).rx(&["main.rx"]);
+        assert_fails(&out, message);
+    }
+}
+
+#[test]
+fn top_level_return_outside_a_script_is_a_compile_error() {
+    let out = Project::new()
+        .file("main.rx", "func main() { return 1; }\nreturn 4;\n")
+        .rx(&["main.rx"]);
+    assert_fails(
+        &out,
+        "`return` at the top level would skip `main`; return the exit status from `main` instead",
+    );
+
+    let out = Project::new()
+        .file("lib.rx", "return 3;\npub func g() { return 1; }\n")
+        .file("main.rx", "import lib;\nprintln g();\n")
+        .rx(&["main.rx"]);
+    assert_fails(
+        &out,
+        "`return` outside a function; an imported module's top level cannot return",
+    );
+}
@@ -0,0 +1,100 @@
+// Each test binary uses its own subset of these helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_PROJECT: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory holding the files of one test program, removed when
+/// dropped. `rx` runs with it as the working directory.
+pub struct Project {
+    pub dir: PathBuf,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        let n = NEXT_PROJECT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("rx-test-{}-{n}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create scratch directory");
+        Project { dir }
+    }
+
+    /// Writes `source` to `path`, relative to the project directory.
+    pub fn file(self, path: &str, source: &str) -> Self {
+        let path = self.dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create module directory");
+        }
+        fs::write(path, source).expect("write source file");
+        self
+    }
+
+    /// `rx` with `args`, without any `RX_PATH` from the environment.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rx"));
+        cmd.args(args).current_dir(&self.dir).env_remove("RX_PATH");
+        cmd
+    }
+
+    pub fn rx(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("run rx")
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Runs `source` as a program of its own.
+pub fn run(source: &str) -> Output {
+    Project::new()
+        .file("main.rx", source)
+        .rx(&["run", "main.rx"])
+}
+
+/// Runs `rx check` on `source`.
+pub fn check(source: &str) -> Output {
+    Project::new()
+        .file("main.rx", source)
+        .rx(&["check", "main.rx"])
+}
+
+pub fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+pub fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+/// The program ran without errors and printed exactly `expected`.
+#[track_caller]
+pub fn assert_prints(out: &Output, expected: &str) {
+    assert!(
+        out.status.success(),
+        "exit status {:?}\nstderr:\n{}",
+        out.status.code(),
+        stderr(out)
+    );
+    assert_eq!(stdout(out), expected);
+}
+
+/// The program failed with a diagnostic containing `message`, reported the
+/// way every user error is: exit status 1, no panic and no VM dump.
+#[track_caller]
+pub fn assert_fails(out: &Output, message: &str) {
+    let err = stderr(out);
+    assert_eq!(out.status.code(), Some(1), "stderr:\n{err}");
+    assert!(
+        err.contains(message),
+        "expected `{message}` in stderr:\n{err}"
+    );
+    assert!(!err.contains("panicked"), "rx panicked:\n{err}");
+    assert!(!err.contains("VM DEBUG"), "rx dumped its state:\n{err}");
+}
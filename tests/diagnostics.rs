@@ -0,0 +1,42 @@
+mod common;
+
+use common::{Project, assert_fails, check, run, stderr};
+
+#[test]
+fn syntax_error_points_at_the_token() {
+    let out = run("func main() {\n    x = (1, 2);\n}\n");
+    assert_fails(&out, "error: expected RParen, got Comma");
+    assert!(
+        stderr(&out).contains(" --> main.rx:2:11\n  |\n2 |     x = (1, 2);\n  |           ^\n")
+    );
+}
+
+#[test]
+fn tokenizer_error_points_at_the_character() {
+    let out = run("x = 1 @ 2;\n");
+    assert_fails(&out, "error: invalid char: @");
+    assert!(stderr(&out).contains(" --> main.rx:1:7\n"));
+}
+
+#[test]
+fn compile_error_underlines_the_statement() {
+    let out = check("func main() {\n    break;\n}\n");
+    assert_fails(&out, "error: break used outside of loop");
+    assert!(stderr(&out).contains("2 |     break;\n  |     ^^^^^\n"));
+}
+
+#[test]
+fn runtime_error_points_at_the_operator() {
+    let out = run("func main() {\n    println 1 / 0;\n}\n");
+    assert_fails(&out, "runtime error: division by zero in `1 / 0`");
+    assert!(stderr(&out).contains(" --> main.rx:2:15\n"));
+}
+
+#[test]
+fn error_in_imported_file_names_that_file() {
+    let out = Project::new()
+        .file("main.rx", "import util;\n")
+        .file("util.rx", "pub func f() {\n    return 1 +;\n}\n")
+        .rx(&["run", "main.rx"]);
+    assert_fails(&out, "util.rx:2:15");
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::{Project, assert_fails, assert_prints, run, stderr};
+use reactive_language::compiler::{EntryPoint, LabelGenerator, compile_program};
+use reactive_language::error::{RxError, RxResult};
+use reactive_language::linker::link;
+use reactive_language::loader::ModuleLoader;
+use reactive_language::parser::parse;
+use reactive_language::span::Source;
+use reactive_language::tokenizer::tokenize;
+use reactive_language::vm::VM;
+
+/// Runs `text` in-process, the way a host embedding the language would:
+/// every failure comes back as an `RxError` instead of a panic.
+fn eval(text: &str, entry: EntryPoint) -> RxResult<i32> {
+    let source = Source::new("embedded.rx", text);
+    let ast = parse(tokenize(&source)?)?;
+    let program = link(ast, &ModuleLoader::new())?;
+    let mut code = Vec::new();
+    compile_program(program, entry, &mut code, &mut LabelGenerator::new())?;
+    let mut vm = VM::new(code);
+    vm.run()?;
+    Ok(vm.exit_status())
+}
+
+#[test]
+fn tokenizer_error_is_a_compile_error() {
+    let err = eval("x = 'ab';", EntryPoint::Auto).unwrap_err();
+    assert!(
+        matches!(err, RxError::Compile { span: Some(_), .. }),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn parse_error_is_a_compile_error() {
+    let err = eval("func main( {", EntryPoint::Auto).unwrap_err();
+    assert!(
+        matches!(err.errors()[0], RxError::Compile { .. }),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn missing_main_is_a_compile_error() {
+    let err = eval("x = 1;", EntryPoint::Main).unwrap_err();
+    assert_eq!(err.message(), "no `main` function defined");
+}
+
+#[test]
+fn runtime_failure_is_a_runtime_error() {
+    let err = eval("func main() { a = [1]; return a[3]; }", EntryPoint::Auto).unwrap_err();
+    assert!(
+        matches!(err, RxError::Runtime { span: Some(_), .. }),
+        "{err:?}"
+    );
+    assert_eq!(
+        err.message(),
+        "array index out of bounds: index 3, length 1"
+    );
+}
+
+#[test]
+fn host_keeps_running_after_an_error() {
+    assert!(eval("func main() { return nope; }", EntryPoint::Auto).is_err());
+    assert_eq!(
+        eval("func main() { return 7; }", EntryPoint::Auto).unwrap(),
+        7
+    );
+}
+
+#[test]
+fn runaway_recursion_is_a_runtime_error() {
+    let message = "stack overflow: more than 1000 nested calls or reactive evaluations";
+    assert_fails(&run("func f(n) { return f(n + 1); }\nf(0);"), message);
+    assert_fails(&run("x ::= x + 1;\nprintln x;"), message);
+    assert_prints(
+        &run("func f(n) { if n == 0 { return 0; } return f(n - 1); }\nprintln f(900);"),
+        "0\n",
+    );
+}
+
+#[test]
+fn user_errors_do_not_dump_vm_state() {
+    assert_fails(
+        &run("func main() { r ::= nope + 1; println r; }"),
+        "undefined variable: nope",
+    );
+    assert_fails(
+        &run("func f(n = nope) { return n; }\nprintln f();"),
+        "undefined variable: nope",
+    );
+}
+
+#[test]
+fn type_errors_name_the_type() {
+    let cases = [
+        (
+            "f = func() { return 1; };\nprintln f == f;",
+            "type error: expected an integer, found function",
+        ),
+        (
+            "x = 3;\nprintln x.y;",
+            "type error: cannot read field `y` of int",
+        ),
+        (
+            "struct P { x = 1; }\np := struct P;\nprintln p[0];",
+            "type error: cannot index struct P",
+        ),
+        (
+            "x = 1.5;\nx.y = 2;",
+            "type error: cannot set field `y` on float",
+        ),
+        (
+            "println 'a'.up();",
+            "call error: cannot call `.up(...)` on char",
+        ),
+        (
+            "f = [1];\nf();",
+            "call error: `f` is not a function (found array)",
+        ),
+        (
+            r#"println ["a", [1]].join(", ");"#,
+            "each element joined must be a string (found array)",
+        ),
+        (
+            "s = \"ab\";\nprintln \"{s:.2}\";",
+            "a format precision needs a number (found string)",
+        ),
+    ];
+    for (src, message) in cases {
+        let out = run(src);
+        assert_fails(&out, message);
+        assert!(!stderr(&out).contains("Ref("), "{}", stderr(&out));
+    }
+}
+
+#[test]
+fn debug_flag_dumps_vm_state() {
+    let out = Project::new()
+        .file("main.rx", "func main() { r ::= nope + 1; println r; }")
+        .rx(&["run", "--debug", "main.rx"]);
+    assert!(stderr(&out).contains("VM DEBUG"));
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{assert_fails, assert_prints, run};
+
+#[test]
+fn float_literals_and_promotion() {
+    let out = run("\
+println 3 / 2;
+println 3 / 2.0;
+println 1.0;
+println 0.1 + 0.2;
+println 2.5 * 4;
+println 7.5 % 2;
+println 2.0 ** 0.5;
+big = 1000000000000000.0 * 1000000000000000.0;
+println big;
+n = 0;
+for i in 1..3 {
+    n = n + i;
+}
+println n;
+");
+    assert_prints(
+        &out,
+        "1\n1.5\n1.0\n0.30000000000000004\n10.0\n1.5\n1.4142135623730951\n1e30\n3\n",
+    );
+}
+
+#[test]
+fn casts_and_comparisons() {
+    let out = run("\
+println (float) 3;
+println (float) 'A';
+println (int) 2.9;
+println (int)(-2.9);
+println 1 == 1.0;
+println 2.5 > 2;
+n = (-1.0) ** 0.5;
+println n;
+println n == n;
+println n != n;
+println n < 1.0;
+println 0.0 ? 1 : 2;
+z = 0.0;
+println -z;
+println -z == z;
+");
+    assert_prints(&out, "3.0\n65.0\n2\n-2\n1\n1\nNaN\n0\n1\n0\n2\n-0.0\n1\n");
+}
+
+#[test]
+fn floats_in_reactive_relations() {
+    let out = run(r#"
+struct Body {
+    x = 0.0;
+    v = 1.5;
+    next ::= x + v * 0.5;
+}
+g := 9.81;
+t = 2;
+fall ::= 0.5 * g * t * t;
+println fall;
+t = 3;
+println "{fall:.1}";
+b = struct Body;
+b.x = 10;
+println b.next;
+f = 0.0;
+r ::= f ? 1 : 2;
+println r;
+f = 0.5;
+println r;
+"#);
+    assert_prints(&out, "19.62\n44.1\n10.75\n2\n1\n");
+}
+
+#[test]
+fn std_maths_roots() {
+    let out = run("\
+import std.maths;
+println sqrt(16.0);
+println sqrt(2.0);
+println sqrt(10.0 ** 400);
+println sqrt(0 - 4);
+println isqrt(17);
+println float_sqrt(0.0 - 1);
+");
+    assert_prints(&out, "4.0\n1.4142135623730951\ninf\n0.0\n4\nNaN\n");
+}
+
+#[test]
+fn float_errors() {
+    let cases = [
+        (
+            "a = [1, 2];\nprintln a[1.0];",
+            "expected an integer, found float 1.0; use (int) to truncate it",
+        ),
+        (
+            "println [2.5];",
+            "expected an integer, found float 2.5; use (int) to truncate it",
+        ),
+        (
+            "println (int)(100000.0 * 100000.0);",
+            "float 10000000000.0 does not fit in an integer",
+        ),
+        (
+            "n = (-1.0) ** 0.5;\nprintln (int) n;",
+            "float NaN does not fit in an integer",
+        ),
+        ("println 1.5 & 1;", "`&` needs integers, not floats"),
+        ("println 1.5 << 1;", "`<<` needs integers, not floats"),
+        ("println ~1.5;", "`~` needs integers, not floats"),
+        ("println 1.5 ^ 1;", "`^` needs integers, not floats"),
+        ("println 1.0 / 0;", "division by zero in `1.0 / 0.0`"),
+        ("println 1 % 0.0;", "division by zero in `1.0 % 0.0`"),
+    ];
+    for (src, message) in cases {
+        assert_fails(&run(src), message);
+    }
+}
@@ -0,0 +1,246 @@
+mod common;
+
+use common::{Project, assert_fails, assert_prints, check, run, stdout};
+use reactive_language::arity::check_arity;
+use reactive_language::linker::{LinkedModule, Program};
+use reactive_language::parser::parse;
+use reactive_language::span::Source;
+use reactive_language::tokenizer::tokenize;
+use std::collections::HashMap;
+
+// =========================================================
+// Closures
+// =========================================================
+
+#[test]
+fn closure_captures_its_environment() {
+    let out = run("\
+func make_adder(n) {
+    return func(x) { return x + n; };
+}
+add3 := make_adder(3);
+println add3(4);
+println make_adder(10)(5);
+");
+    assert_prints(&out, "7\n15\n");
+}
+
+#[test]
+fn captures_are_snapshots() {
+    let out = run("\
+func main() {
+    n = 1;
+    f := func() { return n; };
+    n = 2;
+    println f();
+}
+");
+    assert_prints(&out, "1\n");
+}
+
+#[test]
+fn shared_state_lives_in_structs() {
+    let out = run("\
+struct Button { clicks = 0; handler = 0; }
+b := struct Button;
+b.handler = func(n) { b.clicks = b.clicks + n; return b.clicks; };
+b.handler(1);
+println b.handler(2);
+");
+    assert_prints(&out, "3\n");
+}
+
+#[test]
+fn functions_in_arrays_and_as_arguments() {
+    let out = run("\
+func apply(f, x) { return f(x); }
+func double(x) { return x * 2; }
+ops := [double, func(x) { return x + 1; }];
+println ops[0](5) + ops[1](5);
+println apply(double, 4);
+");
+    assert_prints(&out, "16\n8\n");
+}
+
+#[test]
+fn nested_named_function_recurses() {
+    let out = run("\
+func sum_to(n) {
+    func go(i) {
+        if i > n { return 0; }
+        return i + go(i + 1);
+    }
+    return go(1);
+}
+println sum_to(4);
+");
+    assert_prints(&out, "10\n");
+}
+
+#[test]
+fn calling_a_non_function_is_an_error() {
+    assert_fails(&run("x := 3;\nx(1);"), "`x` is not a function");
+    assert_fails(&run("a := [1];\na[0](1);"), "value is not a function");
+}
+
+// =========================================================
+// Arity
+// =========================================================
+
+#[test]
+fn static_arity_errors_are_reported_before_running() {
+    let cases = [
+        (
+            "func f(a, b) { return a + b; }\nprintln f(1);",
+            "`f` expects 2 arguments but was given 1",
+        ),
+        (
+            "func f(a, b = 1) { return a + b; }\nfunc main() { println f(1, 2, 3); }",
+            "`f` expects 1 to 2 arguments but was given 3",
+        ),
+        (
+            "func f(x) { return x; }\nprintln f(y: 1);",
+            "`f` has no parameter named `y`",
+        ),
+        (
+            "func f(x) { return x; }\nprintln f(1, x: 2);",
+            "`f` was given two values for parameter `x`",
+        ),
+        (
+            "func f(x, y) { return x; }\nprintln f(y: 2);",
+            "missing argument `x` in call to `f`",
+        ),
+    ];
+    for (source, message) in cases {
+        let source = format!("println \"side effect\";\n{source}");
+        assert_fails(&check(&source), message);
+        let out = run(&source);
+        assert_fails(&out, message);
+        assert_eq!(stdout(&out), "", "{source}");
+    }
+}
+
+#[test]
+fn arity_of_imported_functions_is_checked_statically() {
+    let project = Project::new()
+        .file("m.rx", "pub func add(a, b) { return a + b; }\n")
+        .file("by_name.rx", "import m (add);\nprintln add(1);\n")
+        .file(
+            "by_namespace.rx",
+            "import m as x;\nprintln x.add(1, 2, 3);\n",
+        );
+    assert_fails(
+        &project.rx(&["check", "by_name.rx"]),
+        "`add` expects 2 arguments but was given 1",
+    );
+    assert_fails(
+        &project.rx(&["check", "by_namespace.rx"]),
+        "`add` expects 2 arguments but was given 3",
+    );
+}
+
+#[test]
+fn calls_through_values_are_checked_at_runtime() {
+    let source = "g := func(x) { return x; };\nprintln g(1, 2);";
+    assert_prints(&check(source), "");
+    assert_fails(
+        &run(source),
+        "anonymous function expects 1 argument but was given 2",
+    );
+    assert_fails(
+        &run("func f(a) { return a; }\nh := f;\nprintln h();"),
+        "`f` expects 1 argument but was given 0",
+    );
+}
+
+#[test]
+fn local_signature_wins_over_an_imported_one() {
+    // the linker reports the collision itself; the arity pass must not
+    // check the local call against the import's signature
+    let module = |id: usize, name: &str, text: &str, imports: &[(&str, usize)]| LinkedModule {
+        id,
+        name: name.to_string(),
+        ast: parse(tokenize(&Source::new(format!("{name}.rx"), text)).unwrap()).unwrap(),
+        imports: imports
+            .iter()
+            .map(|&(path, id)| (vec![path.to_string()], id))
+            .collect::<HashMap<_, _>>(),
+    };
+    let program = Program {
+        modules: vec![
+            module(1, "a", "pub func add(a, b) { return a + b; }", &[]),
+            module(
+                0,
+                "",
+                "import a;\nfunc add(x) { return x; }\nprintln add(1);",
+                &[("a", 1)],
+            ),
+        ],
+    };
+    assert!(check_arity(&program).is_ok());
+}
+
+// =========================================================
+// Default and named arguments
+// =========================================================
+
+#[test]
+fn defaults_fill_in_missing_arguments() {
+    let out = run("\
+func box(w, h = w, depth = 1) { return w * h * depth; }
+println box(2);
+println box(2, 3);
+println box(2, 3, 4);
+");
+    assert_prints(&out, "4\n6\n24\n");
+}
+
+#[test]
+fn default_can_be_any_expression() {
+    let out = run("\
+struct S { x = 1; }
+func f(s = struct S { x: 4 }, n = s.x * 2, g = func(y) { return y + n; }, a = [n, n]) {
+    return g(s.x) + a.len();
+}
+println f();
+println f(struct S);
+");
+    assert_prints(&out, "14\n5\n");
+}
+
+#[test]
+fn default_is_evaluated_at_each_call() {
+    let out = run("\
+func add(x, to = []) { to.push(x); return to; }
+add(1);
+println add(2);
+");
+    assert_prints(&out, "[2]\n");
+}
+
+#[test]
+fn default_is_skipped_when_argument_is_given() {
+    let out = run("func f(n = nope) { return n; }\nprintln f(3);\nprintln f(n: 4);\n");
+    assert_prints(&out, "3\n4\n");
+}
+
+#[test]
+fn named_arguments_in_any_order() {
+    let out = run("\
+import std.vector2;
+v := vec2full(vy: 2, x: 1);
+println \"{v.x} {v.y} {v.vx} {v.vy}\";
+func f(a, b = 10, c = 20) { return a * 100 + b * 10 + c; }
+println f(1, c: 3);
+");
+    assert_prints(&out, "1 0 0 2\n203\n");
+}
+
+#[test]
+fn undefined_name_in_default_is_a_plain_error() {
+    let out = run("func h(n = missing) { return n; }\nprintln h();\n");
+    assert_fails(
+        &out,
+        "runtime error: undefined variable: missing\n --> main.rx:1:12",
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{assert_fails, assert_prints, check, run};
+
+#[test]
+fn while_loop_runs_while_condition_holds() {
+    let out = run("i = 0;\nwhile i < 3 { print i; i += 1; }\nprintln '.';\n");
+    assert_prints(&out, "012.\n");
+}
+
+#[test]
+fn for_loop_counts_up_to_exclusive_end() {
+    let out = run("for i in 2..5 { print i; }\nfor i in 3..3 { print 9; }\nprintln '.';\n");
+    assert_prints(&out, "234.\n");
+}
+
+#[test]
+fn for_bounds_are_evaluated_once() {
+    let out = run("n = 3;\nfor i in 0..n { n = 10; print i; }\nprintln '.';\n");
+    assert_prints(&out, "012.\n");
+}
+
+#[test]
+fn for_variable_is_immutable() {
+    let out = run("for i in 0..3 { i = 5; }");
+    assert_fails(&out, "cannot assign to immutable variable `i`");
+}
+
+#[test]
+fn continue_skips_to_next_iteration() {
+    let out = run("for i in 0..5 { if i % 2 == 0 { continue; } print i; }\nprintln '.';\n");
+    assert_prints(&out, "13.\n");
+}
+
+#[test]
+fn labeled_continue_and_break_target_outer_loop() {
+    let out = run("\
+'rows: for r in 0..3 {
+    for c in 0..3 {
+        if c > r { continue 'rows; }
+        print r * 10 + c;
+        print ' ';
+    }
+}
+println '.';
+'outer: loop {
+    while 1 { break 'outer; }
+    println \"unreachable\";
+}
+println \"done\";
+");
+    assert_prints(&out, "0 10 11 20 21 22 .\ndone\n");
+}
+
+#[test]
+fn immutable_bindings_are_fresh_each_iteration() {
+    let out = run("for i in 0..3 { x := i * 2; print x; }\nprintln '.';\n");
+    assert_prints(&out, "024.\n");
+}
+
+#[test]
+fn loop_control_outside_a_loop_is_an_error() {
+    assert_fails(&check("break;"), "break used outside of loop");
+    assert_fails(&check("continue;"), "continue used outside of loop");
+    assert_fails(
+        &check("loop { break 'nope; }"),
+        "unknown loop label `'nope`",
+    );
+    assert_fails(
+        &check("func f() { loop { g := func() { break; }; } }"),
+        "break used outside of loop",
+    );
+}
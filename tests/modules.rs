@@ -0,0 +1,387 @@
+mod common;
+
+use common::{Project, assert_fails, assert_prints, stderr, stdout};
+use reactive_language::compiler::{EntryPoint, LabelGenerator, compile_program};
+use reactive_language::error::RxError;
+use reactive_language::linker::{LinkedModule, Program};
+use reactive_language::parser::parse;
+use reactive_language::resolve::check_names;
+use reactive_language::span::Source;
+use reactive_language::tokenizer::tokenize;
+use reactive_language::vm::VM;
+use std::collections::HashMap;
+
+// =========================================================
+// Search path
+// =========================================================
+
+#[test]
+fn import_resolves_next_to_the_importing_file() {
+    let out = Project::new()
+        .file("app/main.rx", "import lib.util;\nprintln util.twice(4);\n")
+        .file(
+            "app/lib/util.rx",
+            "import helper;\npub func twice(x) { return helper.add(x, x); }\n",
+        )
+        .file(
+            "app/lib/helper.rx",
+            "pub func add(a, b) { return a + b; }\n",
+        )
+        .rx(&["run", "app/main.rx"]);
+    assert_prints(&out, "8\n");
+}
+
+#[test]
+fn import_resolves_through_search_path_flag() {
+    let out = Project::new()
+        .file("app/main.rx", "import shapes;\nprintln shapes.SIDES;\n")
+        .file("vendor/shapes.rx", "pub SIDES := 4;\n")
+        .rx(&["run", "-I", "vendor", "app/main.rx"]);
+    assert_prints(&out, "4\n");
+}
+
+#[test]
+fn import_resolves_through_rx_path() {
+    let project = Project::new()
+        .file("app/main.rx", "import shapes;\nprintln shapes.SIDES;\n")
+        .file("lib/a/shapes.rx", "pub SIDES := 3;\n");
+    let roots =
+        std::env::join_paths([project.dir.join("lib/none"), project.dir.join("lib/a")]).unwrap();
+    let out = project
+        .command(&["run", "app/main.rx"])
+        .env("RX_PATH", roots)
+        .output()
+        .unwrap();
+    assert_prints(&out, "3\n");
+}
+
+#[test]
+fn missing_module_fails_before_running() {
+    let out = Project::new()
+        .file(
+            "app/main.rx",
+            "println \"side effect\";\nimport nope.gone;\n",
+        )
+        .rx(&["run", "app/main.rx"]);
+    assert_fails(&out, "could not find module `nope.gone` (searched: app)");
+    assert_eq!(stdout(&out), "");
+}
+
+// =========================================================
+// Standard library
+// =========================================================
+
+#[test]
+fn std_is_embedded_in_the_binary() {
+    let out = Project::new()
+        .file(
+            "main.rx",
+            "import std.maths;\nimport std.stack as st;\ns := st.stack(4);\nst.push(s, maths.abs(-3));\nprintln st.pop(s);\n",
+        )
+        .rx(&["run", "main.rx"]);
+    assert_prints(&out, "3\n");
+}
+
+#[test]
+fn local_std_module_overrides_embedded_one() {
+    let out = Project::new()
+        .file("main.rx", "import std.maths;\nprintln maths.abs(-3);\n")
+        .file("std/maths.rx", "pub func abs(x) { return 42; }\n")
+        .rx(&["run", "main.rx"]);
+    assert_prints(&out, "42\n");
+}
+
+#[test]
+fn unknown_std_module_is_an_error() {
+    let out = Project::new()
+        .file("main.rx", "import std.nope;\n")
+        .rx(&["run", "main.rx"]);
+    assert_fails(&out, "could not find module `std.nope`");
+}
+
+// =========================================================
+// Namespaces
+// =========================================================
+
+const A: &str = "pub func add(a, b) { return a + b; }\npub K := 10;\npub struct P { x = 1; }\n";
+const B: &str = "pub func add(a, b) { return a * b; }\n";
+
+fn with_modules(main: &str) -> Project {
+    Project::new()
+        .file("main.rx", main)
+        .file("a.rx", A)
+        .file("b.rx", B)
+}
+
+#[test]
+fn whole_module_import_binds_namespace_and_items() {
+    let out = with_modules("import a;\nprintln add(1, 2) + a.add(3, 4) + a.K + K;\n")
+        .rx(&["run", "main.rx"]);
+    assert_prints(&out, "30\n");
+}
+
+#[test]
+fn aliased_imports_keep_same_names_apart() {
+    let out = with_modules(
+        "import a as x;\nimport b as y;\nfunc add(p) { return p; }\nprintln add(1) + x.add(2, 3) + y.add(2, 3);\np := struct x.P { x: 5 };\nprintln p.x;\n",
+    )
+    .rx(&["run", "main.rx"]);
+    assert_prints(&out, "12\n5\n");
+}
+
+#[test]
+fn selective_import_binds_only_listed_items() {
+    let out =
+        with_modules("import a (K);\nprintln K;\nprintln add(1, 2);\n").rx(&["run", "main.rx"]);
+    assert_fails(&out, "`add` is not defined");
+}
+
+/// A collision is reported by `rx check`, and by `rx run` before any
+/// statement runs.
+#[track_caller]
+fn assert_collision(main: &str, message: &str) {
+    let project = with_modules(&format!("println \"side effect\";\n{main}"));
+    assert_fails(&project.rx(&["check", "main.rx"]), message);
+    let out = project.rx(&["run", "main.rx"]);
+    assert_fails(&out, message);
+    assert_eq!(stdout(&out), "");
+}
+
+#[test]
+fn same_name_from_two_imports_collides() {
+    assert_collision(
+        "import a;\nimport b;\n",
+        "`add` is imported from both `a` and `b`; import one of them with `as`",
+    );
+    assert_collision(
+        "import a (add);\nimport b (add);\n",
+        "`add` is imported from both `a` and `b`",
+    );
+}
+
+#[test]
+fn local_item_after_import_collides() {
+    assert_collision(
+        "import a;\nfunc add(x) { return x; }\nprintln add(1);\n",
+        "`add` is already imported from `a`; import it with `as` to keep both",
+    );
+    assert_collision("import a;\nK := 1;\n", "`K` is already imported from `a`");
+}
+
+#[test]
+fn import_after_local_item_collides() {
+    assert_collision(
+        "struct P { y = 2; }\nimport a;\n",
+        "import of `P` from `a` conflicts with `P` defined in the main program",
+    );
+}
+
+#[test]
+fn collision_inside_imported_module() {
+    let project = Project::new()
+        .file("main.rx", "println \"side effect\";\nimport c;\n")
+        .file("a.rx", A)
+        .file("c.rx", "import a;\npub func add(x) { return x; }\n");
+    let out = project.rx(&["run", "main.rx"]);
+    assert_fails(&out, "`add` is already imported from `a`");
+    assert!(stderr(&out).contains("c.rx:2:5"));
+    assert_eq!(stdout(&out), "");
+}
+
+#[test]
+fn vm_and_linker_report_collisions_alike() {
+    // a program put together by hand skips the linker's check, so the VM
+    // binds the names itself as the modules run
+    let module = |id: usize, name: &str, text: &str, imports: &[(&str, usize)]| LinkedModule {
+        id,
+        name: name.to_string(),
+        ast: parse(tokenize(&Source::new(format!("{name}.rx"), text)).unwrap()).unwrap(),
+        imports: imports
+            .iter()
+            .map(|&(path, id)| (vec![path.to_string()], id))
+            .collect::<HashMap<_, _>>(),
+    };
+    let program = Program {
+        modules: vec![
+            module(1, "a", A, &[]),
+            module(2, "b", B, &[]),
+            module(0, "", "import a;\nimport b;\n", &[("a", 1), ("b", 2)]),
+        ],
+    };
+    let message = "`add` is imported from both `a` and `b`; import one of them with `as`";
+
+    let linked = check_names(&program).unwrap_err();
+    assert_eq!(linked.errors()[0].message(), message);
+
+    let mut code = Vec::new();
+    compile_program(
+        program,
+        EntryPoint::Auto,
+        &mut code,
+        &mut LabelGenerator::new(),
+    )
+    .unwrap();
+    let err = VM::new(code).run().unwrap_err();
+    assert!(matches!(err, RxError::Runtime { .. }));
+    assert_eq!(err.message(), message);
+}
+
+// =========================================================
+// Visibility
+// =========================================================
+
+const Q: &str = "\
+Q := 5;
+pub P := 1;
+func hidden() { return 1; }
+pub func shown() { return hidden() + Q; }
+pub struct S { x = 1; }
+struct T { y = Q; }
+";
+
+fn with_private(main: &str) -> Project {
+    Project::new()
+        .file("main.rx", &format!("println \"side effect\";\n{main}"))
+        .file("p/q.rx", Q)
+}
+
+#[test]
+fn pub_items_are_reachable() {
+    let out = with_private(
+        "import p.q;\nprintln shown() + P + q.P + q.shown();\nprintln (struct q.S).x;\n",
+    )
+    .rx(&["run", "main.rx"]);
+    assert_prints(&out, "side effect\n14\n1\n");
+}
+
+#[test]
+fn private_items_are_rejected_before_running() {
+    let private = |name: &str| format!("`{name}` is private to module `p.q`; mark it `pub`");
+    let cases = [
+        ("import p.q (Q);", "main.rx:2:", private("Q")),
+        ("import p.q;\nprintln q.Q;", "main.rx:3:", private("Q")),
+        (
+            "import p.q as m;\nm.hidden();",
+            "main.rx:3:",
+            private("hidden"),
+        ),
+        (
+            "import p.q;\nprintln hidden();",
+            "main.rx:3:",
+            private("hidden"),
+        ),
+        ("import p.q;\nprintln Q;", "main.rx:3:", private("Q")),
+        ("import p.q;\nt := struct q.T;", "main.rx:3:", private("T")),
+        ("import p.q;\nt := struct T;", "main.rx:3:", private("T")),
+        (
+            "import p.q (nope);",
+            "main.rx:2:",
+            "module `p.q` has no item `nope`".into(),
+        ),
+        (
+            "import p.q;\nq.nope();",
+            "main.rx:3:",
+            "module `p.q` has no item `nope`".into(),
+        ),
+        (
+            "import p.q;\nt := struct q.U;",
+            "main.rx:3:",
+            "module `p.q` has no struct `U`".into(),
+        ),
+    ];
+    for (main, at, message) in cases {
+        let project = with_private(main);
+        for command in ["check", "run"] {
+            let out = project.rx(&[command, "main.rx"]);
+            assert_fails(&out, &message);
+            assert!(stderr(&out).contains(at), "{main}");
+            assert_eq!(stdout(&out), "", "{main}");
+        }
+    }
+}
+
+#[test]
+fn imported_names_are_not_passed_on() {
+    let out = Project::new()
+        .file("main.rx", "import mid;\nprintln mid.K;\n")
+        .file("mid.rx", "import a;\npub func f() { return K; }\n")
+        .file("a.rx", A)
+        .rx(&["check", "main.rx"]);
+    assert_fails(&out, "module `mid` has no item `K`");
+}
+
+#[test]
+fn local_bindings_shadow_namespaces_and_private_names() {
+    let out = with_private(
+        "import p.q as m;\nfunc f(m) { return m.Q; }\nfunc g(hidden) { return hidden(); }\nstruct V { Q = 3; }\nprintln f(struct V) + g(func() { return 4; });\n",
+    )
+    .rx(&["run", "main.rx"]);
+    assert_prints(&out, "side effect\n7\n");
+}
+
+// =========================================================
+// Linking
+// =========================================================
+
+#[test]
+fn import_cycle_reports_the_whole_chain() {
+    let out = Project::new()
+        .file("main.rx", "println \"side effect\";\nimport a;\n")
+        .file("a.rx", "import b;\n")
+        .file("b.rx", "import c;\n")
+        .file("c.rx", "import a;\n")
+        .rx(&["run", "main.rx"]);
+    assert_fails(&out, "error: import cycle: a -> b -> c -> a\n --> c.rx:1:1");
+    assert_eq!(stdout(&out), "");
+}
+
+#[test]
+fn module_importing_itself_is_a_cycle() {
+    let out = Project::new()
+        .file("main.rx", "import main;\n")
+        .rx(&["check", "main.rx"]);
+    assert_fails(&out, "import cycle: main -> main");
+}
+
+#[test]
+fn each_module_runs_once_after_its_imports() {
+    let out = Project::new()
+        .file("main.rx", "import a;\nimport b;\nprintln \"main\";\n")
+        .file("a.rx", "import c;\nprintln \"a\";\n")
+        .file("b.rx", "import c;\nimport a;\nprintln \"b\";\n")
+        .file("c.rx", "println \"c\";\n")
+        .rx(&["run", "main.rx"]);
+    assert_prints(&out, "c\na\nb\nmain\n");
+}
+
+#[test]
+fn syntax_error_in_import_fails_before_running() {
+    let out = Project::new()
+        .file("main.rx", "println \"side effect\";\nimport a;\n")
+        .file("a.rx", "pub func f( {\n")
+        .rx(&["run", "main.rx"]);
+    assert_fails(&out, "a.rx:1:");
+    assert_eq!(stdout(&out), "");
+}
+
+#[test]
+fn nested_imports_are_rejected() {
+    let cases = [
+        "func f() { import a; }",
+        "if 1 { import a; }",
+        "f := func() { import a; };",
+        "while (func() { import a; return 0; })() { }",
+        "for i in 0..1 { loop { import a; } }",
+        "x ::= [func() { import a; }];",
+    ];
+    for main in cases {
+        let out = Project::new()
+            .file("main.rx", main)
+            .file("a.rx", "")
+            .rx(&["check", "main.rx"]);
+        assert_fails(
+            &out,
+            "`import` is only allowed at the top level of a module",
+        );
+    }
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use common::{assert_fails, assert_prints, run};
+
+// =========================================================
+// Equality and ordering
+// =========================================================
+
+#[test]
+fn equality_compares_contents() {
+    let out = run(r#"
+a := [1, 2, 3];
+println "AB" == "CD";
+println "ab" == "ab";
+println "ab" != "abc";
+println a == [1, 2, 3];
+println a[1..] == [2, 3];
+println a == 3;
+c = [0, 1];
+c.push(c);
+d = [0, 1];
+d.push(d);
+println c == d;
+"#);
+    assert_prints(&out, "0\n1\n1\n1\n1\n1\n1\n");
+}
+
+#[test]
+fn struct_equality_needs_same_struct_and_fields() {
+    let out = run("\
+struct P { x = 1; y ::= x + 1; }
+struct Q { x = 1; y ::= x + 1; }
+p := struct P;
+q := struct P;
+println p == q;
+println p == struct Q;
+q.x = 2;
+println p == q;
+println p != q;
+");
+    assert_prints(&out, "1\n0\n0\n1\n");
+}
+
+#[test]
+fn strings_order_by_character_code() {
+    let out = run(r#"
+println "apple" < "banana";
+println "b" >= "ab";
+println "Apple" > "apple";
+println "ab" <= "ab";
+"#);
+    assert_prints(&out, "1\n1\n0\n1\n");
+}
+
+#[test]
+fn identity_compares_instances() {
+    let out = run("\
+struct P { x = 1; }
+a := [1, 2, 3];
+b = a;
+p := struct P;
+println a === [1, 2, 3];
+println a === b;
+println a !== [1, 2, 3];
+println p === struct P;
+println a[1..] === a[1..];
+println 3 === 3;
+println 'a' !== 'a';
+");
+    assert_prints(&out, "0\n1\n1\n0\n1\n1\n0\n");
+}
+
+// =========================================================
+// Short-circuit logic
+// =========================================================
+
+#[test]
+fn logic_short_circuits() {
+    let out = run("\
+func hit() { println \"hit\"; return 1; }
+a := [1, 2];
+i = 5;
+println i < a.len() && a[i] != 0;
+println i >= a.len() || a[i] != 0;
+println 0 && hit();
+println 1 || hit();
+println 2 && hit();
+");
+    assert_prints(&out, "0\n1\n0\n1\nhit\n1\n");
+}
+
+#[test]
+fn logic_gives_one_or_zero() {
+    let out = run("\
+println (0 - 1) && 1;
+println 7 || 0;
+println 0 || 0.5;
+println !3;
+println !0;
+");
+    assert_prints(&out, "1\n1\n1\n0\n1\n");
+}
+
+#[test]
+fn reactive_logic_short_circuits() {
+    let out = run("\
+a := [1, 2];
+j = 5;
+ok ::= j < a.len() && a[j] > 0;
+println ok;
+j = 1;
+println ok;
+");
+    assert_prints(&out, "0\n1\n");
+}
+
+// =========================================================
+// Bitwise, shift and power
+// =========================================================
+
+#[test]
+fn bitwise_shift_and_power() {
+    let out = run("\
+println 6 & 3;
+println 6 | 3;
+println 6 ^ 3;
+println ~0;
+println 1 << 4;
+println (0 - 16) >> 2;
+println -2 ** 2;
+println 2 ** 3 ** 2;
+println 5 & 4 == 4;
+");
+    assert_prints(&out, "2\n7\n5\n-1\n16\n-4\n-4\n512\n1\n");
+}
+
+#[test]
+fn compound_assignment() {
+    let out = run("\
+struct S { n = 3; }
+x = 10;
+x += 5; x -= 3; x *= 2; x /= 4; x %= 4;
+println x;
+y = 1;
+y <<= 3; y |= 5; y &= 12; y ^= 1; y >>= 1; y **= 2;
+println y;
+s := struct S;
+s.n *= 4;
+println s.n;
+a := [1, 2];
+a[1] += 40;
+a[0] <<= 2;
+println a;
+");
+    assert_prints(&out, "2\n36\n12\n[4, 42]\n");
+}
+
+#[test]
+fn shift_and_power_errors() {
+    let cases = [
+        ("println 1 << 32;", "shift amount 32 is not in 0..32"),
+        (
+            "n = 0 - 1;\nprintln 1 >> n;",
+            "shift amount -1 is not in 0..32",
+        ),
+        ("println 2 ** (0 - 1);", "negative exponent -1 in `**`"),
+    ];
+    for (src, message) in cases {
+        assert_fails(&run(src), message);
+    }
+}
+
+// =========================================================
+// Integer overflow
+// =========================================================
+
+#[test]
+fn overflow_and_division_by_zero_are_errors() {
+    let cases = [
+        (
+            "println 2147483647 + 1;",
+            "integer overflow in `2147483647 + 1`",
+        ),
+        ("println 2 ** 31;", "integer overflow in `2 ** 31`"),
+        (
+            "println (0 - 2147483647 - 1) / (0 - 1);",
+            "integer overflow in `-2147483648 / -1`",
+        ),
+        (
+            "x = 2147483647;\nx += 1;",
+            "integer overflow in `2147483647 + 1`",
+        ),
+        (
+            "x = 2147483646;\ny ::= x + 1;\nx = x + 1;\nprintln y;",
+            "integer overflow in `2147483647 + 1`",
+        ),
+        (
+            "x = 0 - 2147483647 - 1;\nprintln -x;",
+            "integer overflow in `-(-2147483648)`",
+        ),
+        ("println 7 / 0;", "division by zero in `7 / 0`"),
+        ("println 7 % 0;", "division by zero in `7 % 0`"),
+        (
+            "println 2147483648;",
+            "integer literal 2147483648 is too large (the largest is 2147483647)",
+        ),
+        (
+            "println wrapping_add(1);",
+            "`wrapping_add` expects 2 arguments but was given 1",
+        ),
+    ];
+    for (src, message) in cases {
+        assert_fails(&run(src), message);
+    }
+}
+
+#[test]
+fn wrapping_and_saturating_builtins() {
+    let out = run("\
+println wrapping_add(2147483647, 1);
+println wrapping_sub(0 - 2147483647 - 1, 1);
+println wrapping_mul(65536, 65536);
+println saturating_add(2147483647, 1);
+println saturating_sub(0 - 2147483647 - 1, 1);
+println saturating_mul(65536, 0 - 65536);
+println -2147483647 - 1;
+");
+    assert_prints(
+        &out,
+        "-2147483648\n2147483647\n0\n2147483647\n-2147483648\n-2147483648\n-2147483648\n",
+    );
+}
+
+#[test]
+fn builtins_give_way_to_a_definition() {
+    let out = run("\
+func wrapping_add(a, b) { return 7; }
+println wrapping_add(1, 2);
+");
+    assert_prints(&out, "7\n");
+}
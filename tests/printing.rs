@@ -0,0 +1,59 @@
+mod common;
+
+use common::{assert_prints, run};
+
+#[test]
+fn values_print_as_written() {
+    let out = run(r#"
+struct Counter { x = 10; step = 1; next ::= x + step; }
+println struct Counter;
+println [1, 2, [3, 4]];
+println ["ab", 'c'];
+func f() { return 1; }
+println f;
+println func(x) { return x; };
+"#);
+    assert_prints(
+        &out,
+        "Counter { x: 10, step: 1, next: 11 }\n\
+         [1, 2, [3, 4]]\n\
+         [\"ab\", 'c']\n\
+         <func f>\n\
+         <func>\n",
+    );
+}
+
+#[test]
+fn uninitialized_fields_and_cycles() {
+    let out = run("\
+struct Node { value; next; }
+n := struct Node { value: 1 };
+println n;
+n.next = n;
+println n;
+a = [1, 2];
+a.push(a);
+println a;
+println \"{n}\";
+");
+    assert_prints(
+        &out,
+        "Node { value: 1, next: <uninit> }\n\
+         Node { value: 1, next: Node { ... } }\n\
+         [1, 2, [...]]\n\
+         Node { value: 1, next: Node { ... } }\n",
+    );
+}
+
+#[test]
+fn empty_arrays_print_as_nothing_on_their_own() {
+    let out = run(r#"
+println [];
+println "";
+println ["a", ""];
+println [[1, 2], []];
+s = [];
+println "<{s}>";
+"#);
+    assert_prints(&out, "\n\n[\"a\", \"\"]\n[[1, 2], []]\n<>\n");
+}
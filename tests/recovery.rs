@@ -0,0 +1,53 @@
+mod common;
+
+use common::{assert_fails, check, stderr};
+use reactive_language::grammar::AST;
+use reactive_language::parser::parse_recovering;
+use reactive_language::span::Source;
+use reactive_language::tokenizer::tokenize;
+
+const BROKEN: &str = "\
+func main() {
+    x = ;
+    y = 2;
+    z = (;
+}
+struct S { a = ; }
+func g() { return 1; }
+";
+
+#[test]
+fn reports_every_syntax_error() {
+    let out = check(BROKEN);
+    assert_fails(&out, "error: aborting due to 3 previous errors");
+    let err = stderr(&out);
+    for at in ["main.rx:2:9", "main.rx:4:10", "main.rx:6:16"] {
+        assert!(err.contains(at), "missing {at} in:\n{err}");
+    }
+}
+
+#[test]
+fn keeps_the_items_that_parse() {
+    let tokens = tokenize(&Source::new("broken.rx", BROKEN)).unwrap();
+    let (ast, errors) = parse_recovering(tokens);
+    assert_eq!(errors.len(), 3);
+
+    let AST::Program(items) = ast.kind else {
+        panic!("expected a program");
+    };
+    let funcs: Vec<&str> = items
+        .iter()
+        .filter_map(|item| match &item.kind {
+            AST::FuncDef { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(funcs, ["main", "g"]);
+}
+
+#[test]
+fn valid_program_has_no_errors() {
+    let tokens = tokenize(&Source::new("ok.rx", "func main() { println 1; }")).unwrap();
+    let (_, errors) = parse_recovering(tokens);
+    assert!(errors.is_empty());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{Project, assert_fails, assert_prints, run, stdout};
+
+#[test]
+fn top_level_statements_run_in_order() {
+    let out = run("\
+x = 0;
+y ::= x * 2;
+
+loop {
+    x = x + 1;
+    if x > 3 { break; }
+}
+
+println y;
+");
+    assert_prints(&out, "8\n");
+}
+
+#[test]
+fn main_runs_after_the_top_level() {
+    let out = run("\
+println \"top\";
+func main() { println \"main\"; }
+");
+    assert_prints(&out, "top\nmain\n");
+}
+
+#[test]
+fn script_flag_skips_main() {
+    let out = Project::new()
+        .file(
+            "main.rx",
+            "println \"top\";\nfunc main() { println \"main\"; }\n",
+        )
+        .rx(&["run", "--script", "main.rx"]);
+    assert_prints(&out, "top\n");
+}
+
+#[test]
+fn top_level_error_stops_the_script() {
+    let out = run("println 1;\nprintln nope;\nprintln 3;\n");
+    assert_fails(&out, "undefined variable: nope");
+    assert_eq!(stdout(&out), "1\n");
+}
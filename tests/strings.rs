@@ -0,0 +1,158 @@
+mod common;
+
+use common::{assert_fails, assert_prints, run};
+
+// =========================================================
+// String operations
+// =========================================================
+
+#[test]
+fn concatenation_and_casts() {
+    let out = run(r#"
+name = "rx";
+println "hello, " + name + "!";
+println "n = " + (str) 42;
+println (str)(int) 3.9;
+"#);
+    assert_prints(&out, "hello, rx!\nn = 42\n3\n");
+}
+
+#[test]
+fn string_methods() {
+    let out = run(r#"
+fields := "12, 30, 7".split(",");
+total = 0;
+for i in 0..fields.len() {
+    total = total + fields[i].parse();
+}
+println total;
+println "a-b-c".replace("-", ", ");
+println "Apple".compare("apple");
+println "b".compare("a") + "a".compare("a");
+println "hello".substring(1, 3);
+println "hello".find("l");
+println "hello".find("z");
+println "hello"[2..].find("l");
+println ["a", "b", "c"].join(", ");
+println "  x ".trim() + "|";
+println "MiXed".upper() + "MiXed".lower();
+println "-17".parse() + 1;
+"#);
+    assert_prints(
+        &out,
+        "49\na, b, c\n-1\n1\nel\n2\n-1\n0\na, b, c\nx|\nMIXEDmixed\n-16\n",
+    );
+}
+
+#[test]
+fn string_method_errors() {
+    let cases = [
+        (
+            r#"println "x".parse();"#,
+            r#"cannot parse "x" as an integer"#,
+        ),
+        (
+            r#"println "99999999999".parse();"#,
+            r#"cannot parse "99999999999" as an integer"#,
+        ),
+        (
+            "println [1, 2].upper();",
+            "the receiver of `upper` must be a string",
+        ),
+        (
+            r#"println "abc".substring(2, 1);"#,
+            "slice start 2 is past its end 1",
+        ),
+        (
+            r#"println "abc".substring(0, 9);"#,
+            "slice out of bounds: 0..9, length 3",
+        ),
+    ];
+    for (src, message) in cases {
+        assert_fails(&run(src), message);
+    }
+}
+
+// =========================================================
+// Casts
+// =========================================================
+
+#[test]
+fn type_names_can_be_variables() {
+    let out = run("\
+func f(str) { return (str) + 1; }
+func g(float) { return (float)[1]; }
+func h(float) { return (float) - 1; }
+println f(2);
+println g([5, 6]);
+println h(3);
+x = 5;
+println (int) -x;
+println (int) -2.7;
+println (char) 65;
+");
+    assert_prints(&out, "3\n6\n2\n-5\n-2\nA\n");
+}
+
+// =========================================================
+// Interpolation and format specs
+// =========================================================
+
+#[test]
+fn format_specs() {
+    let out = run(r#"
+n := 42;
+m := 0 - 42;
+println "[{n:5}] [{n:<5}] [{n:*^6}] [{n:05}]";
+println "{n:x} {n:X} {n:o} {n:b} {n:c} {'A':d}";
+println "{3.14159:.2} {n:.1} [{2.5:>6}]";
+println "[{m:06}] [{'a':3}] [{[1, 2]:>8}]";
+b = 1;
+println "{b > 0 ? 1 : 2} {[1, 2][1]} {{}}";
+"#);
+    assert_prints(
+        &out,
+        "[   42] [42   ] [**42**] [00042]\n\
+         2a 2A 52 101010 * 65\n\
+         3.14 42.0 [   2.5]\n\
+         [-00042] [a  ] [  [1, 2]]\n\
+         1 2 {}\n",
+    );
+}
+
+#[test]
+fn reactive_template_stays_live() {
+    let out = run(r#"
+n = 1;
+label ::= "count: {n}";
+n = 2;
+println label;
+"#);
+    assert_prints(&out, "count: 2\n");
+}
+
+#[test]
+fn format_spec_errors() {
+    let cases = [
+        (
+            r#"println "{1:q}";"#,
+            "invalid format spec `q`; expected [[fill]align][0][width][.precision][s|d|x|X|o|b|c]",
+        ),
+        (
+            r#"println "{1:.2x}";"#,
+            "format spec `.2x` has a precision, which only applies to numbers shown as they print",
+        ),
+        (
+            r#"println "{1:5";"#,
+            "unclosed `{` in string; write `{{` for a brace",
+        ),
+        (
+            r#"println "{[1, 2]:.2}";"#,
+            "a format precision needs a number",
+        ),
+        (r#"println "{(0 - 1):c}";"#, "invalid char code -1"),
+    ];
+    for (src, message) in cases {
+        assert_fails(&run(src), message);
+    }
+}